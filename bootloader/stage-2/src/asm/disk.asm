@@ -4,91 +4,18 @@
 bits 32
 
 ; ==== REAL MODE / PROTECTED MODE MACROS ===================================================================== ;
-; Define macro name and number of parameters
-; These blocks cannot be declared in actual
-; methods since they deal with real/protected
-; modes and segments.
-
-;* Switches from 32pm to 16rm. Sets the segments
-;* value to 0, keeps offsets as-is.
-%macro from_32pm_to_16rm 0
-    cli                                             ; Disable interrupts for switching
-    push eax
-    
-    jmp word 0x18:.16pm                             ; Jump to 16b segment (word -> address size)
-
-    .16pm:
-    [bits 16]
-
-    mov eax, cr0                                    ; Read control register 0 value
-    and al, ~1                                      ; Unset first bit to switch back to real mode
-    mov cr0, eax                                    ; Update CR0 value
-
-    xor ax, ax                                      ; Setup segment registers to 0, GDT is not used now
-    mov ds, ax
-    mov es, ax
-    mov ss, ax
-    jmp word 0x00:.16rm                             ; Setup CS register for real mode
-
-    .16rm:
-    pop eax
-    sti                                             ; Mode switch completed, re-enable interrupts
-%endmacro
-
-;* Switches from 16rm to 32pm. Does not load GDT
-;* since it must be called after switching back to
-;* 16rm, not as the first time swithing.
-;* The segment selectors are hardcoded and refer to
-;* the selectors defined in main.asm.
-; TODO: find a way to make 'extern gdt' work
-; TODO:     and avoid hardcoding these values.
-%macro from_16rm_to_32pm 0
-    cli                                             ; Disable interrupts for switching
-    push eax
-    
-    ; GDT descriptor already loaded by _entry
-
-    mov eax, cr0                                    ; Read control register 0 value
-    or al, 1                                        ; Set first bit (protected mode)
-    mov cr0, eax                                    ; Update CR0 value
-
-    jmp dword 0x08:.32pm                            ; Setup Code Segment selector
-
-    .32pm:
-    [bits 32]
-    mov ax, 0x10                                    ; Setup Data Segment selector
-    mov ds, ax
-    mov ss, ax
-    mov es, ax
-
-    pop eax
-
-    ;! Re-enabling interrupts eventually crashes
-    ;sti                                            ; Mode switch completed, re-enable interrupts
-%endmacro
-
-
-;* Procudes a segmented memory address from the
-;* given linear address.
-;* Input:
-;* - Linear Memory Address
-;* - Output Segment Register
-;* - Output offset register (32bit version)
-;* - Output offset register (16bit version)
-%macro linear_to_segmented 4
-    mov %3, %1                                      ; Store linear address in 32b register
-    shr %3, 4                                       ; Only keep top 16 bits
-    mov %2, %4                                      ; Store shifted addr (segment) in segment register
-    
-    mov %3, %1                                      ; Store linear address in 32b register
-    and %3, 0xF                                     ; Only keep low 4 bits - Offset is already in the right reg
-%endmacro
+; Mode switch and address conversion macros, shared with the other BIOS thunks.
+; NASM looks for included files in the directory it's run from (stage-2 root).
+%include "src/asm/modes.inc"
 
 
 ; ==== GLOBALS AND EXTERN METHODS ============================================================================ ;
 ; Make the following methods visible to the linker.
 global _c_disk_reset
 global _c_disk_read
+global _c_disk_read_lba
+global _c_disk_write
+global _c_disk_write_lba
 global _c_disk_get_params
 
 ; ==== CODE SECTION ========================================================================================== ;
@@ -205,6 +132,187 @@ _c_disk_read:
     retn
 
 
+;* Reads from a given LBA on the disk using the
+;* INT 13h extensions and loads the data to the
+;* given memory address. Used for the sectors past
+;* the CHS addressable range (hard disks).
+;* Input parameters (from last pushed / left):
+;* - Drive number (u8)
+;* - Target LBA (u32)
+;* - Number of sectors to read (u8)
+;* - Memory address where to load the data (* u8)
+;* Output:
+;* - Outcome of the operation (1 success, 0 error)
+; This method implements the C calling convention.
+_c_disk_read_lba:
+    [bits 32]
+
+    ; Setup and save stack pointers
+    push ebp
+    mov ebp, esp
+    push esi                                        ; SI is not caller saved
+
+    ; Build the Disk Address Packet on the stack, from the last field:
+    ; | size (1B, 16) | 0 (1B) | count (2B) | offset (2B) | segment (2B) | LBA (8B) |
+    ; SS base is 0 in both modes, and the stack is below 0xFFFF: the
+    ; packet address is the same for the BIOS, with DS set to 0.
+    push dword 0                                    ; LBA upper 32 bits
+    push dword [ebp+12]                             ; 2^ Rust param: target LBA (lower 32 bits)
+
+    mov eax, [ebp+20]                               ; 4^ Rust param: loading address
+    mov edx, eax
+    shr edx, 4                                      ; Segment in the upper word
+    shl edx, 16
+    and eax, 0xF                                    ; Offset in the lower word
+    or eax, edx
+    push eax                                        ; Buffer offset and segment
+
+    movzx eax, byte [ebp+16]                        ; 3^ Rust param: sectors to read
+    shl eax, 16                                     ; Count in the upper word
+    or eax, 0x10                                    ; Packet size in the lower byte
+    push eax                                        ; Packet size, reserved byte and count
+
+    mov esi, esp                                    ; INT expects the packet address in DS:SI
+    mov dl, [ebp+8]                                 ; 1^ Rust param: to read drive, already set up for INT
+
+    ; Return to real mode
+    from_32pm_to_16rm
+
+    stc                                             ; Reset CF to 1 to read the outcome of INT
+    mov ah, 0x42
+    int 0x13                                        ; INT 13, 42: Extended Read Sectors
+
+    ; Return to protected mode
+    from_16rm_to_32pm
+
+    mov eax, 1                                       ; AX is the return value, it should reflect INT CF state
+    sbb eax, 0                                       ; ax = ax - (0 + CF) (CF 0 -> AX 1, CF 1 -> AX 0)
+
+    ; Drop the packet, restore registers and return
+    add esp, 16
+    pop esi
+    mov esp, ebp
+    pop ebp
+    retn
+
+
+;* Writes the data at the given memory address to
+;* a given position on the disk.
+;* Input parameters (from last pushed / left):
+;* - Drive number (u8)
+;* - Target Cylinder (u16)
+;* - Target Head (u8)
+;* - Target Sector (u8)
+;* - Number of sectors to write (u8)
+;* - Memory address of the data to write (* u8)
+;* Output:
+;* - Outcome of the operation (1 success, 0 error)
+; This method implements the C calling convention.
+_c_disk_write:
+    [bits 32]
+
+    ; Setup and save stack pointers
+    push ebp
+    mov ebp, esp
+    push ebx                                        ; BX is not caller saved
+    push es                                         ; ES is not caller saved
+
+    ; Parameters read and setup - same layout as _c_disk_read
+    mov dl, [ebp+8]                                  ; 1^ Rust param: to write drive, already set up for INT
+    mov ax, [ebp+12]                                 ; 2^ Rust param: target cylinder
+    mov dh, [ebp+16]                                 ; 3^ Rust param: target head, already set up for INT
+    mov cl, [ebp+20]                                 ; 4^ Rust param: target sector, already set up for INT
+
+    mov ch, al                                      ; INT expects lower 8b of Cylinder value in CH
+    shl ah, 6                                       ; INT expects upper 2b of Cylinder value in last CL's bits
+    or cl, ah                                       ; OR to keep CL's lower 6 to previous value (target sector)
+
+    mov al, [ebp+24]                                ; 5^ Rust param: sectors to write, already set up for INT
+
+    ; Return to real mode
+    from_32pm_to_16rm
+    
+    linear_to_segmented [ebp+28], es, ebx, bx       ; 6^ Rust param: data address, already set up for INT
+                                                    ; Convert linear address to segmented memory model address
+    stc                                             ; Reset CF to 1 to read the outcome of INT
+    mov ah, 0x03
+    int 0x13                                        ; INT 13, 3: Write Disk Sectors
+
+    ; Return to protected mode
+    from_16rm_to_32pm
+
+    mov eax, 1                                       ; AX is the return value, it should reflect INT CF state
+    sbb eax, 0                                       ; ax = ax - (0 + CF) (CF 0 -> AX 1, CF 1 -> AX 0)
+    
+    ; Restore registers and return
+    pop es
+    pop ebx
+    mov esp, ebp
+    pop ebp
+    retn
+
+
+;* Writes the data at the given memory address to
+;* a given LBA on the disk using the INT 13h
+;* extensions. Used for the sectors past the CHS
+;* addressable range (hard disks).
+;* Input parameters (from last pushed / left):
+;* - Drive number (u8)
+;* - Target LBA (u32)
+;* - Number of sectors to write (u8)
+;* - Memory address of the data to write (* u8)
+;* Output:
+;* - Outcome of the operation (1 success, 0 error)
+; This method implements the C calling convention.
+_c_disk_write_lba:
+    [bits 32]
+
+    ; Setup and save stack pointers
+    push ebp
+    mov ebp, esp
+    push esi                                        ; SI is not caller saved
+
+    ; Build the Disk Address Packet on the stack - same layout as _c_disk_read_lba
+    push dword 0                                    ; LBA upper 32 bits
+    push dword [ebp+12]                             ; 2^ Rust param: target LBA (lower 32 bits)
+
+    mov eax, [ebp+20]                               ; 4^ Rust param: data address
+    mov edx, eax
+    shr edx, 4                                      ; Segment in the upper word
+    shl edx, 16
+    and eax, 0xF                                    ; Offset in the lower word
+    or eax, edx
+    push eax                                        ; Buffer offset and segment
+
+    movzx eax, byte [ebp+16]                        ; 3^ Rust param: sectors to write
+    shl eax, 16                                     ; Count in the upper word
+    or eax, 0x10                                    ; Packet size in the lower byte
+    push eax                                        ; Packet size, reserved byte and count
+
+    mov esi, esp                                    ; INT expects the packet address in DS:SI
+    mov dl, [ebp+8]                                 ; 1^ Rust param: to write drive, already set up for INT
+
+    ; Return to real mode
+    from_32pm_to_16rm
+
+    stc                                             ; Reset CF to 1 to read the outcome of INT
+    mov ax, 0x4300                                  ; AL = 0: write without verify
+    int 0x13                                        ; INT 13, 43: Extended Write Sectors
+
+    ; Return to protected mode
+    from_16rm_to_32pm
+
+    mov eax, 1                                       ; AX is the return value, it should reflect INT CF state
+    sbb eax, 0                                       ; ax = ax - (0 + CF) (CF 0 -> AX 1, CF 1 -> AX 0)
+
+    ; Drop the packet, restore registers and return
+    add esp, 16
+    pop esi
+    mov esp, ebp
+    pop ebp
+    retn
+
+
 ;* Uses BIOS to get informations about the disk and
 ;* store the retrieved informations in the given
 ;* memory addresses.
@@ -0,0 +1,81 @@
+; ==== MEMORY AND ARCH DIRECTIVES ============================================================================ ;
+; Code to be used in a 32bp mode environment.
+bits 32
+
+; ==== REAL MODE / PROTECTED MODE MACROS ===================================================================== ;
+%include "src/asm/modes.inc"
+
+; ==== GLOBALS AND EXTERN METHODS ============================================================================ ;
+; Make the following methods visible to the linker.
+global _c_memory_detect
+
+; ==== CODE SECTION ========================================================================================== ;
+; Define the following code in the .text section, so that we can control its location with linker script.
+section .text
+
+; "SMAP" signature, passed in EDX and returned in EAX by INT 15,E820.
+%define E820_SIGNATURE 0x534D4150
+; Size of the entries requested to INT 15,E820 (ACPI 3.0 extended attributes included).
+%define E820_ENTRY_SIZE 24
+
+;* Uses BIOS to retrieve the physical memory map
+;* (INT 15,E820) and stores its entries at the
+;* given memory address, one after the other.
+;* The buffer must be in the first MB of memory.
+;* Input parameters (from last pushed / left):
+;* - Output entries address (24 bytes each)
+;* - Maximum number of entries to store (u32)
+;* Output:
+;* - Number of stored entries (0 if not supported)
+; This method implements the C calling convention.
+_c_memory_detect:
+    [bits 32]
+
+    push ebp
+    mov ebp, esp
+    push ebx                                        ; BX is not caller saved
+    push esi                                        ; SI is not caller saved
+    push edi                                        ; DI is not caller saved
+    push es                                         ; ES is not caller saved
+
+    mov esi, [ebp+12]                               ; 2^ Rust param: maximum entries, decremented for each entry
+
+    ; Return to real mode
+    from_32pm_to_16rm
+
+    linear_to_segmented [ebp+8], es, edi, di        ; 1^ Rust param: entries address, ES:DI for the INT
+    xor ebx, ebx                                    ; Continuation value, 0 for the first entry
+
+    .loop:
+    test esi, esi                                   ; Stop if the output buffer is full
+    jz .exit
+
+    mov dword [es:di+20], 1                         ; Mark entry as valid, in case the BIOS only writes 20 bytes
+    mov eax, 0xE820                                 ; INT 15,E820: Query System Address Map
+    mov edx, E820_SIGNATURE
+    mov ecx, E820_ENTRY_SIZE
+    int 0x15
+
+    jc .exit                                        ; CF set: not supported, or end of the list on some BIOSes
+    cmp eax, E820_SIGNATURE                         ; EAX must contain the signature on success
+    jne .exit
+
+    add di, E820_ENTRY_SIZE                         ; Entry stored, move to the next one
+    dec esi
+    test ebx, ebx                                   ; EBX is 0 after the last entry
+    jnz .loop
+
+    .exit:
+    ; Return to protected mode
+    from_16rm_to_32pm
+
+    mov eax, [ebp+12]                               ; Return value: stored entries (maximum - remaining)
+    sub eax, esi
+
+    pop es
+    pop edi
+    pop esi
+    pop ebx
+    mov esp, ebp
+    pop ebp
+    retn
@@ -0,0 +1,45 @@
+; ==== MEMORY AND ARCH DIRECTIVES ============================================================================ ;
+; Code to be used in a 32bp mode environment.
+bits 32
+
+; ==== REAL MODE / PROTECTED MODE MACROS ===================================================================== ;
+%include "src/asm/modes.inc"
+
+; ==== GLOBALS AND EXTERN METHODS ============================================================================ ;
+; Make the following methods visible to the linker.
+global _c_print_char
+
+; ==== CODE SECTION ========================================================================================== ;
+; Define the following code in the .text section, so that we can control its location with linker script.
+section .text
+
+;* Prints a character to TTY with the BIOS, at the
+;* BIOS cursor position, which is then advanced.
+;* CR and LF are handled by the BIOS itself.
+;* Input parameters (from last pushed / left):
+;* - ASCII character (u8)
+; This method implements the C calling convention.
+_c_print_char:
+    [bits 32]
+
+    push ebp
+    mov ebp, esp
+    push ebx                                        ; BX is not caller saved
+
+    mov al, [ebp+8]                                 ; 1^ Rust param: character to print, already set up for INT
+
+    ; Return to real mode
+    from_32pm_to_16rm
+
+    mov ah, 0x0E                                    ; INT 10, E: Write Character in TTY
+    mov bh, 0                                       ; Set page number used by int
+    int 0x10
+
+    ; Return to protected mode
+    from_16rm_to_32pm
+
+    ; Restore registers and return
+    pop ebx
+    mov esp, ebp
+    pop ebp
+    retn
@@ -1,3 +1,5 @@
+use super::FatType;
+
 /* ==== TYPE DEFINITION ===================================================== */
 /*  Define FAT12 headers and bootloader sector.
     All the header values are mapped, but the bootloader code is ignored. */
@@ -29,31 +31,96 @@ pub struct BootSector {
     /*  BootLoader code (ignored)
     !   We need this padding or we'll overflow into memory, disk reading
     !   only works in chunks of 512 byte. */
-    padding: [u8; 512 - 61]
+    padding: [u8; 512 - 61 - 2],
+
+    // Boot signature, 0x55 0xAA (little endian 0xAA55) on bootable disks
+    boot_signature: u16
+}
+
+/* ==== TYPE CONSTANTS ====================================================== */
+impl BootSector {
+    /** Expected value of the last two bytes of the boot sector. */
+    const BOOT_SIGNATURE: u16 = 0xAA55;
 }
 
 /* ==== TYPE METHODS ======================================================== */
 impl BootSector {
+    /** Checks the boot sector signature: if it doesn't match, the disk is
+        blank or not formatted, and the other values can't be trusted. */
+    pub fn has_valid_signature(&self) -> bool { self.boot_signature == BootSector::BOOT_SIGNATURE }
+
+    /** Returns the size in bytes of the logical sectors of the volume; all
+        the other sector offsets and sizes are expressed in these units. */
+    pub fn get_bytes_per_sector(&self) -> u16 { self.bytes_per_sector }
+
     /** Returns the sector on the disk in which the FAT starts. */
     pub fn get_fat_offset(&self) -> u16 { self.reserved_sectors }
     /** Returns the size in sectors of a single FAT. */
     pub fn get_fat_size(&self) -> u16 { self.sectors_per_fat }
 
     /** Returns the sector on the disk in which the root directory starts.
-        The root directory is placed right after the FATs. */
-    pub fn get_root_offset(&self) -> u16 { self.get_fat_offset() + (self.get_fat_size() * self.fat_count as u16) }
-    /** Returns the size in sectors of the full root directory.
-        Complete formula would be: entries * entry_size / sector_size */
-    pub fn get_root_size(&self) -> u16 { self.root_entries / 16 }
+        The root directory is placed right after the FATs.
+        Offsets are computed in u32, they don't fit in u16 on larger disks. */
+    pub fn get_root_offset(&self) -> u32 { self.get_fat_offset() as u32 + (self.get_fat_size() as u32 * self.fat_count as u32) }
+    /** Returns the size in sectors of the full root directory: entries are
+        32 bytes long, the last sector may only be partially used. */
+    pub fn get_root_size(&self) -> u16 { (self.root_entries * 32).div_ceil(self.get_bytes_per_sector()) }
 
     /** Returns the sector on the disk in which the data cluster start.
         The clusters are placed right after the root directory. */
-    pub fn get_cluster_region_offset(&self) -> u16 { self.get_root_offset() + self.get_root_size() }
+    pub fn get_cluster_region_offset(&self) -> u32 { self.get_root_offset() + self.get_root_size() as u32 }
     /** Returuns the secton on the disk in which the given cluster starts.
-        The given cluster number has to account for the empty FAT entries. */
-    pub fn get_cluster_offset(&self, cluster: u16) -> u16 {
-        self.get_cluster_region_offset() + (self.get_cluster_size() * (cluster - 2))
+        The given cluster number has to account for the empty FAT entries:
+        data clusters start from 2, lower numbers are invalid (panics).
+        The math is done in u32: in u16, a 1-sector cluster past ~65000
+        sectors from the cluster region would wrap to the wrong sector. */
+    pub fn get_cluster_offset(&self, cluster: u32) -> u32 {
+        if cluster < 2 { panic!("Invalid cluster number!"); }
+        self.get_cluster_region_offset() + (self.get_cluster_size() as u32 * (cluster - 2))
     }
     /** Returns the size in sectors of a single cluster. */
     pub fn get_cluster_size(&self) -> u16 { self.sectors_per_cluster as u16}
+
+    /** Returns the total number of sectors of the volume. The 16 bit field is
+        0 if the count doesn't fit, the 32 bit field is used in that case. */
+    pub fn get_total_sectors(&self) -> u32 {
+        if self.sector_count != 0 { return self.sector_count as u32; }
+        self.large_sector_count
+    }
+    /** Returns the number of data clusters of the volume. Clusters are numbered
+        starting from 2, so the last valid cluster is this value + 1. */
+    pub fn get_cluster_count(&self) -> u32 {
+        (self.get_total_sectors() - self.get_cluster_region_offset()) / self.get_cluster_size() as u32
+    }
+    /** Returns the FAT type of the volume, which only depends on the number
+        of data clusters: less than 4085 clusters is FAT12, less than 65525
+        is FAT16. FAT32 volumes are not supported. */
+    pub fn fat_type(&self) -> FatType {
+        let cluster_count: u32 = self.get_cluster_count();
+        if cluster_count < 4085 { return FatType::Fat12; }
+        if cluster_count < 65525 { return FatType::Fat16; }
+        panic!("FAT32 is not supported!");
+    }
+    /** Returns the number of sectors per track the volume was formatted with. */
+    pub fn get_sectors_per_track(&self) -> u16 { self.sectors_per_cylinder }
+    /** Returns the number of heads the volume was formatted with. */
+    pub fn get_heads_count(&self) -> u16 { self.heads_count }
+
+    /** Returns the volume label, without the trailing padding spaces.
+        Byte arrays have no alignment, so they can be borrowed directly. */
+    pub fn volume_label(&self) -> &[u8] { Self::trim_padding(&self.volume_label) }
+    /** Returns the name of the system that formatted the volume (OEM ID),
+        without the trailing padding spaces. */
+    #[allow(dead_code)]
+    pub fn oem_id(&self) -> &[u8] { Self::trim_padding(&self.oem_id) }
+    /** Returns the volume serial number. The field is returned by value, as
+        borrowing it from the packed struct could be unaligned. */
+    #[allow(dead_code)]
+    pub fn volume_serial(&self) -> u32 { self.volume_id }
+
+    /** Strips the spaces used to pad the text fields to their size. */
+    fn trim_padding(field: &[u8]) -> &[u8] {
+        let length: usize = field.iter().rposition(|c| *c != b' ').map_or(0, |i| i + 1);
+        &field[..length]
+    }
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+/* ==== CRC32 =============================================================== */
+/*  CRC32 as used by zip, gzip and PNG (IEEE 802.3): reflected polynomial
+    0xEDB88320, initial value and final XOR 0xFFFFFFFF.
+    The lookup table would take 1KB of stage-2: bits are processed one at a
+    time instead, which is fast enough for the size of the kernel. */
+
+/** Reflected CRC32 polynomial. */
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/** Value to start the computation from, passed to the first update call. */
+pub const CRC32_INIT: u32 = 0xFFFF_FFFF;
+
+/** Adds the given bytes to a running CRC, to be computed in chunks as the
+    data is read: start from CRC32_INIT and finalize with crc32_finish. */
+pub fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask: u32 = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (POLYNOMIAL & mask);
+        }
+    }
+    crc
+}
+
+/** Returns the final CRC value of a running computation. */
+pub fn crc32_finish(crc: u32) -> u32 { !crc }
+
+/** Returns the CRC32 of the given bytes: crc32(b"123456789") is 0xCBF43926. */
+#[allow(dead_code)]
+pub fn crc32(data: &[u8]) -> u32 { crc32_finish(crc32_update(CRC32_INIT, data)) }
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn crc32_matches_the_check_value() {
+        assert!(crc32(b"123456789") == 0xCBF4_3926);
+        assert!(crc32(b"") == 0);
+    }
+
+    #[test_case]
+    fn crc32_update_can_be_called_in_chunks() {
+        let crc: u32 = crc32_update(crc32_update(CRC32_INIT, b"1234"), b"56789");
+        assert!(crc32_finish(crc) == 0xCBF4_3926);
+    }
+}
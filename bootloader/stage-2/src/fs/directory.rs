@@ -16,14 +16,261 @@ pub struct DirectoryEntry {
     pub file_size: u32
 }   // 32 byte
 
+/** Long file name reassembled from the LFN entries that precede an 8.3
+ *  entry. LFN entries are stored in reverse order: the first one found has
+ *  the highest sequence number and the 0x40 bit set, the last one is number
+ *  1, right before the 8.3 entry. Each holds 13 UTF-16 characters, and a
+ *  checksum of the 8.3 name that links them to it.
+ *  Only ASCII characters are supported, others are replaced by '?'. */
+pub struct LongName {
+    chars: [u8; LongName::MAX_ENTRIES * LongName::CHARS_PER_ENTRY],
+    length: usize,
+    /** Checksum of the 8.3 name stored in every LFN entry of the sequence. */
+    checksum: u8,
+    /** Sequence number expected for the next LFN entry; 0 when complete. */
+    next_order: u8,
+    /** Unset if no sequence has been started or the sequence is broken. */
+    valid: bool
+}
+
+/** Date and time decoded from the packed format of the directory entries. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    /** Hundredths of second, only available for creation timestamps. */
+    pub centiseconds: u8
+}
+
+/* ==== TYPE CONSTANTS ====================================================== */
+impl LongName {
+    /** Maximum number of LFN entries of a single name (255 characters). */
+    const MAX_ENTRIES: usize = 20;
+    /** Number of UTF-16 characters stored by a single LFN entry. */
+    const CHARS_PER_ENTRY: usize = 13;
+    /** Sequence number bit set on the last LFN entry (the first stored). */
+    const LAST_ENTRY: u8 = 0x40;
+}
+
+/* ==== STATIC TYPE METHODS ================================================= */
+impl LongName {
+    /** Creates an empty long name, with no sequence started. */
+    pub const fn new() -> Self {
+        Self { chars: [0; LongName::MAX_ENTRIES * LongName::CHARS_PER_ENTRY], length: 0, checksum: 0, next_order: 0, valid: false }
+    }
+}
+
+impl DateTime {
+    /** Decodes FAT packed date and time words:
+     *  - date: year since 1980 (15-9), month (8-5), day (4-0);
+     *  - time: hours (15-11), minutes (10-5), seconds / 2 (4-0).
+     *
+     *  Tenths are the 10ms units (0-199) of the creation time, that add 0 or
+     *  1 second to the 2 seconds resolution of the time word. */
+    pub fn from_fat(date: u16, time: u16, tenths: u8) -> Self {
+        Self {
+            year: 1980 + (date >> 9),
+            month: ((date >> 5) & 0x0F) as u8,
+            day: (date & 0x1F) as u8,
+            hours: (time >> 11) as u8,
+            minutes: ((time >> 5) & 0x3F) as u8,
+            seconds: ((time & 0x1F) * 2) as u8 + tenths / 100,
+            centiseconds: tenths % 100
+        }
+    }
+}
+
 
 /* ==== TYPE METHODS ======================================================== */
+impl LongName {
+    /** Discards the characters collected so far. */
+    pub fn reset(&mut self) { self.valid = false; }
+
+    /** Adds the characters of an LFN entry to the name. Entries must be
+     *  given in the order they are stored: if the sequence is broken, the
+     *  name is discarded until a new sequence starts. */
+    pub fn push(&mut self, entry: &DirectoryEntry) {
+        let order: u8 = entry.name[0];
+        let sequence: u8 = order & !LongName::LAST_ENTRY;
+
+        // The last entry starts a new sequence, its characters are the last
+        // of the name: the name is at most as long as all the entries.
+        if order & LongName::LAST_ENTRY != 0 {
+            if sequence == 0 || sequence as usize > LongName::MAX_ENTRIES { return self.reset(); }
+            self.valid = true;
+            self.checksum = entry.lfn_checksum();
+            self.next_order = sequence;
+            self.length = sequence as usize * LongName::CHARS_PER_ENTRY;
+        }
+
+        if !self.valid || sequence != self.next_order || entry.lfn_checksum() != self.checksum { return self.reset(); }
+
+        // Place the characters at the entry position; the name ends at the
+        // first NULL character, the following ones are 0xFFFF padding.
+        let start: usize = (sequence as usize - 1) * LongName::CHARS_PER_ENTRY;
+        for (i, c) in entry.lfn_chars().iter().enumerate() {
+            if *c == 0x0000 { self.length = core::cmp::min(self.length, start + i); break; }
+            self.chars[start + i] = if *c < 0x80 { *c as u8 } else { b'?' };
+        }
+        self.next_order -= 1;
+    }
+
+    /** Checks if the name has been completely read and belongs to the given
+     *  8.3 entry (the LFN checksum matches the 8.3 name). */
+    pub fn belongs_to(&self, entry: &DirectoryEntry) -> bool {
+        self.valid && self.next_order == 0 && self.checksum == entry.short_name_checksum()
+    }
+
+    /** Compares the name with the given one, ignoring ASCII case as FAT does. */
+    pub fn eq_ignore_case(&self, name: &[u8]) -> bool {
+        self.chars[..self.length].eq_ignore_ascii_case(name)
+    }
+}
+
 impl DirectoryEntry {
     /** Checks if the entry attributes indicate a directory-type file. */
     pub fn is_directory(&self) -> bool { self.attributes & 0x10 > 0 }
 
-    /* Gets the u32 cluster number of the described file. *
+    /** Checks if the entry belongs to a deleted file (first name byte E5). */
+    pub fn is_deleted(&self) -> bool { self.name[0] == 0xE5 }
+
+    /** Checks if the entry attributes indicate a long file name entry: these
+        entries precede the 8.3 entry of the file they belong to. */
+    pub fn is_lfn(&self) -> bool { self.attributes & 0x0F == 0x0F }
+
+    /** Returns the sequence checksum of an LFN entry (same offset as the
+        creation time tenths of 8.3 entries). */
+    pub fn lfn_checksum(&self) -> u8 { self.creation_time_tenths }
+
+    /** Returns the 13 UTF-16 characters of an LFN entry: they are spread in
+        three groups across the fields of the 8.3 layout (offsets 1-10, 14-25
+        and 28-31), the other fields keep the LFN order, attributes, type,
+        checksum and a zeroed cluster. */
+    pub fn lfn_chars(&self) -> [u16; 13] {
+        let name: [u8; 11] = self.name;
+        let size: u32 = self.file_size;
+        [
+            u16::from_le_bytes([name[1], name[2]]), u16::from_le_bytes([name[3], name[4]]),
+            u16::from_le_bytes([name[5], name[6]]), u16::from_le_bytes([name[7], name[8]]),
+            u16::from_le_bytes([name[9], name[10]]),
+            self.creation_time, self.creation_date, self.last_access_date,
+            self.upper_first_cluster, self.last_change_time, self.last_change_date,
+            size as u16, (size >> 16) as u16
+        ]
+    }
+
+    /** Computes the checksum of the 8.3 name stored in the LFN entries of the
+        file, to check that they actually belong to this entry. */
+    pub fn short_name_checksum(&self) -> u8 {
+        self.name.iter().fold(0u8, |sum, c| sum.rotate_right(1).wrapping_add(*c))
+    }
+
+    /** Writes the 8.3 name in its human readable form to the given buffer and
+        returns the written length: the padding spaces of the 8 byte name and
+        3 byte extension are trimmed, and they are joined with a '.' only if
+        there is an extension ("TEST    BIN" is "TEST.BIN", "DIR        " is
+        "DIR"). It is the inverse of FS::parse_entry_name.
+        A first byte of 0x05 stands for 0xE5, which marks deleted entries.
+        The name is truncated if the buffer is shorter than 12 bytes. */
+    #[allow(dead_code)]
+    pub fn display_name(&self, out: &mut [u8]) -> usize {
+        let name: [u8; 11] = self.name;
+        let trimmed_length = |field: &[u8]| field.iter().rposition(|c| *c != b' ').map_or(0, |i| i + 1);
+        let base: &[u8] = &name[..8];
+        let base: &[u8] = &base[..trimmed_length(base)];
+        let extension: &[u8] = &name[8..];
+        let extension: &[u8] = &extension[..trimmed_length(extension)];
+
+        let mut length: usize = 0;
+        let separator: &[u8] = if extension.is_empty() { &[] } else { b"." };
+        for c in base.iter().chain(separator).chain(extension) {
+            if length == out.len() { break; }
+            out[length] = if length == 0 && *c == 0x05 { 0xE5 } else { *c };
+            length += 1;
+        }
+        length
+    }
+
+    /** Returns the decoded creation date and time, with sub-second precision. */
+    #[allow(dead_code)]
+    pub fn creation_datetime(&self) -> DateTime {
+        DateTime::from_fat(self.creation_date, self.creation_time, self.creation_time_tenths)
+    }
+
+    /** Returns the decoded last modification date and time. The entry has no
+        tenths for it, so the seconds have a 2 seconds resolution. */
+    #[allow(dead_code)]
+    pub fn last_change_datetime(&self) -> DateTime {
+        DateTime::from_fat(self.last_change_date, self.last_change_time, 0)
+    }
+
+    /** Returns the first cluster of the described file, joining the high and
+        low words. The high word is only used by FAT32: on FAT12/16 it is
+        reserved and must be masked (see FS::entry_first_cluster).
+        The fields are copied first, as references into the packed struct
+        could be unaligned. */
     pub fn get_cluster(&self) -> u32 {
-        ((self.upper_first_cluster as u32) << 16) | self.lower_first_cluster as u32
-    } */
-}
\ No newline at end of file
+        let (upper, lower): (u16, u16) = (self.upper_first_cluster, self.lower_first_cluster);
+        ((upper as u32) << 16) | lower as u32
+    }
+}
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Returns a zeroed entry with the given 8.3 name. */
+    fn entry(name: &[u8; 11]) -> DirectoryEntry {
+        DirectoryEntry {
+            name: *name, attributes: 0, reserved: 0, creation_time_tenths: 0, creation_time: 0,
+            creation_date: 0, last_access_date: 0, upper_first_cluster: 0, last_change_time: 0,
+            last_change_date: 0, lower_first_cluster: 0, file_size: 0
+        }
+    }
+
+    #[test_case]
+    fn display_name_joins_base_and_extension() {
+        let mut out: [u8; 12] = [0; 12];
+        let length: usize = entry(b"TEST    BIN").display_name(&mut out);
+        assert!(&out[..length] == b"TEST.BIN");
+    }
+
+    #[test_case]
+    fn display_name_omits_the_dot_without_extension() {
+        let mut out: [u8; 12] = [0; 12];
+        let length: usize = entry(b"DIR        ").display_name(&mut out);
+        assert!(&out[..length] == b"DIR");
+    }
+
+    #[test_case]
+    fn display_name_restores_a_leading_0xe5() {
+        let mut out: [u8; 12] = [0; 12];
+        let length: usize = entry(b"\x05ILE    TXT").display_name(&mut out);
+        assert!(&out[..length] == b"\xE5ILE.TXT");
+    }
+
+    #[test_case]
+    fn display_name_is_truncated_to_the_buffer() {
+        let mut out: [u8; 6] = [0; 6];
+        let length: usize = entry(b"KERNEL  BIN").display_name(&mut out);
+        assert!(length == 6);
+        assert!(&out == b"KERNEL");
+    }
+
+    #[test_case]
+    fn creation_tenths_add_seconds_and_centiseconds() {
+        // 2024-03-15 10:20:30 (seconds field 15), tenths 150
+        let date: u16 = (44 << 9) | (3 << 5) | 15;
+        let time: u16 = (10 << 11) | (20 << 5) | 15;
+        let datetime: DateTime = DateTime::from_fat(date, time, 150);
+        assert!(datetime.year == 2024 && datetime.month == 3 && datetime.day == 15);
+        assert!(datetime.hours == 10 && datetime.minutes == 20);
+        assert!(datetime.seconds == 31);
+        assert!(datetime.centiseconds == 50);
+    }
+}
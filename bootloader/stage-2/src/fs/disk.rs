@@ -0,0 +1,302 @@
+use core::cell::Cell;
+use crate::{println, prints::ToString};
+
+use super::{bootsector::BootSector, file::File};
+
+/* ==== ASM EXTERN METHODS ================================================== */
+extern "C" {
+    fn _c_disk_reset(drive: u8) -> bool;
+    fn _c_disk_read(drive: u8, cylinder: u16, head: u8, sector: u8, count: u8, addr: *const u8) -> bool;
+    fn _c_disk_read_lba(drive: u8, lba: u32, count: u8, addr: *const u8) -> bool;
+    fn _c_disk_write(drive: u8, cylinder: u16, head: u8, sector: u8, count: u8, addr: *const u8) -> bool;
+    fn _c_disk_write_lba(drive: u8, lba: u32, count: u8, addr: *const u8) -> bool;
+    fn _c_disk_get_params(drive: u8, drive_type: *const u8, max_cylinders: *const u16, max_heads: *const u8, max_sectors: *const u8) -> bool;
+}
+
+/* ==== TYPE DEFINITION ===================================================== */
+/** Backend used by the FS driver for the disk I/O. LBA and count are in disk
+    sectors (File::SECTOR_SIZE bytes), the FS converts its logical sectors.
+    Each method returns false if the operation failed. */
+pub trait DiskRead {
+    /** Loads count sectors starting from the given LBA at the given address. */
+    fn read(&self, lba: u32, count: u8, addr: *mut u8) -> bool;
+
+    /** Writes count sectors starting from the given LBA from the given
+        address. Backends are read-only unless they override it. */
+    fn write(&self, _lba: u32, _count: u8, _addr: *const u8) -> bool { false }
+
+    /** Resets the disk controller after a failed read, before retrying. */
+    fn reset(&self) -> bool { true }
+
+    /** Called once the boot sector has been read, for the backends that have
+        to cross-check their geometry against the one of the volume. */
+    fn check_geometry(&self, _boot_sector: &BootSector) {}
+}
+
+/** BIOS disk backend: sectors are read and written through the extern ASM
+    methods, which switch to real mode and use INT 13h with CHS addressing.
+    Sectors past the CHS range are accessed with the INT 13h extensions (LBA).
+    The geometry is updated once the boot sector is read, so it's kept in
+    Cells: the FS only holds a shared reference to its backend. */
+pub struct BiosDisk {
+    drive_number: u8,
+    max_cylinders: Cell<u16>,
+    max_heads: Cell<u8>,
+    max_sectors: Cell<u8>,
+
+    /*  Set if the BIOS reported the geometry, otherwise the boot sector one
+        is used as soon as it is read. */
+    bios_geometry: bool
+}
+
+/** In-memory disk backend, reading and writing the sectors of a byte slice
+    holding a disk image. Used to run the FS driver without the BIOS.
+    The FS only holds a shared reference to its backend: the bytes are kept
+    in Cells, so that they can be written anyway. */
+#[allow(dead_code)]
+pub struct MemoryDisk<'a> {
+    data: &'a [Cell<u8>]
+}
+
+/* ==== BIOS DISK =========================================================== */
+impl BiosDisk {
+    /** Returns the BIOS backend for the given disk number. The geometry is
+        retrieved with _c_disk_get_params, implemented in ASM: it switches to
+        16rm, calls BIOS INT 13,8 and returns to 32pm.
+        Many hard disk BIOSes don't support this legacy call: if it fails (or
+        returns an empty geometry), the boot sector geometry is used. */
+    pub fn new(drive_number: u8) -> Self {
+        let drive_type: u8 = 0;
+        let max_cylinders: u16 = 0;
+        let max_heads: u8 = 0;
+        let max_sectors: u8 = 0;
+        let outcome: bool = unsafe { _c_disk_get_params(drive_number, &drive_type, &max_cylinders, &max_heads, &max_sectors) };
+        let bios_geometry: bool = outcome && max_cylinders != 0 && max_heads != 0 && max_sectors != 0;
+
+        // Without the BIOS geometry, use a placeholder one until the boot
+        // sector is read: LBA 0 is always CHS 0/0/1, whatever the geometry.
+        let (max_cylinders, max_heads, max_sectors) = if bios_geometry { (max_cylinders, max_heads, max_sectors) } else { (1, 1, 1) };
+
+        fs_trace!("Number: ", drive_number, " - Type: ", drive_type, " - Cylinders: ", max_cylinders, " - Heads: ", max_heads, " - Sectors: ", max_sectors);
+
+        Self {
+            drive_number,
+            max_cylinders: Cell::new(max_cylinders),
+            max_heads: Cell::new(max_heads),
+            max_sectors: Cell::new(max_sectors),
+            bios_geometry
+        }
+    }
+
+    /** Sets the disk geometry used for the CHS translation from the boot
+        sector values (sectors per track, heads), to be used when the BIOS
+        can't report it or reports a different one. The cylinders are the ones needed to fit the volume.
+        Panics if the boot sector doesn't have a geometry either. */
+    fn use_boot_sector_geometry(&self, boot_sector: &BootSector) {
+        let sectors: u16 = boot_sector.get_sectors_per_track();
+        let heads: u16 = boot_sector.get_heads_count();
+        if sectors == 0 || heads == 0 || sectors > u8::MAX as u16 || heads > u8::MAX as u16 {
+            panic!("Could not read disk parameters!");
+        }
+
+        let sectors_per_cylinder: u32 = sectors as u32 * heads as u32;
+        let cylinders: u32 = boot_sector.get_total_sectors().div_ceil(sectors_per_cylinder);
+
+        self.max_sectors.set(sectors as u8);
+        self.max_heads.set(heads as u8);
+        self.max_cylinders.set(core::cmp::min(cylinders, u16::MAX as u32) as u16);
+        println!("Warning: using the boot sector disk geometry");
+    }
+
+    /** Cross-checks the disk geometry reported by the BIOS against the one the
+        boot sector has been formatted with, printing a warning on mismatch.
+        Returns true if the geometries are compatible.
+        - If sectors per track or heads differ, CHS translation of any LBA
+          past the first track points to different sectors than expected.
+        - If the volume has more sectors than the BIOS disk can address (ex:
+          a 2.88MB image on a BIOS that thinks it's 1.44MB), the data at the
+          end of the volume can't be read. */
+    fn check_bios_geometry(&self, boot_sector: &BootSector) -> bool {
+        let (max_cylinders, max_heads, max_sectors) = (self.max_cylinders.get(), self.max_heads.get(), self.max_sectors.get());
+        let mut compatible: bool = true;
+
+        let bpb_sectors: u16 = boot_sector.get_sectors_per_track();
+        let bpb_heads: u16 = boot_sector.get_heads_count();
+        if bpb_sectors != max_sectors as u16 || bpb_heads != max_heads as u16 {
+            println!("Warning: disk geometry mismatch! BIOS: ", max_heads, " heads, ", max_sectors, " sectors - Boot Sector: ", bpb_heads, " heads, ", bpb_sectors, " sectors");
+            compatible = false;
+        }
+
+        let total_sectors: u32 = boot_sector.get_total_sectors();
+        let bios_sectors: u32 = max_cylinders as u32 * max_heads as u32 * max_sectors as u32;
+        if total_sectors > bios_sectors {
+            println!("Warning: volume larger than disk! BIOS: ", bios_sectors, " sectors - Boot Sector: ", total_sectors, " sectors");
+            compatible = false;
+        }
+
+        compatible
+    }
+
+    /** Returns the number of sectors addressable with CHS: the LBAs from
+        this one on can only be read with the INT 13h extensions. */
+    fn chs_sectors(&self) -> u32 {
+        self.max_cylinders.get() as u32 * self.max_heads.get() as u32 * self.max_sectors.get() as u32
+    }
+
+    /** Translates the LBA (Logical Block Address, the sector we need to read
+        from disk starting from 0) into CHS (Cylinder Head Sector, physical
+        coordinates of the sector on the disk), to be used in disk I/Os.
+        The math is done in u32 so that large LBAs don't overflow: if the
+        cylinder is past the disk geometry, the sector can't be addressed.
+        With the 1.44MB floppy geometry (80/2/18), LBA 0 is 0/0/1, LBA 18 is
+        0/1/1, LBA 36 is 1/0/1 and LBA 2879 (the last one) is 79/1/18. */
+    fn lba_to_chs(&self, lba: u32) -> (u16, u8, u8) {
+        let max_sectors = self.max_sectors.get() as u32;
+        let max_heads = self.max_heads.get() as u32;
+
+        let cylinder: u32 = (lba / max_sectors) / max_heads;
+        if cylinder >= self.max_cylinders.get() as u32 { panic!("LBA out of the disk CHS range!"); }
+        (
+            cylinder as u16,                            // C
+            ((lba / max_sectors) % max_heads) as u8,    // H
+            ((lba % max_sectors) + 1) as u8             // S
+        )
+    }
+}
+
+impl DiskRead for BiosDisk {
+    /** Loading address must be less than the maximum real mode segmented
+        memory limit, since the address is translated and passed to the BIOS
+        in real mode.
+        Floppies and small disks are read with CHS, supported by any BIOS;
+        reads that go past the CHS range use INT 13h AH=42 instead. */
+    fn read(&self, lba: u32, count: u8, addr: *mut u8) -> bool {
+        if lba + count as u32 > self.chs_sectors() {
+            fs_trace!("Extended read LBA: ", lba);
+            return unsafe { _c_disk_read_lba(self.drive_number, lba, count, addr) };
+        }
+
+        let (cylinder, head, sector) = self.lba_to_chs(lba);
+        fs_trace!("CHS: ", cylinder, "/", head, "/", sector);
+        unsafe { _c_disk_read(self.drive_number, cylinder, head, sector, count, addr) }
+    }
+
+    /** Same constraints as read: writes past the CHS range use INT 13h AH=43. */
+    fn write(&self, lba: u32, count: u8, addr: *const u8) -> bool {
+        if lba + count as u32 > self.chs_sectors() {
+            fs_trace!("Extended write LBA: ", lba);
+            return unsafe { _c_disk_write_lba(self.drive_number, lba, count, addr) };
+        }
+
+        let (cylinder, head, sector) = self.lba_to_chs(lba);
+        fs_trace!("CHS: ", cylinder, "/", head, "/", sector);
+        unsafe { _c_disk_write(self.drive_number, cylinder, head, sector, count, addr) }
+    }
+
+    fn reset(&self) -> bool {
+        unsafe { _c_disk_reset(self.drive_number) }
+    }
+
+    /** The CHS translation uses the BIOS geometry: if the image has been
+        built for another one, wrong sectors would be silently read.
+        If the BIOS geometry isn't available or doesn't match, the BPB one is
+        used instead: the volume has been formatted with it, so it's the best
+        guess. */
+    fn check_geometry(&self, boot_sector: &BootSector) {
+        if self.bios_geometry && self.check_bios_geometry(boot_sector) { return; }
+        self.use_boot_sector_geometry(boot_sector);
+    }
+}
+
+/* ==== MEMORY DISK ========================================================= */
+impl<'a> MemoryDisk<'a> {
+    /** Returns the backend for the given disk image. */
+    #[allow(dead_code)]
+    pub fn new(data: &'a mut [u8]) -> Self { Self { data: Cell::from_mut(data).as_slice_of_cells() } }
+
+    /** Returns the cells of count sectors starting from the given LBA, or
+        None if they're past the end of the image. */
+    #[allow(dead_code)]
+    fn sectors(&self, lba: u32, count: u8) -> Option<&'a [Cell<u8>]> {
+        let start: usize = lba as usize * File::SECTOR_SIZE;
+        let end: usize = start + count as usize * File::SECTOR_SIZE;
+        self.data.get(start..end)
+    }
+}
+
+impl<'a> DiskRead for MemoryDisk<'a> {
+    /** Copies the sectors from the image, fails if they're past its end. */
+    fn read(&self, lba: u32, count: u8, addr: *mut u8) -> bool {
+        match self.sectors(lba, count) {
+            Some(sectors) => {
+                for (i, byte) in sectors.iter().enumerate() { unsafe { *addr.add(i) = byte.get() }; }
+                true
+            },
+            None => false
+        }
+    }
+
+    /** Copies the sectors to the image, fails if they're past its end. */
+    fn write(&self, lba: u32, count: u8, addr: *const u8) -> bool {
+        match self.sectors(lba, count) {
+            Some(sectors) => {
+                for (i, byte) in sectors.iter().enumerate() { byte.set(unsafe { *addr.add(i) }); }
+                true
+            },
+            None => false
+        }
+    }
+}
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::build_image;
+
+    /** Returns a BIOS backend with the given geometry, as if the BIOS had
+        reported it: the drive is never accessed by the geometry checks. */
+    fn bios_disk(max_cylinders: u16, max_heads: u8, max_sectors: u8) -> BiosDisk {
+        BiosDisk {
+            drive_number: 0,
+            max_cylinders: Cell::new(max_cylinders),
+            max_heads: Cell::new(max_heads),
+            max_sectors: Cell::new(max_sectors),
+            bios_geometry: true
+        }
+    }
+
+    /** Returns the boot sector of the test image (18 sectors, 2 heads). */
+    fn boot_sector() -> BootSector {
+        unsafe { core::ptr::read_unaligned(build_image().as_ptr() as *const BootSector) }
+    }
+
+    #[test_case]
+    fn matching_bios_geometry_is_kept() {
+        let disk: BiosDisk = bios_disk(80, 2, 18);
+        disk.check_geometry(&boot_sector());
+        assert!(disk.max_cylinders.get() == 80 && disk.max_heads.get() == 2 && disk.max_sectors.get() == 18);
+    }
+
+    #[test_case]
+    fn mismatching_bios_geometry_is_replaced_by_the_boot_sector_one() {
+        // A 2.88MB geometry for an image formatted with 18 sectors per track
+        let disk: BiosDisk = bios_disk(80, 2, 36);
+        assert!(!disk.check_bios_geometry(&boot_sector()));
+        disk.check_geometry(&boot_sector());
+
+        // 64 sectors fit in 2 cylinders of 36
+        assert!(disk.max_cylinders.get() == 2 && disk.max_heads.get() == 2 && disk.max_sectors.get() == 18);
+        assert!(disk.lba_to_chs(19) == (0, 1, 2));
+    }
+
+    #[test_case]
+    fn lba_to_chs_follows_the_floppy_geometry() {
+        let disk: BiosDisk = bios_disk(80, 2, 18);
+        assert!(disk.lba_to_chs(0) == (0, 0, 1));
+        assert!(disk.lba_to_chs(17) == (0, 0, 18));
+        assert!(disk.lba_to_chs(18) == (0, 1, 1));
+        assert!(disk.lba_to_chs(36) == (1, 0, 1));
+        assert!(disk.lba_to_chs(2879) == (79, 1, 18));
+    }
+}
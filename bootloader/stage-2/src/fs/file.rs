@@ -1,38 +1,106 @@
 use core::mem::zeroed;
 
-use super::directory::DirectoryEntry;
+use super::{directory::DirectoryEntry, FS};
 
 /* ==== TYPE DEFINITION ===================================================== */
 pub struct File {
     /** Copy of the DirectoryEntry associated with the file in the FileSystem */
     pub metadata: DirectoryEntry,
 
+    /** First cluster of the file, 0 for empty files. u32 as in the FAT32
+        entries, FAT12/16 clusters only use the low word. */
+    pub first_cluster: u32,
     /** Current cluster stored in the buffer */
-    pub current_cluster: u16,
+    pub current_cluster: u32,
     /** Buffered sectors of the current cluster stored in the buffer */
     pub current_cluster_read_sectors: u16,
+    /** Offset in the next sector to read of the next byte to be returned by
+        file_read_range - set when seeking or reading at non sector-aligned
+        offsets, since only whole sectors can be read from the disk. */
+    pub byte_within_sector: usize,
+    /** Bytes from the file start to the next sector to read: whole sectors
+        already read or skipped, used to tell how much of the file is left. */
+    pub sector_position: u32,
+    /** Location on the disk of the file DirectoryEntry (LBA of the sector,
+        byte offset in it), if known: used to update it after writing. */
+    pub entry_location: Option<(u32, usize)>,
     /** Buffer used to store the content of the file during read operations */
     pub buffer: [u8; File::SECTOR_SIZE * File::BUFFER_SIZE],
+    /** Slot of the handle registry where this file is listed, if any */
+    #[cfg(feature = "fs-handles")]
+    handle_slot: Option<usize>,
 }
 
 /* ==== TYPE CONSTANTS ====================================================== */
 impl File {
     /** Size of the minimum readable memory from the disk. */
     pub const SECTOR_SIZE: usize = 512;
-    /** Size of file buffer in sections. */
-    pub const BUFFER_SIZE: usize = 1;
+    /** Size of file buffer in sectors: each file_read loads up to this many
+        sectors, with one BIOS call per contiguous run. The File lives on the
+        stage-2 stack, which is shared with the stage-2 code below 0xFFFF. */
+    pub const BUFFER_SIZE: usize = 8;
 }
 
+/* ==== HANDLE REGISTRY ===================================================== */
+/** Maximum number of open files listed by the handle registry. Files opened
+ *  while the registry is full are still counted, but not listed. */
+#[cfg(feature = "fs-handles")]
+const MAX_LISTED_HANDLES: usize = 8;
+
+/** Number of File instances currently alive. */
+#[cfg(feature = "fs-handles")]
+static mut OPEN_HANDLES: usize = 0;
+
+/** Name and first cluster of the listed open files. */
+#[cfg(feature = "fs-handles")]
+static mut LISTED_HANDLES: [Option<([u8; 11], u32)>; MAX_LISTED_HANDLES] = [None; MAX_LISTED_HANDLES];
+
+/** Adds a file to the registry, returning the slot where it is listed. */
+#[cfg(feature = "fs-handles")]
+fn register_handle(metadata: &DirectoryEntry, first_cluster: u32) -> Option<usize> {
+    unsafe {
+        OPEN_HANDLES += 1;
+        let slot: usize = (*core::ptr::addr_of!(LISTED_HANDLES)).iter().position(|h| h.is_none())?;
+        LISTED_HANDLES[slot] = Some((metadata.name, first_cluster));
+        Some(slot)
+    }
+}
+
+/** Removes a file from the registry. */
+#[cfg(feature = "fs-handles")]
+fn deregister_handle(slot: Option<usize>) {
+    unsafe {
+        OPEN_HANDLES -= 1;
+        if let Some(slot) = slot { LISTED_HANDLES[slot] = None; }
+    }
+}
+
+/** Returns the number of File instances currently alive. */
+#[cfg(feature = "fs-handles")]
+pub fn open_handles() -> usize { unsafe { OPEN_HANDLES } }
+
+/** Returns the name and first cluster of the listed open files. */
+#[cfg(feature = "fs-handles")]
+pub fn listed_handles() -> &'static [Option<([u8; 11], u32)>] { unsafe { &*core::ptr::addr_of!(LISTED_HANDLES) } }
+
 /* ==== STATIC TYPE METHODS ================================================= */
 impl File {
     /** Creates a File instance, which contains metadata and reading state.
+        The first cluster is given apart, as the entry doesn't know whether
+        its high word is valid: use FS::file_from_entry to create Files.
         The file content buffer is also initialized, but it's empty.
         In order to fill it, use the file_read method of a Fat12 instance. */
-    pub fn new(metadata: DirectoryEntry) -> Self {
+    pub fn new(metadata: DirectoryEntry, first_cluster: u32) -> Self {
         Self {
-            current_cluster: metadata.lower_first_cluster,
+            first_cluster,
+            current_cluster: first_cluster,
             current_cluster_read_sectors: 0,
+            byte_within_sector: 0,
+            sector_position: 0,
+            entry_location: None,
             buffer: unsafe { zeroed() },
+            #[cfg(feature = "fs-handles")]
+            handle_slot: register_handle(&metadata, first_cluster),
             metadata
         }
     }
@@ -43,11 +111,37 @@ impl File {
     /** Resets File read metadata as it was just initialized.
         To be used before reading to make sure to start from file start. */
     pub fn reset(&mut self) -> () {
-        self.current_cluster = self.metadata.lower_first_cluster;
+        self.current_cluster = self.first_cluster;
         self.current_cluster_read_sectors = 0;
+        self.byte_within_sector = 0;
+        self.sector_position = 0;
     }
 
-    /** Returns if the file has been fully read (current cluster is > FF8, so
-        the last FAT entry has been reached). */
-    pub fn is_fully_read(&self) -> bool { self.current_cluster >= 0x0FF8 }
-}
\ No newline at end of file
+    /** Returns the actual file size in bytes. Reads return whole sectors, so
+        the last one usually contains slack bytes past the end of the file. */
+    pub fn size(&self) -> u32 { self.metadata.file_size }
+
+    /** Returns the number of file bytes after the current reading position:
+        the bytes of the sectors still to read, not counting the slack ones. */
+    pub fn bytes_remaining(&self) -> u32 {
+        self.size().saturating_sub(self.sector_position + self.byte_within_sector as u32)
+    }
+
+    /** Returns if the file has been fully read (current cluster is >= FFF8,
+        so the last FAT entry has been reached). */
+    pub fn is_fully_read(&self) -> bool { self.current_cluster < 2 || self.current_cluster >= FS::FAT_END_OF_CHAIN as u32 }
+
+    /** Moves the reading position to the given byte offset from the file
+        start, so that the next read begins at the sector containing it.
+        Needs the FS to follow the cluster chain: see FS::file_seek. */
+    #[allow(dead_code)]
+    pub fn seek(&mut self, fs: &mut FS, offset: usize) { fs.file_seek(self, offset); }
+
+    /** Closes the file. Same as dropping it, but makes the intent explicit. */
+    pub fn close(self) {}
+}
+
+#[cfg(feature = "fs-handles")]
+impl Drop for File {
+    fn drop(&mut self) { deregister_handle(self.handle_slot); }
+}
@@ -1,104 +1,269 @@
 use core::{slice::from_raw_parts, mem::zeroed};
-//>use crate::{println, prints::{ToString, ToStringBase}, vga::get_vga};
-
-use self::{bootsector::BootSector, directory::DirectoryEntry, file::File};
+use crate::{println, prints::ToString};
+#[cfg(feature = "fs-trace")] use crate::prints::ToStringBase;
+
+use self::{bootsector::BootSector, directory::{DirectoryEntry, LongName}, disk::DiskRead, file::File};
+
+/* ==== TRACING ============================================================= */
+/// Prints the given parameters like println! if the "fs-trace" feature is
+/// enabled, used to trace the driver decisions (LBA/CHS, clusters, entries).
+/// Without the feature, it expands to nothing: arguments are not evaluated.
+#[cfg(feature = "fs-trace")]
+macro_rules! fs_trace {
+    ($($arg:expr),*) => { println!($($arg),*); };
+}
+#[cfg(not(feature = "fs-trace"))]
+macro_rules! fs_trace {
+    ($($arg:expr),*) => {};
+}
 
 /* ==== MODULE EXPORTS ====================================================== */
 pub mod bootsector;
+pub mod crc32;
 pub mod directory;
+pub mod disk;
 pub mod file;
 
-/* ==== ASM EXTERN METHODS ================================================== */
-extern "C" {
-    fn _c_disk_reset(drive: u8) -> bool;
-    fn _c_disk_read(drive: u8, cylinder: u16, head: u8, sector: u8, count: u8, addr: *const u8) -> bool;
-    fn _c_disk_get_params(drive: u8, drive_type: *const u8, max_cylinders: *const u16, max_heads: *const u8, max_sectors: *const u8) -> bool;
+/* ==== TYPE DEFINITION ===================================================== */
+/* FAT variants supported by the driver: they only differ in the FAT entries
+   size (12 or 16 bits), the type is determined by the clusters count. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FatType { Fat12, Fat16 }
+
+/* Reason why a path could not be resolved, returned by the Result variants
+   of the path lookups (FS::resolve_path, FS::open_file). */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /* The path doesn't start with '/'. */
+    NotAbsolute,
+    /* A path component doesn't exist. */
+    NotFound,
+    /* A path component that should contain others is a file. */
+    NotADirectory,
+    /* The path descends through more than FS::MAX_PATH_DEPTH directories. */
+    PathTooDeep,
+    /* The file content doesn't match its checksum (see FS::read_file_checked). */
+    ChecksumMismatch
+}
+
+/* Name of the entry to look for in a directory: the 8.3 form (already parsed
+   with FS::parse_entry_name) or the long file name, as given in the path. */
+#[derive(Clone, Copy)]
+pub enum EntryName<'a> { Short(&'a [u8]), Long(&'a [u8]) }
+
+/* Cursor over the entries of a directory, returned by FS::list_root and
+   FS::list_directory. Deleted and long file name entries are skipped. */
+pub struct DirectoryListing<'a, 'd> {
+    fs: &'a mut FS<'d>,
+    /* Directory file to read the entries from; None for the root directory. */
+    directory: Option<&'a mut File>,
+    index: usize,
+    ended: bool
 }
 
-/* ==== TYPE DEFINITION ===================================================== */
 /* Public struct used from the main module */
-pub struct FS {
+pub struct FS<'d> {
 
-    /*  Physical drive informations */
-    drive_number: u8,
-    //drive_type: u8,
-    //max_cylinders: u16,
-    max_heads: u8,
-    max_sectors: u8,
+    /*  Disk backend used for the sector I/O (BIOS or in-memory image) */
+    disk: &'d dyn DiskRead,
 
     /*  FileSystem informations */
     boot_sector: BootSector,
+    fat_type: FatType,
 
-    /*  FAT and Root Directory buffers - only store one sector (512 bytes)
-        at a time, no need to always store the whole thing.
+    /*  Root Directory buffer - only stores one sector (up to MAX_SECTOR_SIZE
+        bytes) at a time, no need to always store the whole thing.
         The kernel is probably the only file in the disk anyway.
-        The "sector" property indicates which sector of the FAT or the Root
-        Directory is stored in the buffer - if another sector need to be
-        accessed, it must be read from disk and stored in the buffer first. */
-    fat_buffer: [u8; 512],
-    root_buffer: [DirectoryEntry; 16],
-    fat_sector: usize,
-    root_sector: usize
+        The "sector" property indicates which sector of the Root Directory is
+        stored in the buffer - if another sector need to be accessed, it must
+        be read from disk and stored in the buffer first. */
+    root_buffer: [DirectoryEntry; FS::MAX_SECTOR_SIZE / 32],
+    root_sector: usize,
+
+    /*  FAT cache - stores FAT_CACHE_SLOTS sectors, so that following a
+        fragmented cluster chain doesn't reload the same sectors each time
+        the chain jumps back and forth. Each slot has a "sector" tag (as
+        root_sector, 0 if empty) and the tick of its last use: on miss, the
+        least recently used slot is replaced. */
+    fat_cache: [[u8; FS::MAX_SECTOR_SIZE]; FS::FAT_CACHE_SLOTS],
+    fat_cache_sectors: [usize; FS::FAT_CACHE_SLOTS],
+    fat_cache_last_use: [u32; FS::FAT_CACHE_SLOTS],
+    fat_cache_tick: u32,
+
+    /*  Set when the buffered sector has been modified in memory and has not
+        been written back to the disk yet. Dirty buffers are written before
+        a different sector is loaded in their place and on unmount. */
+    fat_cache_dirty: [bool; FS::FAT_CACHE_SLOTS],
+    root_dirty: bool,
+
+    /*  FAT copy the last FAT sector has been read from: the first one, unless
+        its sector could not be read and a backup copy has been used. */
+    fat_copy: u8,
+
+    /*  Number of FAT sectors read from the disk, to check the cache hits. */
+    #[cfg(feature = "fs-trace")]
+    fat_disk_reads: u32,
+
+    /*  Number of free clusters, counted by the first free_bytes call and then
+        kept up to date by set_fat_entry. None until the first count. */
+    free_clusters: Option<u32>,
+
+    /*  Location on the disk of the entry last found by a directory lookup:
+        LBA of its sector and byte offset in it. Copied to the Files opened
+        by path, so that their entry can be updated when they're written. */
+    entry_location: Option<(u32, usize)>,
+
+    /*  If set, file sectors are read again after loading and compared, to
+        catch silent mis-reads on flaky media (see read_disk_verified). */
+    verify: bool
 }
 
 /* ==== CONTRUCTOR ========================================================== */
-impl FS {
-    /// Returns FS instance (Fat12 driver) for the disk associated to the given
-    /// disk number. Initial data are read using extern methods that call BIOS
-    /// for disk I/O to retrieve physical disk metadata and bootsector data.
-    pub fn new(drive_number: u8) -> Self {
-
-        // Initialize variables and get disk parameters:
-        // _c_disk_get_params is implemented in ASM, it switches to 16rm,
-        // calls BIOS INT 13,8 to retrieve disk data and returns to 32pm.
-        // If the BIOS can't retrieve the data, return None.
-        let drive_type: u8 = 0;
-        let max_cylinders: u16 = 0;
-        let max_heads: u8 = 0;
-        let max_sectors: u8 = 0;
-        let outcome: bool = unsafe { _c_disk_get_params(drive_number, &drive_type, &max_cylinders, &max_heads, &max_sectors) };
-        if !outcome { panic!("Could not read disk parameters!"); }
-
-        //>println!("Number: ", drive_number, " - Type: ", drive_type, " - Cylinders: ", max_cylinders, " - Heads: ", max_heads, " - Sectors: ", max_sectors);
-        
+impl<'d> FS<'d> {
+    /// Returns FS instance (Fat12 driver) for the volume on the given disk
+    /// backend: BiosDisk for the boot disk, MemoryDisk for a disk image.
+    /// The boot sector is read right away to retrieve the volume metadata.
+    pub fn new(disk: &'d dyn DiskRead) -> Self {
+
         // Initialize Self struct with zeroed boot sector (empty), to be filled.
-        let fs: Self = Self {
-            drive_number,
-            //drive_type, max_cylinders,
-            max_heads, max_sectors,
-
-            boot_sector: unsafe { zeroed() },
-            fat_buffer: unsafe { zeroed() }, fat_sector: 0, 
-            root_buffer: unsafe { zeroed() }, root_sector: 0
+        let mut fs: Self = Self {
+            disk,
+
+            boot_sector: unsafe { zeroed() }, fat_type: FatType::Fat12,
+            root_buffer: unsafe { zeroed() }, root_sector: 0,
+            fat_cache: unsafe { zeroed() }, fat_cache_sectors: [0; Self::FAT_CACHE_SLOTS],
+            fat_cache_last_use: [0; Self::FAT_CACHE_SLOTS], fat_cache_tick: 0,
+            fat_cache_dirty: [false; Self::FAT_CACHE_SLOTS], root_dirty: false,
+            fat_copy: 0,
+            #[cfg(feature = "fs-trace")]
+            fat_disk_reads: 0,
+            free_clusters: None,
+            entry_location: None,
+            verify: false
         };
 
         // For the boot sector, read one sector (count=1) at disk start (lba=0).
-        // Call BIOS to load boot sector into memory at [&boot_sector].
+        // Load boot sector into memory at [&boot_sector].
         // The boot sector is only 1 sector wide (512 bytes), so count = 1.
-        // If the BIOS can't read from disk, panic.
-        // TODO: before panicking, try resetting and retrying up to 3 times
-        let addr: *const u8 = &fs.boot_sector as *const BootSector as *const u8;
+        // If the disk can't be read, even after retrying, panic.
+        let addr: *mut u8 = &mut fs.boot_sector as *mut BootSector as *mut u8;
         fs.read_disk(0, 1, addr, b"Boot Sector");
 
-        //*! FAT and Root Directory buffers are not initialized, data is read
-        //*! from the disk when fat_buffer_read and root_buffer_read are called.
+        // A blank or non-FAT disk would give garbage geometry: fail early.
+        if !fs.boot_sector.has_valid_signature() { panic!("Invalid boot sector signature"); }
+
+        // The buffers can only hold sectors up to MAX_SECTOR_SIZE bytes, and
+        // logical sectors are read as a whole number of disk sectors.
+        let bytes_per_sector: usize = fs.boot_sector.get_bytes_per_sector() as usize;
+        if bytes_per_sector > Self::MAX_SECTOR_SIZE { panic!("Disk sector size exceeds the FS buffers capacity!"); }
+        if bytes_per_sector == 0 || !bytes_per_sector.is_multiple_of(File::SECTOR_SIZE) { panic!("Unsupported disk sector size!"); }
+
+        // CHS backends translate the LBAs with their geometry: let them check
+        // it against (or adopt) the one the volume has been formatted with.
+        fs.disk.check_geometry(&fs.boot_sector);
+
+        // The FAT type only depends on the number of clusters of the volume.
+        fs.fat_type = fs.boot_sector.fat_type();
+        fs_trace!("FAT type: ", if fs.fat_type == FatType::Fat12 { "FAT12" } else { "FAT16" });
+
+        //*! FAT cache and Root Directory buffer are not initialized, data is read
+        //*! from the disk when fat_buffer_read and root_entry_at are called.
 
         fs
     }
+
+    /// Largest logical sector size (bytes_per_sector) supported: the FAT and
+    /// Root Directory buffers are sized to hold one sector of this size.
+    const MAX_SECTOR_SIZE: usize = 2048;
+
+    /// Returns the size in bytes of the volume logical sectors.
+    fn sector_size(&self) -> usize { self.boot_sector.get_bytes_per_sector() as usize }
+
+    /// Returns the number of BIOS sectors (SECTOR_SIZE bytes) each logical
+    /// sector is made of. Before the boot sector is loaded, it's 1.
+    fn bios_sectors_per_sector(&self) -> u16 {
+        core::cmp::max(1, self.sector_size() / File::SECTOR_SIZE) as u16
+    }
+
+    /// Returns the boot sector of the volume, to read its metadata (label,
+    /// geometry, sizes) without going through the driver.
+    pub fn boot_sector(&self) -> &BootSector { &self.boot_sector }
+
+    /// Enables or disables the verification of file reads: each loaded sector
+    /// is read again and compared, retrying on mismatch. Disabled by default.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Writes back the FAT and Root Directory buffers if they have been
+    /// modified, then drops the FS instance. Without calling this method, any
+    /// change still buffered in memory would be lost.
+    pub fn unmount(mut self) {
+        self.fat_buffer_flush();
+        self.root_buffer_flush();
+    }
+
+    /// Returns the number of File instances currently alive.
+    #[cfg(feature = "fs-handles")]
+    pub fn open_handles(&self) -> usize { file::open_handles() }
+
+    /// Prints name and first cluster of every open File, to look for handles
+    /// that have been kept alive longer than expected.
+    #[cfg(feature = "fs-handles")]
+    pub fn print_open_handles(&self) {
+        println!("Open files: ", file::open_handles());
+        for (name, cluster) in file::listed_handles().iter().flatten() {
+            println!(" - ", &name[..], " @ cluster ", *cluster);
+        }
+    }
 }
 
 
 /* ==== FAT ================================================================= */
-impl FS {
+impl<'d> FS<'d> {
+    /// FAT entry value of a cluster that is not used by any file.
+    const FAT_FREE_CLUSTER: u16 = 0x000;
+    /// FAT entry value of a cluster marked as defective: it must never be
+    /// allocated nor read, even if no file references it.
+    const FAT_BAD_CLUSTER: u16 = 0xFFF7;
+    /// FAT entry values from this one to the bad cluster marker are reserved
+    /// and never used for data clusters.
+    const FAT_RESERVED_CLUSTER: u16 = 0xFFF0;
+    /// FAT entry values greater or equal than this mark the last cluster of a
+    /// file (End Of Chain).
+    pub const FAT_END_OF_CHAIN: u16 = 0xFFF8;
+
     /// Reads the FAT entry located at the given index/cluster from the FAT.
+    /// FAT12 reserved values (0xFF0-0xFFF) are extended to their FAT16
+    /// counterparts (0xFFF0-0xFFFF), so that callers don't need to care about
+    /// the FAT type when checking for bad clusters and end of chain.
+    /// Returns None if the cluster has no entry in the FAT (corrupt chain).
     fn fat_entry_read(&mut self, cluster: u16) -> Option<u16> {
+        if cluster as u32 >= self.fat_entries_count() {
+            fs_trace!("FAT cluster out-of-bounds: ", cluster);
+            return None;
+        }
+
+        if self.fat_type == FatType::Fat16 {
+            // Entries are just little endian words, no packing involved
+            let i: usize = cluster as usize * 2;
+            let lsb: u8 = *self.fat_buffer_read(i)?;
+            let msb: u8 = *self.fat_buffer_read(i+1)?;
+            return Some(((msb as u16) << 8) | lsb as u16);
+        }
+
+        let entry: u16 = self.fat12_entry_read(cluster)?;
+        if entry >= 0x0FF0 { return Some(entry | 0xF000); }
+        Some(entry)
+    }
+
+    /// Reads the 12 bit FAT entry located at the given cluster.
+    fn fat12_entry_read(&mut self, cluster: u16) -> Option<u16> {
 
         // Get single byte position and find index array (element = 2B)
+        // The cluster has already been checked against the FAT size
         let i: usize = cluster as usize * 3 / 2;
 
-        // Check if the requested cluster can exist
-        if i >= self.fat_buffer.len() { panic!("FAT cluster out-of-bounds!"); }
-
         // Get 4 if the reminder is 1 (odd number), 0 otherwise (even number)
         // This number is used for bitshifting by half byte
         let c: u16 = ((cluster * 3) % 2) * 4;
@@ -119,38 +284,223 @@ impl FS {
         Some((word >> c) & 0x0FFF)
     }
 
-    /// Reads the byte at the given index from the FAT stored in the buffer.
-    /// The buffer doesn't store the whole FAT: if the requested value is out
-    /// of range, a disk read is performed to load the correct data first.<br>
-    /// TODO: check out of bounds (requested index > maximum FAT size)
-    /// TODO:   this shouldn't be necessary since the caller already checks.
+    /// Returns the number of entries of a single FAT: 12 bit entries for
+    /// FAT12 (2 entries every 3 bytes), 16 bit entries for FAT16. The whole
+    /// FAT is considered, not only the buffered sector.
+    fn fat_entries_count(&self) -> u32 {
+        let fat_bytes: u32 = self.boot_sector.get_fat_size() as u32 * self.boot_sector.get_bytes_per_sector() as u32;
+        match self.fat_type {
+            FatType::Fat12 => fat_bytes * 2 / 3,
+            FatType::Fat16 => fat_bytes / 2
+        }
+    }
+
+    /// Writes the given value to the FAT entry located at the given cluster.
+    /// FAT12 entries are 12 bits long: the byte shared with the adjacent entry
+    /// keeps the other entry's half, and the value is truncated to 12 bits.
+    /// Changes are buffered (see [`Self::fat_buffer_write()`]).
+    fn set_fat_entry(&mut self, cluster: u16, value: u16) -> Option<()> {
+        if cluster as u32 >= self.fat_entries_count() { return None; }
+
+        // Keep the cached free clusters count in sync with the change
+        if let Some(free) = self.free_clusters {
+            let was_free: bool = self.fat_entry_read(cluster)? == Self::FAT_FREE_CLUSTER;
+            let is_free: bool = value == Self::FAT_FREE_CLUSTER;
+            if was_free && !is_free { self.free_clusters = Some(free - 1); }
+            if !was_free && is_free { self.free_clusters = Some(free + 1); }
+        }
+
+        if self.fat_type == FatType::Fat16 {
+            let i: usize = cluster as usize * 2;
+            self.fat_buffer_write(i, value as u8)?;
+            self.fat_buffer_write(i+1, (value >> 8) as u8)?;
+            return Some(());
+        }
+
+        // Get single byte position, as in fat12_entry_read
+        let i: usize = cluster as usize * 3 / 2;
+        let value: u16 = value & 0x0FFF;
+
+        if cluster.is_multiple_of(2) {
+            // Even: low byte in i, high nibble in the lower 4 bits of i+1
+            let next: u8 = *self.fat_buffer_read(i+1)?;
+            self.fat_buffer_write(i, value as u8)?;
+            self.fat_buffer_write(i+1, (next & 0xF0) | (value >> 8) as u8)?;
+        } else {
+            // Odd: low nibble in the upper 4 bits of i, high byte in i+1
+            let first: u8 = *self.fat_buffer_read(i)?;
+            self.fat_buffer_write(i, (first & 0x0F) | ((value & 0x0F) << 4) as u8)?;
+            self.fat_buffer_write(i+1, (value >> 4) as u8)?;
+        }
+        Some(())
+    }
+
+    /// Searches the FAT for a cluster that can be allocated and returns it.
+    /// Clusters marked as bad are permanently unavailable and always skipped.
+    fn find_free_cluster(&mut self) -> Option<u16> {
+        // Data clusters start from 2, the first two entries are reserved
+        for cluster in 2..self.boot_sector.get_cluster_count() as u16 + 2 {
+            let entry: u16 = self.fat_entry_read(cluster)?;
+            if entry == Self::FAT_BAD_CLUSTER { continue; }
+            if entry == Self::FAT_FREE_CLUSTER { return Some(cluster); }
+        }
+        None
+    }
+
+    /// Reserves a free cluster for a file and returns it, or None if the
+    /// volume is full. The cluster is marked as end of chain: to append it to
+    /// a file, the previous last cluster must be linked to it.
+    /// Changes are buffered, the FAT must be flushed to persist them.
+    fn allocate_cluster(&mut self) -> Option<u16> {
+        let cluster: u16 = self.find_free_cluster()?;
+        self.set_fat_entry(cluster, Self::FAT_END_OF_CHAIN)?;
+        fs_trace!("Allocated cluster ", cluster);
+        Some(cluster)
+    }
+
+    /// Returns the free space of the volume in bytes.
+    /// The first call scans the whole FAT, then the count is updated by each
+    /// FAT change, so that following calls don't need to read the disk.
+    #[allow(dead_code)]
+    pub fn free_bytes(&mut self) -> Option<u32> {
+        let free: u32 = match self.free_clusters {
+            Some(free) => free,
+            None => {
+                let mut free: u32 = 0;
+                for cluster in 2..self.boot_sector.get_cluster_count() as u16 + 2 {
+                    if self.fat_entry_read(cluster)? == Self::FAT_FREE_CLUSTER { free += 1; }
+                }
+                self.free_clusters = Some(free);
+                free
+            }
+        };
+        Some(free * self.boot_sector.get_cluster_size() as u32 * self.sector_size() as u32)
+    }
+
+    /// Returns the cluster that follows the given one in its cluster chain.
+    /// If there's none, it's treated as last cluster (shouldn't happen).
+    /// Bad (FFF7) and reserved (FFF0-FFF6) values are not data clusters: the
+    /// chain is broken, reading them would return garbage.
+    fn next_cluster(&mut self, cluster: u16) -> u16 {
+        let next: u16 = match self.fat_entry_read(cluster) {
+            Some(c) => c,
+            None => Self::FAT_END_OF_CHAIN
+        };
+        if next == Self::FAT_BAD_CLUSTER { panic!("Encountered bad cluster in chain"); }
+        if (Self::FAT_RESERVED_CLUSTER..Self::FAT_BAD_CLUSTER).contains(&next) { panic!("Encountered reserved cluster in chain"); }
+        next
+    }
+
+    /// Marks the given cluster as bad, so that it is never allocated again.
+    /// To be used when a surface scan finds a defective cluster.
+    #[allow(dead_code)]
+    pub fn mark_cluster_bad(&mut self, cluster: u16) -> Option<()> {
+        self.set_fat_entry(cluster, Self::FAT_BAD_CLUSTER)
+    }
+
+    /// Number of FAT sectors kept in the FAT cache.
+    const FAT_CACHE_SLOTS: usize = 4;
+
+    /// Reads the byte at the given index from the FAT stored in the cache.
+    /// The cache doesn't store the whole FAT: if the requested value is not
+    /// cached, a disk read is performed to load the correct data first.
     fn fat_buffer_read(&mut self, entry_index: usize) -> Option<&u8> {
-        // Initialize min and max entry to the actual FAT entries stored in FS
-        // instance. If there is not fat sector loaded, max and min would be 0.
-        let max_entry: usize = self.fat_sector * 512;
-        let min_entry: usize = max_entry.saturating_sub(512 - 1);
+        let sector_size: usize = self.sector_size();
+        let slot: usize = self.fat_cache_slot(entry_index / sector_size + 1);
 
-        // If entry is out of range, buffer correct entries before reading.
-        // If FAT is not loaded, min and max are 0, so entry + 1 is always out
-        // of range and the disk is read.
-        if entry_index+1 < min_entry || max_entry < entry_index+1 {
-            
-            // Calculate the sector (from root start) the entry is in.
-            // Add te number to LBA to read only the needed sector.
-            // Call BIOS to load root entries into memory at [&root_buffer].
-            self.fat_sector = entry_index / 512 + 1;
-            let lba: u16 = self.boot_sector.get_fat_offset() + self.fat_sector as u16 - 1;
-            self.read_disk(lba, 1, &self.fat_buffer as *const u8, b"FAT");
+        // Get relative byte index in the sector and read from cache.
+        self.fat_cache[slot].get(entry_index % sector_size)
+    }
+
+    /// Writes the byte at the given index to the FAT stored in the cache,
+    /// loading the correct sector first (see [`Self::fat_buffer_read()`]).
+    /// The change is only written to disk when the sector is flushed.
+    fn fat_buffer_write(&mut self, entry_index: usize, value: u8) -> Option<()> {
+        let sector_size: usize = self.sector_size();
+        let slot: usize = self.fat_cache_slot(entry_index / sector_size + 1);
+        *self.fat_cache[slot].get_mut(entry_index % sector_size)? = value;
+        self.fat_cache_dirty[slot] = true;
+        Some(())
+    }
+
+    /// Loads the given FAT sector (1-based) in the given cache slot. The FATs
+    /// are identical copies: if the sector of the first one can't be read
+    /// (ex: bad sector on marginal media), the same sector of the following
+    /// copies is tried, at get_fat_offset() + fat_size * copy + sector.
+    /// Panics if no copy can be read.
+    fn fat_sector_load(&mut self, slot: usize, sector: usize) {
+        for copy in 0..self.boot_sector.fat_count {
+            let lba: u32 = self.boot_sector.get_fat_offset() as u32 + self.boot_sector.get_fat_size() as u32 * copy as u32 + sector as u32 - 1;
+            let addr: *mut u8 = self.fat_cache[slot].as_mut_ptr();
+            if self.try_read_disk(lba, 1, addr, b"FAT") {
+                if copy != 0 { println!("Warning: FAT sector ", sector, " unreadable, using FAT copy ", copy); }
+                self.fat_copy = copy;
+                return;
+            }
         }
+        panic!("Could not read any FAT copy!");
+    }
 
-        // Get relative entry index (0-223 --> 0-15) and read from buffer.
-        self.fat_buffer.get(entry_index % 512)
+    /// Returns the FAT copy the last FAT sector has been read from: 0 unless
+    /// the first copy had an unreadable sector. For diagnostics.
+    #[allow(dead_code)]
+    pub fn active_fat_copy(&self) -> u8 { self.fat_copy }
+
+    /// Returns the cache slot storing the given FAT sector (1 based, as the
+    /// tags). On miss, the least recently used slot (or an empty one, whose
+    /// last use is 0) is written back if needed and replaced by the sector.
+    fn fat_cache_slot(&mut self, sector: usize) -> usize {
+        self.fat_cache_tick += 1;
+
+        let slot: usize = match self.fat_cache_sectors.iter().position(|s| *s == sector) {
+            Some(slot) => slot,
+            None => {
+                // Evict the slot that hasn't been used for the longest time
+                let mut slot: usize = 0;
+                for i in 1..Self::FAT_CACHE_SLOTS {
+                    if self.fat_cache_last_use[i] < self.fat_cache_last_use[slot] { slot = i; }
+                }
+
+                // Write back the cached sector before replacing it, if needed.
+                // Add the sector number to the FAT LBA to read only it.
+                // Call BIOS to load FAT entries into memory at [&fat_cache[slot]].
+                self.fat_slot_flush(slot);
+                self.fat_cache_sectors[slot] = sector;
+                self.fat_sector_load(slot, sector);
+
+                #[cfg(feature = "fs-trace")]
+                { self.fat_disk_reads += 1; }
+                slot
+            }
+        };
+
+        self.fat_cache_last_use[slot] = self.fat_cache_tick;
+        slot
+    }
+
+    /// Writes back every modified FAT sector stored in the cache.
+    fn fat_buffer_flush(&mut self) {
+        for slot in 0..Self::FAT_CACHE_SLOTS { self.fat_slot_flush(slot); }
+        fs_trace!("FAT sectors read from disk: ", self.fat_disk_reads);
+    }
+
+    /// If the FAT sector cached in the given slot has been modified, writes it
+    /// back to the disk. The sector is written to each FAT copy to keep them
+    /// identical.
+    fn fat_slot_flush(&mut self, slot: usize) {
+        if !self.fat_cache_dirty[slot] { return; }
+
+        for copy in 0..self.boot_sector.fat_count as u32 {
+            let lba: u32 = self.boot_sector.get_fat_offset() as u32 + self.boot_sector.get_fat_size() as u32 * copy + self.fat_cache_sectors[slot] as u32 - 1;
+            self.write_disk(lba, 1, &self.fat_cache[slot] as *const u8, b"FAT");
+        }
+        self.fat_cache_dirty[slot] = false;
     }
 }
 
 
 /* ==== DIRECTORIES ========================================================= */
-impl FS {
+impl<'d> FS<'d> {
     /// Path separator character, also used as first char for root directory.
     const PATH_SEPARATOR: u8 = b'/';
 
@@ -158,150 +508,320 @@ impl FS {
     /// directory and following eventual sub-directories.
     /// - If any of the path values is not found, [None] is returned.
     /// - If a path directory is found but is file instead, [None] is returned.
+    /// - If the path doesn't start with '/', [None] is returned.
+    ///
+    /// See [`Self::resolve_path()`] to know why the lookup failed.
+    #[allow(dead_code)]
     pub fn get_entry_from_absolute_path(&mut self, path: &[u8]) -> Option<DirectoryEntry> {
+        self.resolve_path(path).ok()
+    }
 
-        // Split the string slice at '/'s and convert to iterator.
-        // Since this is an absolute path, always ignore first char (/).
-        if path[0] != FS::PATH_SEPARATOR { panic!("Path must be absolute"); };
-        let mut path = path[1..].split(|char| *char == FS::PATH_SEPARATOR);
+    /// Searches for the entry at the provided path like
+    /// [`Self::get_entry_from_absolute_path()`], but returns the reason of
+    /// the failure.
+    pub fn resolve_path(&mut self, path: &[u8]) -> Result<DirectoryEntry, FsError> {
+        self.get_entry_from_path(path, false)
+    }
 
-        // Parse original entry name to fit Fat12 format
-        let root_entry_name: [u8; 11] = Self::parse_entry_name(path.next()?);
-        let root_entry_name: &[u8] = root_entry_name.as_slice();
-        //>println!("Reading entry: \"", root_entry_name, "\"");
+    /// Searches for the entry at the provided path like
+    /// [`Self::get_entry_from_absolute_path()`], but path components are
+    /// matched against the long file names of the entries, so they can be
+    /// longer than 8.3. Entries without a long name are matched by 8.3 name.
+    #[allow(dead_code)]
+    pub fn get_entry_from_absolute_path_long(&mut self, path: &[u8]) -> Option<DirectoryEntry> {
+        self.resolve_path_long(path).ok()
+    }
 
-        // Get first entry from root directory
-        // If directory entry has not been found, return None ('?')
-        let mut entry: DirectoryEntry = self.get_entry_from_root(root_entry_name)?.clone();
+    /// Like [`Self::get_entry_from_absolute_path_long()`], but returns the
+    /// reason of the failure.
+    #[allow(dead_code)]
+    pub fn resolve_path_long(&mut self, path: &[u8]) -> Result<DirectoryEntry, FsError> {
+        self.get_entry_from_path(path, true)
+    }
 
-        // If directory entry has been found, keep iterating through the path
-        loop {
-            let entry_name: [u8; 11] = match path.next() {
-                // If iterator is consumed, exit loop and return previous entry
-                None => break,
-
-                // If there's another element in the path, the previous must be
-                // a directory. If not, return None.
-                // If the entry name is empty, ignore and go to next loop (//).
-                // If it is, parse next name and search it in previous dir.
-                Some(e) => {
-                    if !entry.is_directory() { return None; }
-                    if e.is_empty() { continue; }
-                    Self::parse_entry_name(e)
-                }
-            };
+    /// Checks whether an entry (file or directory) exists at the given
+    /// absolute path. Never panics on invalid paths, they don't exist.
+    #[allow(dead_code)]
+    pub fn exists(&mut self, path: &[u8]) -> bool {
+        self.walk_path(path, false).is_ok()
+    }
+
+    /// Checks whether the given absolute path leads to a directory. Returns
+    /// false if nothing is found or if the entry is a file.
+    #[allow(dead_code)]
+    pub fn is_directory(&mut self, path: &[u8]) -> bool {
+        match self.walk_path(path, false) {
+            Ok(Some(entry)) => entry.is_directory(),
+            Ok(None) => true,
+            Err(_) => false
+        }
+    }
+
+    /// Maximum number of nested directories a path can descend through.
+    const MAX_PATH_DEPTH: usize = 16;
 
+    /// Implementation of the absolute path lookups: if long is set, the path
+    /// components are matched as long names, otherwise they're parsed as 8.3.
+    /// The root directory has no entry: paths that lead to it are NotFound.
+    fn get_entry_from_path(&mut self, path: &[u8], long: bool) -> Result<DirectoryEntry, FsError> {
+        self.walk_path(path, long)?.ok_or(FsError::NotFound)
+    }
 
-            // Create file instance using the previos directory metadata
-            let mut file: File = File::new(entry);
+    /// Walks the given absolute path, returning the entry it leads to, or None
+    /// for the root directory ("/", "/dir/.."...).
+    /// Empty components ("//") and "." are skipped, ".." goes back to the
+    /// parent: the entries descended through are kept in a stack, so that the
+    /// parent is known without reading the ".." entry from the disk. As on
+    /// Unix, ".." in the root directory is the root directory itself.
+    fn walk_path(&mut self, path: &[u8], long: bool) -> Result<Option<DirectoryEntry>, FsError> {
 
-            // Read the entries of the directory and retrieve the one we need.
-            // If there's actually no entry with the given name, return None.
-            //>println!("Reading entry: \"", entry_name.as_slice(), "\"");
-            entry = self.get_entry_from_directory(&mut file, entry_name.as_slice())?.clone();
+        // Split the string slice at '/'s and convert to iterator.
+        // Since this is an absolute path, always ignore first char (/).
+        // Relative (or empty) paths can't be resolved: nothing is found.
+        if path.first() != Some(&FS::PATH_SEPARATOR) { return Err(FsError::NotAbsolute); }
+        let path = path[1..].split(|char| *char == FS::PATH_SEPARATOR);
+
+        // Entries of the directories descended through, the last one is the
+        // current entry; if the stack is empty, we're in the root directory.
+        // Their disk locations are kept too: after a "..", the last lookup
+        // isn't the one of the current entry.
+        let mut stack: [DirectoryEntry; FS::MAX_PATH_DEPTH] = unsafe { zeroed() };
+        let mut locations: [Option<(u32, usize)>; FS::MAX_PATH_DEPTH] = [None; FS::MAX_PATH_DEPTH];
+        let mut depth: usize = 0;
+
+        for entry_name in path {
+
+            // If there's another element in the path, the previous must be
+            // a directory. If not, return NotADirectory.
+            if depth > 0 && !stack[depth - 1].is_directory() { return Err(FsError::NotADirectory); }
+
+            // If the entry name is empty or ".", stay in the same directory.
+            // If it's "..", go back to the parent one.
+            match entry_name {
+                b"" | b"." => continue,
+                b".." => { depth = depth.saturating_sub(1); continue; },
+                _ => {}
+            }
+            if depth == Self::MAX_PATH_DEPTH { return Err(FsError::PathTooDeep); }
+
+            // Parse the name (if needed) and search it in the current directory.
+            let parsed: [u8; 11];
+            let entry_name: EntryName = if long { EntryName::Long(entry_name) } else {
+                parsed = Self::parse_entry_name(entry_name);
+                EntryName::Short(parsed.as_slice())
+            };
+            fs_trace!("Reading entry: \"", entry_name.as_bytes(), "\"");
+
+            // The root directory has its own region, the others are read as
+            // files using the directory metadata.
+            // If there's actually no entry with the given name, return NotFound.
+            let entry: DirectoryEntry = if depth == 0 {
+                self.get_entry_from_root(entry_name).ok_or(FsError::NotFound)?.clone()
+            } else {
+                let mut file: File = self.file_from_entry(stack[depth - 1].clone());
+                self.get_entry_from_directory(&mut file, entry_name).ok_or(FsError::NotFound)?.clone()
+            };
+            stack[depth] = entry;
+            locations[depth] = self.entry_location;
+            depth += 1;
         }
 
-        // Entry is not a reference (&) because there would be lifetime issues
-        // since we are mutably referencing self in a loop and returning a
-        // lifetime that would be bound to self from the method.
-        Some(entry)
+        // Entries are not references (&) because there would be lifetime
+        // issues since we are mutably referencing self in a loop and returning
+        // a lifetime that would be bound to self from the method.
+        if depth == 0 { return Ok(None); }
+        self.entry_location = locations[depth - 1];
+        Ok(Some(stack[depth - 1].clone()))
+    }
+
+    /// Checks a directory entry against the name being looked for. Long file
+    /// name entries are collected in the given LongName, to be matched with
+    /// the 8.3 entry that follows them; deleted entries are never matched.
+    /// Long names are compared ignoring case; entries without one are matched
+    /// by their 8.3 name, if the looked for name can be one.
+    fn entry_matches(entry: &DirectoryEntry, name: EntryName, long_name: &mut LongName) -> bool {
+        if entry.is_deleted() { long_name.reset(); return false; }
+        if entry.is_lfn() { long_name.push(entry); return false; }
+
+        let matches: bool = match name {
+            EntryName::Short(name) => name.eq(&entry.name),
+            EntryName::Long(name) if long_name.belongs_to(entry) => long_name.eq_ignore_case(name),
+            EntryName::Long(name) => name.len() <= FS::ENTRY_NAME_LENGTH + 1 && Self::parse_entry_name(name).eq(&entry.name)
+        };
+        long_name.reset();
+        matches
     }
 
     /// The number of directory entries that can be stored in one disk sector.
     /// Used to calculate entry indexes when reading from root directory.
-    const ENTRIES_PER_SECTOR: usize = 512 / 32;
+    fn entries_per_sector(&self) -> usize { self.sector_size() / 32 }
 
     /// Searches for the entry with the provided name in the root directory.
     /// If the needed entry is not currently loaded to the root directory
     /// buffer, a disk read is performed to load the correct data.
     /// The loaded directory entries are checked against the given entry name.
     /// If an entry that matches it is found, it is returned.
-    fn get_entry_from_root(&mut self, name: &[u8]) -> Option<&DirectoryEntry>{
+    fn get_entry_from_root(&mut self, name: EntryName) -> Option<&DirectoryEntry>{
 
         // Start looping for each root directory entry
+        let mut long_name: LongName = LongName::new();
+        let mut found: Option<usize> = None;
         for entry_index in 0..self.boot_sector.root_entries as usize {
-
-            // Calculate current buffered entries first and last actual index.
-            // If there is not root_sector stored, both would be 0.
-            //* Ex: root_sector = 0     max=0*16 -> 0       min=0-(16-1) -> 0
-            //* Ex: root_sector = 1     max=1*16 -> 16      min=16-(16-1) -> 1
-            //* Ex: root_sector = 2     max=2*16 -> 32      min=32-(16-1) -> 17
-            // Entries 0-0: entry always out of range (empty buffer), read disk.
-            // Entries 1-16 (-1 --> 0-15) are buffered and ready to be read.
-            // Entries 17-32 (-1 --> 16-31) are buffered and ready to be read.
-            let max_entry: usize = self.root_sector * Self::ENTRIES_PER_SECTOR;
-            let min_entry: usize = max_entry.saturating_sub(Self::ENTRIES_PER_SECTOR - 1);
-
-            // If entry is out of range, buffer correct entries before reading.
-            if entry_index+1 < min_entry || max_entry < entry_index+1 {
-                
-                // Calculate the sector (from root start) the entry is in.
-                // Add te number to LBA to read only the needed sector.
-                // Call BIOS to load root entries into memory at [&root_buffer].
-                self.root_sector = entry_index / 16 + 1;
-                let lba: u16 = self.boot_sector.get_root_offset() + self.root_sector as u16 - 1;
-                self.read_disk(lba, 1, &self.root_buffer as *const DirectoryEntry as *const u8, b"Root Directory");
-            }
-
-            // Get relative entry index (0-223 --> 0-15) and read from buffer.
-            let entry: &DirectoryEntry =  self.root_buffer.get(entry_index % Self::ENTRIES_PER_SECTOR)?;
+            let entry: &DirectoryEntry = self.root_entry_at(entry_index)?;
 
             // If name's first byte is NULL, there are no more entries, exit.
             if *entry.name.get(0)? == 0x00 { break; }
 
-            // If the name matches the input, this is the entry, return it.
-            if name.eq(&entry.name) { return Some(entry); }
+            // If the name matches the input, this is the entry, exit the loop.
+            // Deleted entries keep their old name, they're never matched, or
+            // a stale entry pointing to reclaimed clusters could be returned.
+            // Returning the entry from here would keep self borrowed, and the
+            // buffer could not be flushed in the next iterations.
+            if Self::entry_matches(entry, name, &mut long_name) { found = Some(entry_index % self.entries_per_sector()); break; }
         }
 
-        // The file has not been found, return None.
-        None
+        // Return the found entry, still in the buffer. If the file has not
+        // been found, return None.
+        let found: usize = found?;
+        let lba: u32 = self.boot_sector.get_root_offset() + self.root_sector as u32 - 1;
+        self.entry_location = Some((lba, found * 32));
+        self.root_buffer.get(found)
+    }
+
+    /// Returns the root directory entry at the given index, loading the
+    /// sector that contains it in the root directory buffer if needed.
+    /// Returns None if the index is past the root directory size.
+    fn root_entry_at(&mut self, entry_index: usize) -> Option<&DirectoryEntry> {
+        if entry_index >= self.boot_sector.root_entries as usize { return None; }
+        let entries_per_sector: usize = self.entries_per_sector();
+
+        // Calculate current buffered entries first and last actual index.
+        // If there is not root_sector stored, both would be 0.
+        //* Ex: root_sector = 0     max=0*16 -> 0       min=0-(16-1) -> 0
+        //* Ex: root_sector = 1     max=1*16 -> 16      min=16-(16-1) -> 1
+        //* Ex: root_sector = 2     max=2*16 -> 32      min=32-(16-1) -> 17
+        // Entries 0-0: entry always out of range (empty buffer), read disk.
+        // Entries 1-16 (-1 --> 0-15) are buffered and ready to be read.
+        // Entries 17-32 (-1 --> 16-31) are buffered and ready to be read.
+        let max_entry: usize = self.root_sector * entries_per_sector;
+        let min_entry: usize = max_entry.saturating_sub(entries_per_sector - 1);
+
+        // If entry is out of range, buffer correct entries before reading.
+        if entry_index+1 < min_entry || max_entry < entry_index+1 {
+            
+            // Write back the buffered sector before replacing it, if needed.
+            // Calculate the sector (from root start) the entry is in.
+            // Add te number to LBA to read only the needed sector.
+            // Call BIOS to load root entries into memory at [&root_buffer].
+            self.root_buffer_flush();
+            self.root_sector = entry_index / entries_per_sector + 1;
+            let lba: u32 = self.boot_sector.get_root_offset() + self.root_sector as u32 - 1;
+            let addr: *mut u8 = self.root_buffer.as_mut_ptr() as *mut u8;
+            self.read_disk(lba, 1, addr, b"Root Directory");
+        }
+
+        // Get relative entry index (0-223 --> 0-15) and read from buffer.
+        self.root_buffer.get(entry_index % entries_per_sector)
     }
 
-    /// The maximum number of directory entries that can be stored in the file
-    /// buffer depends on the file buffer size and entry size (32).
-    const ENTRIES_PER_FILE_BUFFER: usize = File::BUFFER_SIZE * File::SECTOR_SIZE / 32 - 1;
+    /// If the buffered Root Directory sector has been modified, writes it back
+    /// to the disk.
+    fn root_buffer_flush(&mut self) {
+        if !self.root_dirty { return; }
+
+        let lba: u32 = self.boot_sector.get_root_offset() + self.root_sector as u32 - 1;
+        self.write_disk(lba, 1, &self.root_buffer as *const DirectoryEntry as *const u8, b"Root Directory");
+        self.root_dirty = false;
+    }
+
+    /// Returns a cursor over the entries of the root directory.
+    #[allow(dead_code)]
+    pub fn list_root(&mut self) -> DirectoryListing<'_, 'd> {
+        DirectoryListing { fs: self, directory: None, index: 0, ended: false }
+    }
+
+    /// Returns a cursor over the entries of the given directory: its clusters
+    /// are read from the start, following the cluster chain as needed.
+    #[allow(dead_code)]
+    pub fn list_directory<'a>(&'a mut self, file: &'a mut File) -> DirectoryListing<'a, 'd> {
+        file.reset();
+        DirectoryListing { fs: self, directory: Some(file), index: 0, ended: false }
+    }
+
+    /// Returns the directory entry at the given index, to be called with
+    /// sequential indexes starting from 0 on a reset directory File: the next
+    /// chunk is read in the File buffer when the index crosses its end.
+    fn directory_entry_at(&mut self, file: &mut File, entry_index: usize) -> Option<DirectoryEntry> {
+        let entries_per_sector: usize = self.entries_per_sector();
+        if entry_index.is_multiple_of(entries_per_sector) && self.directory_read_sector(file).is_none() { return None; }
+
+        let dir_buffer: &[DirectoryEntry] = unsafe { from_raw_parts(&file.buffer as *const u8 as *const DirectoryEntry, entries_per_sector) };
+        dir_buffer.get(entry_index % entries_per_sector).cloned()
+    }
+
+    /// Reads the next sector of the given directory in its File buffer and
+    /// returns its LBA, or None if the cluster chain is over.
+    /// Directories are read one sector at a time, whatever the buffer size:
+    /// consecutive sectors of the buffer could belong to clusters far apart
+    /// on the disk, and the location of the found entries must be known.
+    /// The buffer tail would also keep stale entries after a short read.
+    fn directory_read_sector(&mut self, file: &mut File) -> Option<u32> {
+        if file.buffer.len() < self.sector_size() { panic!("File buffer is smaller than a disk sector!"); }
+        let lba: u32 = self.file_next_lba(file);
+        let addr: *const u8 = &file.buffer as *const u8;
+        if self.file_read_at(file, addr, 1) == 0 { return None; }
+        Some(lba)
+    }
 
     /// Searches for the entry with the provided name in the given directory.
     /// The given file is reset and read, then the loaded directory entries are
     /// checked against the given entry name. If an entry that matches it is
     /// found, it is returned.<br>
-    pub fn get_entry_from_directory(&mut self, file: &mut File, entry_name: &[u8]) -> Option<&DirectoryEntry> {
+    pub fn get_entry_from_directory(&mut self, file: &mut File, entry_name: EntryName) -> Option<&DirectoryEntry> {
         // Since we need to seek each entry in the directory, we want to start
         // from the first - if for some reason the file has already been read,
         // reset the reading metadata and start from 0.
         if file.current_cluster_read_sectors != 0 { file.reset(); }
 
-        // Read first chunk of file data from disk and place it in the buffer
-        self.file_read(file);
+        // Read first sector of the directory from disk and place it in the
+        // buffer. Keep its LBA, to locate the found entry.
+        let mut buffer_lba: u32 = self.directory_read_sector(file)?;
 
         // Cast byte buffer to entries buffer so that we can loop through them
-        let dir_buffer: &[DirectoryEntry] =  unsafe { from_raw_parts(&file.buffer as *const u8 as *const DirectoryEntry, File::SECTOR_SIZE * File::BUFFER_SIZE / 32) };
+        let entries_per_sector: usize = self.entries_per_sector();
+        let dir_buffer: &[DirectoryEntry] =  unsafe { from_raw_parts(&file.buffer as *const u8 as *const DirectoryEntry, entries_per_sector) };
         
-        // Initialize starting index, max/min values for first chunk of data,
-        // which depend on the file buffer size.
+        // Initialize starting index, min value and end (first index past the
+        // buffer) for first sector of data, which depend on the sector size.
         let mut entry_index: usize = 0;
-        let mut max_entry: usize = Self::ENTRIES_PER_FILE_BUFFER;
+        let mut long_name: LongName = LongName::new();
+        let mut max_entry: usize = entries_per_sector;
         let mut min_entry: usize = 0;
         loop {
 
-            // If entry is out of range, read next chunk of file data from disk.
+            // If entry is out of range, read next sector from disk.
             // Since we are moving to the next set of entries, also increment
-            // min and max entry index values by entries per buffer size.
-            if entry_index < min_entry || max_entry < entry_index {
-                //>println!("Min: ", min_entry, " - Max: ", max_entry, " - Index: ", entry_index);
-                self.file_read(file);
-                max_entry += Self::ENTRIES_PER_FILE_BUFFER;
-                min_entry += Self::ENTRIES_PER_FILE_BUFFER;
+            // min and max entry index values by entries per sector.
+            // If the cluster chain is over, the directory has no more entries.
+            if entry_index < min_entry || max_entry <= entry_index {
+                fs_trace!("Min: ", min_entry, " - Max: ", max_entry, " - Index: ", entry_index);
+                buffer_lba = match self.directory_read_sector(file) { Some(lba) => lba, None => break };
+                max_entry += entries_per_sector;
+                min_entry += entries_per_sector;
             }
 
             // Get relative entry index (0-223 --> 0-15) and read from buffer.
-            let entry: &DirectoryEntry =  dir_buffer.get(entry_index % Self::ENTRIES_PER_FILE_BUFFER)?;
+            let entry: &DirectoryEntry =  dir_buffer.get(entry_index % entries_per_sector)?;
 
             // If name's first byte is NULL, there are no more entries, exit.
             if *entry.name.get(0)? == 0x00 { break; }
 
             // If the entry name matches the given file name, return this entry.
-            if entry_name.eq(&entry.name) { return Some(entry); }
+            if Self::entry_matches(entry, entry_name, &mut long_name) {
+                let offset: usize = (entry_index % entries_per_sector) * 32;
+                self.entry_location = Some((buffer_lba, offset));
+                return Some(entry);
+            }
 
             entry_index += 1;
         }
@@ -311,16 +831,70 @@ impl FS {
 
 
 /* ==== FILES =============================================================== */
-impl FS {
+impl<'d> FS<'d> {
     /// Searches for the given file starting from the root directory and
     /// returns a File instance with a copy of its metadata.
     /// Searching for the file name could involve reading more root directory
     /// entries than are buffered (if any is buffered), so reading from disk
     /// and updating stored data might happen (hence, the mutable reference).
-    /// TODO: implement disk WRITE
+    #[allow(dead_code)]
     pub fn get_file_from_absolute_path(&mut self, path: &[u8]) -> Option<File> {
-        let entry: DirectoryEntry = self.get_entry_from_absolute_path(path)?;
-        Some( File::new(entry) )
+        self.open_file(path).ok()
+    }
+
+    /// Like [`Self::get_file_from_absolute_path()`], but returns the reason
+    /// of the failure.
+    pub fn open_file(&mut self, path: &[u8]) -> Result<File, FsError> {
+        let entry: DirectoryEntry = self.resolve_path(path)?;
+        let mut file: File = self.file_from_entry(entry);
+        file.entry_location = self.entry_location;
+        Ok(file)
+    }
+
+    /// Returns the first cluster of the given entry. The high word of the
+    /// cluster is only used by FAT32: on FAT12/16 it is reserved (OS/2 stored
+    /// extended attributes there), so it is masked to zero.
+    fn entry_first_cluster(&self, entry: &DirectoryEntry) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 | FatType::Fat16 => entry.get_cluster() & 0xFFFF
+        }
+    }
+
+    /// Creates a File instance for the given entry, starting from its first
+    /// cluster as read by [`Self::entry_first_cluster()`].
+    fn file_from_entry(&self, entry: DirectoryEntry) -> File {
+        let first_cluster: u32 = self.entry_first_cluster(&entry);
+        File::new(entry, first_cluster)
+    }
+
+    /// Returns the LBA of the next sector the given File would read.
+    fn file_next_lba(&self, file: &File) -> u32 {
+        if file.is_fully_read() { return 0; }
+        self.boot_sector.get_cluster_offset(file.current_cluster) + file.current_cluster_read_sectors as u32
+    }
+
+    /// Writes the File metadata back to its directory entry on the disk, at
+    /// the location found when the file was opened. If the sector is the one
+    /// in the Root Directory buffer, the buffer is updated instead (flushed
+    /// later), otherwise the sector is read, patched and written.
+    fn file_update_entry(&mut self, file: &File) {
+        let (lba, offset) = match file.entry_location {
+            Some(location) => location,
+            None => return
+        };
+        let entry: &[u8] = unsafe { from_raw_parts(&file.metadata as *const DirectoryEntry as *const u8, 32) };
+
+        let root_lba: u32 = self.boot_sector.get_root_offset() + self.root_sector as u32 - 1;
+        if self.root_sector != 0 && lba == root_lba {
+            self.root_buffer[offset / 32] = file.metadata.clone();
+            self.root_dirty = true;
+            return;
+        }
+
+        let mut scratch: [u8; FS::MAX_SECTOR_SIZE] = [0; FS::MAX_SECTOR_SIZE];
+        self.read_disk(lba, 1, scratch.as_mut_ptr(), b"Directory");
+        scratch[offset..offset + 32].copy_from_slice(entry);
+        self.write_disk(lba, 1, scratch.as_ptr(), b"Directory");
     }
 
     /// Fills the File buffer with its actual content read from the disk.
@@ -328,24 +902,37 @@ impl FS {
     pub fn file_read(&mut self, file: &mut File) -> usize {
         // Get content buffer raw pointer and maximum size
         let addr: *const u8 = &file.buffer as *const u8;
-        let sectors: usize = file.buffer.len() / File::SECTOR_SIZE;
+        let sectors: usize = file.buffer.len() / self.sector_size();
+        if sectors == 0 { panic!("File buffer is smaller than a disk sector!"); }
         return self.file_read_at(file, addr, sectors);
     }
 
-    /// If the File cluster info are present, they're used to load the next
-    /// data to the buffer, overriding the previous content.
+    /// Maximum number of BIOS sectors read with a single call: the count is
+    /// passed to the BIOS as u8, and some BIOSes fail reading 128 or more.
+    const MAX_SECTORS_PER_READ: u16 = 127;
+
+    /// Reads the next sectors of the file directly to the given memory address
+    /// instead of the File buffer: up to `sectors` sectors are read, following
+    /// the cluster chain, and the address is advanced by the sector size for each
+    /// sector so that multi-cluster files are loaded contiguously.
+    /// The File cluster info are updated as in [`Self::file_read()`], so that
+    /// the next call continues where this one stopped; once the last cluster
+    /// has been read, [`File::is_fully_read()`] returns true.
     /// Reading file content could involve following the FAT for cluster lookup
     /// in non buffered entries (if any is buffered), so reading from disk and
     /// updating stored data might happen (hence, the mutable reference).
     /// If the file has been completely read, the method immediatly returns.
     /// In order to read the file from the start again, call File.reset first.
+    /// ! The address must be reachable in real mode (below 1MB), since the
+    /// ! BIOS is used to read from the disk.
     pub fn file_read_at(&mut self, file: &mut File, mut addr: *const u8, mut sectors: usize) -> usize {
-        // If the file has already been fully read, exit.
-        if file.is_fully_read() { return 0; }
+        // If the file has already been fully read or there's no room, exit.
+        if file.is_fully_read() || sectors == 0 { return 0; }
 
         // Get the first cluster the data is stored in from the entry.
         // This cluster number already accounts for the two empty FAT entries.
-        let mut current_cluster: u16 = file.current_cluster;
+        // FAT12/16 clusters fit in the low word of the File cluster.
+        let mut current_cluster: u16 = file.current_cluster as u16;
 
         // Get the size of the disk data that needs to be read
         let cluster_size: u16 = self.boot_sector.get_cluster_size();
@@ -354,29 +941,44 @@ impl FS {
         let mut current_cluster_read_sectors: u16 = file.current_cluster_read_sectors;
 
         let addr_old: *const u8 = addr;
-        //>let sectors_old: usize = sectors;
+
+        // Run of sectors to be read with a single call: clusters that follow
+        // each other on the disk (unfragmented files) are merged, so that a
+        // large buffer is filled with as few BIOS calls as possible.
+        let max_run: u16 = Self::MAX_SECTORS_PER_READ / self.bios_sectors_per_sector();
+        let mut run_lba: u32 = 0;
+        let mut run_count: u16 = 0;
 
         loop {
             // Get offset of the given cluster in the disk
-            let cluster_offset_start: u16 = self.boot_sector.get_cluster_offset(current_cluster);
+            let cluster_offset_start: u32 = self.boot_sector.get_cluster_offset(current_cluster as u32);
 
             // Get address of next sector to read, usually first cluster sector
             // If we already read some sectors of this cluster, skip those
-            let lba = cluster_offset_start + current_cluster_read_sectors;
+            let lba: u32 = cluster_offset_start + current_cluster_read_sectors as u32;
 
             // As the LBA skips already read sectors, the count also decreases
             // If the leftover buffer is smaller than the current count, only
-            // read what fits in.
+            // read what fits in. Large clusters may also exceed what the BIOS
+            // can read in one call: the rest is read in the next iterations.
             let count: u16 = cluster_size - current_cluster_read_sectors;
-            let count: u16 = core::cmp::min(count, sectors as u16);
-
-            // Write at load_addr disk data from given offset (Root Directory)
-            self.read_disk(lba, count as u8, addr, file.metadata.name.as_slice());
+            let count: u16 = core::cmp::min(count as usize, sectors) as u16;
+            let count: u16 = core::cmp::min(count, max_run);
+
+            // If these sectors don't follow the pending run on the disk, or
+            // the run would be too long, read the run first and start anew.
+            if run_count != 0 && (run_lba + run_count as u32 != lba || run_count + count > max_run) {
+                self.file_read_run(file, run_lba, run_count, addr);
+                addr = unsafe { addr.add(run_count as usize * self.sector_size()) };
+                run_count = 0;
+            }
+            if run_count == 0 { run_lba = lba; }
+            run_count += count;
 
-            // After reading, update read sectors and leftover buffer capacity
+            // Update read sectors and leftover buffer capacity
             sectors -= count as usize;
             current_cluster_read_sectors += count;
-            addr = unsafe { addr.add(count as usize * File::SECTOR_SIZE) };
+            file.sector_position += (count as usize * self.sector_size()) as u32;
 
             // If the cluster has been fully read, read next cluster:
             // reset read sectors count, retrieve next cluster from FAT
@@ -384,128 +986,1060 @@ impl FS {
                 current_cluster_read_sectors = 0;
 
                 // Check the FAT for the next cluster
-                // If there's none, treat it as last cluster (shouldn't happen)
-                current_cluster = match self.fat_entry_read(current_cluster) {
-                    Some(c) => c,
-                    None => 0x0FF8
-                };
+                current_cluster = self.next_cluster(current_cluster);
             }
 
-            // If cluster number is >= FF8, that was the last cluster, exit
-            if current_cluster >= 0x0FF8 {
-                file.current_cluster = current_cluster;
+            // If cluster number is >= FFF8, that was the last cluster, exit
+            if current_cluster >= Self::FAT_END_OF_CHAIN {
+                file.current_cluster = current_cluster as u32;
                 break;
             }
 
             // If the buffer is full, save reading metadata and exit
             if sectors == 0 {
-                file.current_cluster = current_cluster;
+                file.current_cluster = current_cluster as u32;
                 file.current_cluster_read_sectors = current_cluster_read_sectors;
                 break;
             }
         }
 
+        // Read the last run, at least one sector is always pending.
+        self.file_read_run(file, run_lba, run_count, addr);
+        addr = unsafe { addr.add(run_count as usize * self.sector_size()) };
+
         // Return number of bytes read from disk; value is not aligned with
-        // file size since only chunks of SECTOR_SIZE can be read.
+        // file size since only whole sectors can be read.
         let read_bytes: usize = addr as usize - addr_old as usize;
 
-        //>println!("Read ", read_bytes, " bytes at ", addr_old.to_string_base(16));
-        //>let slice: &[u8] = unsafe { core::slice::from_raw_parts(addr_old, sectors_old * File::SECTOR_SIZE) };
-        //>println!("File content value:\r\n", slice);
+        fs_trace!("Read ", read_bytes, " bytes at ", addr_old.to_hex());
+        fs_trace!("File content value:\r\n", unsafe { from_raw_parts(addr_old, read_bytes) });
 
         read_bytes
     }
+
+    /// Loads count sectors of the given file starting from the given LBA at
+    /// the given address, verifying them if enabled (see [`Self::set_verify()`]).
+    fn file_read_run(&self, file: &File, lba: u32, count: u16, addr: *const u8) {
+        if self.verify {
+            self.read_disk_verified(lba, count as u8, addr, file.metadata.name.as_slice());
+        } else {
+            self.read_disk(lba, count as u8, addr, file.metadata.name.as_slice());
+        }
+    }
+
+    /// Moves the File reading position to the given byte offset from the file
+    /// start: the next read starts from the sector containing it, and
+    /// [`Self::file_read_range()`] starts exactly from that byte.
+    /// Clusters are a singly linked list, so the FAT is walked from the first
+    /// cluster, even when seeking forward. If the offset is past the cluster
+    /// chain, the file is marked as fully read.
+    pub fn file_seek(&mut self, file: &mut File, offset: usize) {
+        file.reset();
+        self.file_advance(file, offset);
+    }
+
+    /// Moves the File reading position forward by the given amount of bytes,
+    /// following the cluster chain in the FAT if needed.
+    fn file_advance(&mut self, file: &mut File, bytes: usize) {
+        let sector_size: usize = self.sector_size();
+        let cluster_bytes: usize = self.boot_sector.get_cluster_size() as usize * sector_size;
+
+        // Absolute offset from the start of the current cluster
+        let offset: usize = file.current_cluster_read_sectors as usize * sector_size + file.byte_within_sector + bytes;
+
+        // Whole sectors skipped from the current reading position
+        file.sector_position += ((file.byte_within_sector + bytes) / sector_size * sector_size) as u32;
+
+        // Skip the whole clusters, stopping if the chain ends
+        for _ in 0..offset / cluster_bytes {
+            if file.is_fully_read() { break; }
+            file.current_cluster = self.next_cluster(file.current_cluster as u16) as u32;
+        }
+
+        // Set the position inside the cluster reached
+        file.current_cluster_read_sectors = ((offset % cluster_bytes) / sector_size) as u16;
+        file.byte_within_sector = offset % sector_size;
+    }
+
+    /// Fills the given slice with up to out.len() bytes of actual file content
+    /// from the current reading position, stopping at the end of the file
+    /// (file size) instead of the end of the last sector. Returns the number
+    /// of bytes written: less than the slice size only for the final read.
+    #[allow(dead_code)]
+    pub fn read_file(&mut self, file: &mut File, out: &mut [u8]) -> usize {
+        let count: usize = core::cmp::min(out.len(), file.bytes_remaining() as usize);
+        self.file_read_range(file, &mut out[..count])
+    }
+
+    /// Reads the file content like [`Self::read_file()`], computing the CRC32
+    /// of the bytes as they're read, and compares it with the checksum stored
+    /// in the file at the given path: 4 bytes, the CRC32 in little endian (as
+    /// in the gzip trailer). Returns the number of bytes read, or
+    /// [`FsError::ChecksumMismatch`] if the content doesn't match (a short
+    /// checksum file doesn't match either). The checksum file is read first.
+    /// Catches silent corruption that slipped through the disk reads, so that
+    /// a damaged file (ex: the kernel) is never used.
+    pub fn read_file_checked(&mut self, file: &mut File, out: &mut [u8], checksum_path: &[u8]) -> Result<usize, FsError> {
+        let mut checksum_file: File = self.open_file(checksum_path)?;
+        let mut checksum: [u8; 4] = [0; 4];
+        let checksum_read: usize = self.read_file(&mut checksum_file, &mut checksum);
+        checksum_file.close();
+        if checksum_read != checksum.len() { return Err(FsError::ChecksumMismatch); }
+
+        // Read one buffer at a time, adding each chunk to the running CRC.
+        let mut crc: u32 = crc32::CRC32_INIT;
+        let mut read: usize = 0;
+        while read < out.len() {
+            let end: usize = core::cmp::min(read + file.buffer.len(), out.len());
+            let count: usize = self.read_file(file, &mut out[read..end]);
+            if count == 0 { break; }
+            crc = crc32::crc32_update(crc, &out[read..read + count]);
+            read += count;
+        }
+
+        let crc: u32 = crc32::crc32_finish(crc);
+        let expected: u32 = u32::from_le_bytes(checksum);
+        fs_trace!("CRC32: ", crc.to_hex(), " - Expected: ", expected.to_hex());
+        if crc != expected { return Err(FsError::ChecksumMismatch); }
+        Ok(read)
+    }
+
+    /// Fills the given slice with the file content starting from the current
+    /// byte position (see [`Self::file_seek()`]), reading the sectors through
+    /// the File buffer. Returns the number of bytes written to the slice,
+    /// which is less than its size only if the cluster chain has ended.
+    /// If the slice is filled in the middle of a buffered sector, the reading
+    /// position is set to the first byte that hasn't been returned.
+    #[allow(dead_code)]
+    pub fn file_read_range(&mut self, file: &mut File, out: &mut [u8]) -> usize {
+        let mut written: usize = 0;
+
+        while written < out.len() && !file.is_fully_read() {
+            // Save the position to go back to if the buffer isn't consumed
+            let cluster: u32 = file.current_cluster;
+            let read_sectors: u16 = file.current_cluster_read_sectors;
+            let position: u32 = file.sector_position;
+            let skip: usize = file.byte_within_sector;
+
+            // Read next sectors, skip bytes before the position in the first
+            let read: usize = self.file_read(file);
+            if read <= skip { break; }
+            let count: usize = core::cmp::min(read - skip, out.len() - written);
+            out[written..written + count].copy_from_slice(&file.buffer[skip..skip + count]);
+            written += count;
+
+            // All the read bytes have been consumed, keep reading from the
+            // next sector; otherwise, go back and advance to the next byte.
+            file.byte_within_sector = 0;
+            if skip + count < read {
+                file.current_cluster = cluster;
+                file.current_cluster_read_sectors = read_sectors;
+                file.sector_position = position;
+                self.file_advance(file, skip + count);
+            }
+        }
+
+        written
+    }
+
+    /// Overwrites the file content from its start with the given data, one
+    /// sector at a time, reusing the clusters already allocated to the file.
+    /// If the data doesn't fit in the current cluster chain, free clusters
+    /// are allocated and appended to it; if there aren't enough, nothing is
+    /// written and None is returned. Otherwise, returns the bytes written.
+    /// The bytes of the last sector past the data are left untouched. If the
+    /// file grows, its size is updated in the directory entry too.
+    /// The FAT and the entry are written back before returning.
+    /// The File reading position is reset, buffered content may be stale.
+    /// ! Each sector is copied to a scratch buffer before being written, so
+    /// ! that the data doesn't need to be reachable in real mode.
+    #[allow(dead_code)]
+    pub fn file_write(&mut self, file: &mut File, data: &[u8]) -> Option<usize> {
+        let sector_size: usize = self.sector_size();
+        let cluster_size: u16 = self.boot_sector.get_cluster_size();
+        let first_cluster: u16 = file.first_cluster as u16;
+
+        // Count the allocated clusters before writing anything, so that the
+        // file isn't left half overwritten if the data doesn't fit.
+        // Empty files have no clusters at all (first cluster 0).
+        let mut clusters: usize = 0;
+        let mut tail: u16 = 0;
+        let mut cluster: u16 = first_cluster;
+        while (2..Self::FAT_END_OF_CHAIN).contains(&cluster) {
+            clusters += 1;
+            tail = cluster;
+            cluster = self.next_cluster(cluster);
+        }
+
+        // Grow the chain if needed, checking the free space first so that
+        // the file isn't left with only some of the clusters it needs.
+        let cluster_bytes: usize = cluster_size as usize * sector_size;
+        let needed: usize = data.len().div_ceil(cluster_bytes);
+        if needed > clusters {
+            if ((needed - clusters) * cluster_bytes) as u32 > self.free_bytes()? { return None; }
+
+            for _ in clusters..needed {
+                let cluster: u16 = self.allocate_cluster()?;
+
+                // Empty files have no chain: the cluster becomes the first one
+                if tail == 0 {
+                    file.first_cluster = cluster as u32;
+                    file.metadata.lower_first_cluster = cluster;
+                }
+                else { self.set_fat_entry(tail, cluster)?; }
+                tail = cluster;
+            }
+        }
+        let first_cluster: u16 = file.first_cluster as u16;
+
+        let mut scratch: [u8; FS::MAX_SECTOR_SIZE] = [0; FS::MAX_SECTOR_SIZE];
+        let mut written: usize = 0;
+        let mut cluster: u16 = first_cluster;
+
+        while written < data.len() {
+            let cluster_offset: u32 = self.boot_sector.get_cluster_offset(cluster as u32);
+
+            for sector in 0..cluster_size {
+                if written == data.len() { break; }
+                let lba: u32 = cluster_offset + sector as u32;
+                let count: usize = core::cmp::min(sector_size, data.len() - written);
+
+                // Partial sector: load it first to preserve the trailing bytes
+                if count < sector_size { self.read_disk(lba, 1, scratch.as_mut_ptr(), file.metadata.name.as_slice()); }
+                scratch[..count].copy_from_slice(&data[written..written + count]);
+                self.write_disk(lba, 1, scratch.as_ptr(), file.metadata.name.as_slice());
+                written += count;
+            }
+
+            cluster = self.next_cluster(cluster);
+        }
+
+        // Persist the new chain and size (and first cluster, if it was empty)
+        if written as u32 > file.metadata.file_size { file.metadata.file_size = written as u32; }
+        self.file_update_entry(file);
+        self.fat_buffer_flush();
+        self.root_buffer_flush();
+
+        file.reset();
+        Some(written)
+    }
 }
 
 
 /* ==== I/O ================================================================= */
-impl FS {
-    /// Translates the LBA (Logical Block Address, the sector we need to read
-    /// from disk starting from 0) into CHS (Cylinder Head Sector, physical
-    /// coordinates of the sector on the disk), to be used in disk I/Os.
-    fn lba_to_chs(lba: u16, max_sectors: u8, max_heads: u8) -> (u16, u8, u8) {
-        let max_sectors = max_sectors as u16;
-        let max_heads = max_heads as u16;
-        (
-            (lba / max_sectors) / max_heads,            // C
-            ((lba / max_sectors) % max_heads) as u8,    // H
-            ((lba % max_sectors) + 1) as u8             // S
-        )
-    }
-
-    /// Uses disk metadata to load data from disk at given memory location.
-    /// It translates the LBA into CHS and uses it to call an extern ASM
-    /// method that reverts CPU to real mode, performs disk I/O using BIOS
+impl<'d> FS<'d> {
+    /// Uses the disk backend to load data from disk at given memory location.
+    /// LBA and count are in volume logical sectors, converted to disk sectors
+    /// (SECTOR_SIZE bytes): callers must keep the converted count below 256.
+    /// With the BIOS backend, the LBA is translated into CHS (or passed as is
+    /// to the INT 13h extensions, past the CHS range) and an extern
+    /// ASM method reverts CPU to real mode, performs disk I/O using BIOS
     /// interrupts to load data to designated memory address and sets protected
-    /// mode again.
-    /// 
-    /// Loading address must be less than the maximum real mode segmented
-    /// memory limit, since the address is translated and passed to the BIOS
-    /// in real mode.
-    fn read_disk(&self, lba: u16, count: u8, addr: *const u8, _reason: &[u8]) {
-        let (cylinder, head, sector) = FS::lba_to_chs(lba, self.max_sectors, self.max_heads);
+    /// mode again: the address must be below the real mode memory limit.
+    ///
+    /// BIOS reads can fail on real hardware (ex: the floppy motor is still
+    /// spinning up on the first access): on failure, the disk controller is
+    /// reset and the read is retried, up to MAX_DISK_RETRIES times.
+    fn read_disk(&self, lba: u32, count: u8, addr: *const u8, reason: &[u8]) {
+        if !self.try_read_disk(lba, count, addr, reason) { panic!("Could not read from disk!"); }
+    }
+
+    /// Converts an LBA in volume logical sectors into one in disk sectors
+    /// (SECTOR_SIZE bytes), as taken by the disk backends.
+    fn disk_lba(&self, lba: u32) -> u32 {
+        lba * self.bios_sectors_per_sector() as u32
+    }
+
+    /// Loads data from disk like [`Self::read_disk()`], but returns false
+    /// instead of panicking if the read still fails after the retries, so
+    /// that the caller can fall back to another copy of the data.
+    fn try_read_disk(&self, lba: u32, count: u8, addr: *const u8, reason: &[u8]) -> bool {
+        let lba: u32 = self.disk_lba(lba);
+        let count: u8 = (count as u16 * self.bios_sectors_per_sector()) as u8;
+
+        fs_trace!("LBA: ", lba, " - Count: ", count, " - Addr: ", addr.to_hex(), " < ", reason);
+
+        let mut attempts: u8 = 0;
+        loop {
+            attempts += 1;
+            if self.disk.read(lba, count, addr as *mut u8) { return true; }
+            if attempts > Self::MAX_DISK_RETRIES { break; }
+
+            fs_trace!("Read failed, resetting disk - attempt ", attempts);
+            self.disk.reset();
+        }
+
+        // The panic message can't be formatted, print the details before
+        println!("Disk read failed after ", attempts, " attempts < ", reason);
+        false
+    }
 
-        //>println!("LBA: ", lba, " - CHS: ", cylinder, "/", head, "/", sector, " - Count: ", count, " - Addr: ", addr.to_string_base(16), " < ", _reason);
+    /// Maximum number of times a failed disk read is retried.
+    const MAX_DISK_RETRIES: u8 = 3;
+
+    /// Maximum number of times a sector is read again when verification fails.
+    const MAX_VERIFY_RETRIES: u8 = 3;
+
+    /// Loads data from disk like [`Self::read_disk()`], then reads each loaded
+    /// sector again in a scratch buffer and compares the two copies. On
+    /// mismatch one of the reads is wrong: the sector is loaded again and
+    /// compared with a new read, up to MAX_VERIFY_RETRIES times.
+    ///
+    /// Overhead: each sector is read twice, and the verification reads are
+    /// issued one sector at a time, so loading takes a bit more than double
+    /// the time (each BIOS call also switches to real mode and back).
+    /// Only worth it on flaky media, where a silent mis-read would otherwise
+    /// be executed as kernel code.
+    fn read_disk_verified(&self, lba: u32, count: u8, addr: *const u8, reason: &[u8]) {
+        self.read_disk(lba, count, addr, reason);
+
+        let sector_size: usize = self.sector_size();
+        let mut scratch: [u8; FS::MAX_SECTOR_SIZE] = [0; FS::MAX_SECTOR_SIZE];
+        for i in 0..count as u16 {
+            let sector_addr: *const u8 = unsafe { addr.add(i as usize * sector_size) };
+
+            let mut retries: u8 = 0;
+            loop {
+                self.read_disk(lba + i as u32, 1, scratch.as_mut_ptr(), reason);
+                let sector: &[u8] = unsafe { from_raw_parts(sector_addr, sector_size) };
+                if sector.eq(&scratch[..sector_size]) { break; }
+
+                retries += 1;
+                if retries > Self::MAX_VERIFY_RETRIES { panic!("Could not verify disk read!"); }
+                self.read_disk(lba + i as u32, 1, sector_addr, reason);
+            }
+        }
+    }
 
-        let outcome: bool = unsafe { _c_disk_read(self.drive_number, cylinder, head, sector, count, addr) };
-        if !outcome { panic!("Could not read from disk!"); }
+    /// Uses disk metadata to write data from the given memory location to the
+    /// disk. Works just like [`Self::read_disk()`], but in the other direction.
+    fn write_disk(&self, lba: u32, count: u8, addr: *const u8, _reason: &[u8]) {
+        let lba: u32 = self.disk_lba(lba);
+        let count: u8 = (count as u16 * self.bios_sectors_per_sector()) as u8;
+
+        fs_trace!("Write LBA: ", lba, " - Count: ", count, " - Addr: ", addr.to_hex(), " < ", _reason);
+
+        if !self.disk.write(lba, count, addr) { panic!("Could not write to disk!"); }
     }
 
 }
 
 
+/* ==== DIRECTORY LISTING =================================================== */
+impl<'a, 'd> Iterator for DirectoryListing<'a, 'd> {
+    type Item = DirectoryEntry;
+
+    /// Returns the next valid entry, until the end of directory entry (first
+    /// name byte NULL) or the end of the directory region/cluster chain.
+    fn next(&mut self) -> Option<DirectoryEntry> {
+        while !self.ended {
+            let entry: Option<DirectoryEntry> = match &mut self.directory {
+                Some(file) => self.fs.directory_entry_at(file, self.index),
+                None => self.fs.root_entry_at(self.index).cloned()
+            };
+            self.index += 1;
+
+            match entry {
+                Some(entry) if entry.name[0] != 0x00 => {
+                    if entry.is_deleted() || entry.is_lfn() { continue; }
+                    return Some(entry);
+                },
+                _ => self.ended = true
+            }
+        }
+        None
+    }
+}
+
+
 /* ==== PATH AND FILE PARSING =============================================== */
-impl FS {
+impl<'a> EntryName<'a> {
+    /// Returns the name bytes, in the form they are matched.
+    #[allow(dead_code)]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        match self { EntryName::Short(name) | EntryName::Long(name) => name }
+    }
+}
+
+impl<'d> FS<'d> {
     /// Maximum supported size for FAT12 entry names.
     const ENTRY_NAME_LENGTH: usize = 11;
+    /// Size of the name part of FAT12 entry names, the rest is the extension.
+    const ENTRY_BASE_NAME_LENGTH: usize = 8;
 
     /// Parses an ASCII string to be used in the Fat12 I/O operations.
     /// The given string is uppercased, file name and extension are respectively
-    /// place at the start and at the end of the 11 byte string, with spaces
-    /// in between. If the string is longer than 11, it is truncated.
+    /// placed at the start of the 8 byte name and 3 byte extension fields, with
+    /// spaces filling the rest. Longer names and extensions are truncated.
+    /// The extension starts after the last dot; other dots are not valid in
+    /// 8.3 names and are removed, as DOS does. A leading dot doesn't start an
+    /// extension (".gitignore" is a name).
     /// 
     /// # Examples
     /// ```
     /// assert_eq!(FS::parse_entry_name(b"test.bin"), b"TEST    BIN");
     /// assert_eq!(FS::parse_entry_name(b"dir"), b"DIR        ");
-    /// assert_eq!(FS::parse_entry_name(b"iamlongerthan11"), b"IAMLONGERTH");
+    /// assert_eq!(FS::parse_entry_name(b"iamlongerthan11"), b"IAMLONGE   ");
+    /// assert_eq!(FS::parse_entry_name(b"my.file.bin"), b"MYFILE  BIN");
+    /// assert_eq!(FS::parse_entry_name(b"readme.md"), b"README  MD ");
+    /// assert_eq!(FS::parse_entry_name(b".gitignore"), b"GITIGNOR   ");
     /// ```
-    pub fn parse_entry_name(mut entry_name: &[u8]) -> [u8; FS::ENTRY_NAME_LENGTH] {
-
-        // If the file name length is > 11, take a slice
-        if entry_name.len() > FS::ENTRY_NAME_LENGTH {
-            entry_name = &entry_name[..11];
-        }
+    pub fn parse_entry_name(entry_name: &[u8]) -> [u8; FS::ENTRY_NAME_LENGTH] {
 
         // Initialize parsed name buffer with empty spaces
         let mut parsed_entry_name: [u8; FS::ENTRY_NAME_LENGTH] = [b' '; FS::ENTRY_NAME_LENGTH];
 
-        // Initialize extension start position to file length (no extension)
-        let mut extension_index: usize = entry_name.len();
-
-        // Enumerate through file name characters from the end;
-        // if a '.' is found, that is the start of the file extension.
-        for (i, char) in entry_name.iter().enumerate().rev() {
-            if *char == b'.' {
-                extension_index = i;
-                break;
-            }
-        }
+        // Search the last '.' from the end of the file name: if found (and
+        // not the first character), that is the start of the file extension.
+        // Otherwise, set the extension start to the file length (no extension).
+        let extension_index: usize = match entry_name.iter().rposition(|char| *char == b'.') {
+            Some(i) if i > 0 => i,
+            _ => entry_name.len()
+        };
 
         // Parse name before the extension (from 0 to extension index).
-        // Place the uppercase name starting from the start of the buffer.
-        for i in 0..extension_index {
-            parsed_entry_name[i] = entry_name[i].to_ascii_uppercase();
+        // Place the uppercase name, without dots, at the start of the buffer.
+        let name = entry_name[..extension_index].iter().filter(|char| **char != b'.');
+        for (i, char) in name.take(FS::ENTRY_BASE_NAME_LENGTH).enumerate() {
+            parsed_entry_name[i] = char.to_ascii_uppercase();
         }
 
-        // Parse extension (from extension index to file name length).
-        // Place the uppercase extension starting from the end of the buffer.
-        // Characters between name and extension are set to empty space.
-        for i in (extension_index+1..entry_name.len()).rev() {
-            parsed_entry_name[FS::ENTRY_NAME_LENGTH-(entry_name.len()-i)] = entry_name[i].to_ascii_uppercase();
+        // Parse extension (from extension index + 1 to file name length).
+        // Place the uppercase extension right after the 8 byte name field.
+        let extension: &[u8] = entry_name.get(extension_index+1..).unwrap_or(&[]);
+        for (i, char) in extension.iter().take(FS::ENTRY_NAME_LENGTH - FS::ENTRY_BASE_NAME_LENGTH).enumerate() {
+            parsed_entry_name[FS::ENTRY_BASE_NAME_LENGTH + i] = char.to_ascii_uppercase();
         }
 
         // Return parsed file name buffer ("test.bin" --> "TEST    BIN")
         parsed_entry_name
     }
-}
\ No newline at end of file
+}
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+    use core::ops::Range;
+    use super::*;
+    use super::disk::MemoryDisk;
+
+    /// Free conventional memory where the test disk image is built: it doesn't
+    /// fit in the stage-2 stack, and a static would bloat the binary.
+    const IMAGE_ADDR: usize = 0x20000;
+    /// Sectors of the test image: boot sector, two 1-sector FATs, a 1-sector
+    /// root directory (16 entries) and 60 data clusters of 1 sector each.
+    const IMAGE_SECTORS: usize = 64;
+    /// Sector of the first data cluster (cluster 2).
+    const DATA_SECTOR: usize = 4;
+
+    /// Builds a tiny FAT12 volume in memory and returns its bytes:
+    /// - /KERNEL.BIN: 1300 bytes in the clusters 2 -> 3 -> 5, filled with
+    ///   'A', 'B' and 'C' respectively; cluster 4 is free;
+    /// - /DIR: directory in cluster 6, holding FILE.TXT ("hello", cluster 7).
+    pub(super) fn build_image() -> &'static mut [u8] {
+        let image: &mut [u8] = unsafe { core::slice::from_raw_parts_mut(IMAGE_ADDR as *mut u8, IMAGE_SECTORS * File::SECTOR_SIZE) };
+        image.fill(0);
+
+        // Boot sector: BPB fields at their offsets, then the signature
+        image[11..13].copy_from_slice(&(File::SECTOR_SIZE as u16).to_le_bytes());
+        image[13] = 1;                                          // Sectors per cluster
+        image[14..16].copy_from_slice(&1u16.to_le_bytes());     // Reserved sectors
+        image[16] = 2;                                          // FAT count
+        image[17..19].copy_from_slice(&16u16.to_le_bytes());    // Root entries
+        image[19..21].copy_from_slice(&(IMAGE_SECTORS as u16).to_le_bytes());
+        image[22..24].copy_from_slice(&1u16.to_le_bytes());     // Sectors per FAT
+        image[24..26].copy_from_slice(&18u16.to_le_bytes());    // Sectors per track
+        image[26..28].copy_from_slice(&2u16.to_le_bytes());     // Heads
+        image[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        // Both FAT copies: media descriptor and reserved entry, then chains
+        for copy in 0..2 {
+            let fat: &mut [u8] = &mut image[sector_range(1 + copy)];
+            for (cluster, value) in [(0, 0xFF0), (1, 0xFFF), (2, 3), (3, 5), (5, 0xFFF), (6, 0xFFF), (7, 0xFFF)] {
+                set_fat12_entry(fat, cluster, value);
+            }
+        }
+
+        write_entry(&mut image[sector_range(3)], 0, b"KERNEL  BIN", 0x20, 2, 1300);
+        write_entry(&mut image[sector_range(3)], 1, b"DIR        ", 0x10, 6, 0);
+        write_entry(&mut image[cluster_range(6)], 0, b"FILE    TXT", 0x20, 7, 5);
+
+        for (cluster, fill) in [(2, b'A'), (3, b'B'), (5, b'C')] { image[cluster_range(cluster)].fill(fill); }
+        image[cluster_range(7)][..5].copy_from_slice(b"hello");
+        image
+    }
+
+    /// Returns the byte range of the given sector in the test image.
+    pub(super) fn sector_range(sector: usize) -> Range<usize> {
+        sector * File::SECTOR_SIZE..(sector + 1) * File::SECTOR_SIZE
+    }
+
+    /// Returns the byte range of the given cluster in the test image.
+    pub(super) fn cluster_range(cluster: usize) -> Range<usize> {
+        sector_range(DATA_SECTOR + cluster - 2)
+    }
+
+    /// Writes a 12 bit FAT entry in the given FAT bytes, packed as the driver
+    /// expects it (see FS::set_fat_entry).
+    fn set_fat12_entry(fat: &mut [u8], cluster: usize, value: u16) {
+        let i: usize = cluster * 3 / 2;
+        if cluster.is_multiple_of(2) {
+            fat[i] = value as u8;
+            fat[i + 1] = (fat[i + 1] & 0xF0) | (value >> 8) as u8;
+        } else {
+            fat[i] = (fat[i] & 0x0F) | ((value & 0x0F) << 4) as u8;
+            fat[i + 1] = (value >> 4) as u8;
+        }
+    }
+
+    /// Turns the test image into a FAT16 volume with the same files: the
+    /// total sector count is raised past the FAT12 limit of 4084 clusters
+    /// (the sectors past the image are never read) and the FAT is rewritten
+    /// with 16 bit entries.
+    fn build_fat16_image() -> &'static mut [u8] {
+        let image: &mut [u8] = build_image();
+        image[19..21].copy_from_slice(&4200u16.to_le_bytes());
+        for copy in 0..2 {
+            let fat: &mut [u8] = &mut image[sector_range(1 + copy)];
+            fat.fill(0);
+            for (cluster, value) in [(0, 0xFFF0), (1, 0xFFFF), (2, 3), (3, 5), (5, 0xFFFF), (6, 0xFFFF), (7, 0xFFFF)] {
+                set_fat16_entry(fat, cluster, value);
+            }
+        }
+        image
+    }
+
+    /// Writes a 16 bit FAT entry in the given FAT bytes.
+    fn set_fat16_entry(fat: &mut [u8], cluster: usize, value: u16) {
+        fat[cluster * 2..cluster * 2 + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Disk that returns a corrupted copy of the given sector the first time
+    /// it's read, as a flaky medium would, and counts the reads of it.
+    struct FlakyDisk<'a> {
+        disk: MemoryDisk<'a>,
+        lba: u32,
+        reads: Cell<u32>
+    }
+
+    impl<'a> DiskRead for FlakyDisk<'a> {
+        fn read(&self, lba: u32, count: u8, addr: *mut u8) -> bool {
+            if !self.disk.read(lba, count, addr) { return false; }
+            if lba <= self.lba && self.lba < lba + count as u32 {
+                self.reads.set(self.reads.get() + 1);
+                if self.reads.get() == 1 { unsafe { *addr.add((self.lba - lba) as usize * File::SECTOR_SIZE) ^= 0xFF; } }
+            }
+            true
+        }
+    }
+
+    /// Disk that records the reads of the data area (LBA and count) without
+    /// loading anything, so that clusters past the test image can be read.
+    struct RecordingDisk<'a> {
+        disk: MemoryDisk<'a>,
+        reads: Cell<[(u32, u8); 8]>,
+        count: Cell<usize>
+    }
+
+    impl<'a> DiskRead for RecordingDisk<'a> {
+        fn read(&self, lba: u32, count: u8, addr: *mut u8) -> bool {
+            if lba < DATA_SECTOR as u32 { return self.disk.read(lba, count, addr); }
+            let mut reads: [(u32, u8); 8] = self.reads.get();
+            reads[self.count.get()] = (lba, count);
+            self.reads.set(reads);
+            self.count.set(self.count.get() + 1);
+            true
+        }
+    }
+
+    /// Writes the directory entry at the given index of the directory bytes.
+    fn write_entry(directory: &mut [u8], index: usize, name: &[u8; 11], attributes: u8, cluster: u16, size: u32) {
+        let entry: &mut [u8] = &mut directory[index * 32..(index + 1) * 32];
+        entry[..11].copy_from_slice(name);
+        entry[11] = attributes;
+        entry[26..28].copy_from_slice(&cluster.to_le_bytes());
+        entry[28..32].copy_from_slice(&size.to_le_bytes());
+    }
+
+    #[test_case]
+    fn get_file_from_absolute_path_finds_root_and_nested_files() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+
+        let file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+        assert!(file.first_cluster == 2 && file.size() == 1300);
+        let file: File = fs.get_file_from_absolute_path(b"/dir/file.txt").unwrap();
+        assert!(file.first_cluster == 7 && file.size() == 5);
+
+        assert!(fs.get_file_from_absolute_path(b"/missing.bin").is_none());
+        assert!(fs.get_file_from_absolute_path(b"kernel.bin").is_none());
+        assert!(fs.get_file_from_absolute_path(b"/kernel.bin/file.txt").is_none());
+    }
+
+    #[test_case]
+    fn get_file_from_absolute_path_follows_dot_entries() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+
+        assert!(fs.get_file_from_absolute_path(b"/dir/./file.txt").unwrap().first_cluster == 7);
+        assert!(fs.get_file_from_absolute_path(b"/dir/../kernel.bin").unwrap().first_cluster == 2);
+        assert!(matches!(fs.resolve_path(b"/dir/file.txt/.."), Err(FsError::NotADirectory)));
+
+        // The parent of the root directory is the root directory itself
+        assert!(fs.get_file_from_absolute_path(b"/../kernel.bin").unwrap().first_cluster == 2);
+        assert!(fs.get_file_from_absolute_path(b"/../../dir/file.txt").unwrap().first_cluster == 7);
+    }
+
+    #[test_case]
+    fn resolve_path_fails_past_the_maximum_depth() {
+        // /DIR/LOOP is DIR itself: paths can be as deep as needed
+        let image: &mut [u8] = build_image();
+        write_entry(&mut image[cluster_range(6)], 1, b"LOOP       ", 0x10, 6, 0);
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+
+        let mut path: [u8; 4 + 5 * FS::MAX_PATH_DEPTH] = [0; 4 + 5 * FS::MAX_PATH_DEPTH];
+        path[..4].copy_from_slice(b"/dir");
+        for i in 0..FS::MAX_PATH_DEPTH { path[4 + i * 5..9 + i * 5].copy_from_slice(b"/loop"); }
+
+        // One component per level, DIR included
+        let deepest: usize = 4 + 5 * (FS::MAX_PATH_DEPTH - 1);
+        assert!(matches!(fs.resolve_path(&path[..deepest]), Ok(entry) if entry.get_cluster() == 6));
+        assert!(matches!(fs.resolve_path(&path), Err(FsError::PathTooDeep)));
+    }
+
+    #[test_case]
+    fn fat_entry_read_covers_the_whole_multi_sector_fat() {
+        // Two sectors per FAT: the first copy takes sectors 1 and 2. Only the
+        // FAT is read, the rest of the layout doesn't matter here.
+        let image: &mut [u8] = build_image();
+        image[22..24].copy_from_slice(&2u16.to_le_bytes());
+        let fat: &mut [u8] = &mut image[sector_range(1).start..sector_range(2).end];
+        set_fat12_entry(fat, 341, 0x123);   // Split across the two sectors
+        set_fat12_entry(fat, 681, 0xABC);   // Last entry, in the last sector
+
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+        assert!(fs.fat_entry_read(341) == Some(0x123));
+        assert!(fs.fat_entry_read(681) == Some(0xABC));
+        assert!(fs.fat_entry_read(682).is_none());
+    }
+
+    #[test_case]
+    fn next_cluster_follows_the_fat_chain() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+
+        assert!(fs.next_cluster(2) == 3);
+        assert!(fs.next_cluster(3) == 5);
+        assert!(fs.next_cluster(5) >= FS::FAT_END_OF_CHAIN);
+        assert!(fs.fat_entry_read(4) == Some(FS::FAT_FREE_CLUSTER));
+    }
+
+    #[test_case]
+    fn file_read_follows_a_fragmented_chain() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        // The buffer holds more sectors than the chain: the read stops there
+        assert!(fs.file_read(&mut file) == 3 * File::SECTOR_SIZE);
+        assert!(file.is_fully_read());
+        assert!(file.buffer[sector_range(0)].iter().all(|b| *b == b'A'));
+        assert!(file.buffer[sector_range(1)].iter().all(|b| *b == b'B'));
+        assert!(file.buffer[sector_range(2)].iter().all(|b| *b == b'C'));
+        assert!(fs.file_read(&mut file) == 0);
+    }
+
+    #[test_case]
+    fn read_file_stops_at_the_file_size() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/dir/file.txt").unwrap();
+
+        let mut out: [u8; 16] = [0; 16];
+        assert!(fs.read_file(&mut file, &mut out) == 5);
+        assert!(&out[..5] == b"hello");
+    }
+
+    #[test_case]
+    fn verified_reads_load_again_a_mismatching_sector() {
+        let disk: FlakyDisk = FlakyDisk { disk: MemoryDisk::new(build_image()), lba: (DATA_SECTOR + 1) as u32, reads: Cell::new(0) };
+        let mut fs: FS = FS::new(&disk);
+        fs.set_verify(true);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        // Corrupted load, verification read, new load and new verification
+        assert!(fs.file_read(&mut file) == 3 * File::SECTOR_SIZE);
+        assert!(disk.reads.get() == 4);
+        assert!(file.buffer[sector_range(1)].iter().all(|b| *b == b'B'));
+    }
+
+    #[test_case]
+    fn file_write_past_the_chain_end_allocates_and_links_clusters() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        // 4 clusters needed, the chain has 3: the free cluster 4 is appended
+        let data: [u8; 2000] = [b'D'; 2000];
+        assert!(fs.file_write(&mut file, &data) == Some(2000));
+
+        // Check the persisted state with a new driver, without its caches
+        let mut fs: FS = FS::new(&disk);
+        assert!(fs.next_cluster(5) == 4);
+        assert!(fs.next_cluster(4) >= FS::FAT_END_OF_CHAIN);
+
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+        assert!(file.size() == 2000);
+        assert!(fs.file_read(&mut file) == 4 * File::SECTOR_SIZE);
+        assert!(file.buffer[..2000].iter().all(|b| *b == b'D'));
+    }
+
+    /// Adds /KERNEL.CRC (cluster 8) to the test image, holding the CRC32 of
+    /// the /KERNEL.BIN content.
+    fn add_kernel_checksum(image: &mut [u8]) {
+        let mut content: [u8; 1300] = [0; 1300];
+        content[..512].fill(b'A');
+        content[512..1024].fill(b'B');
+        content[1024..].fill(b'C');
+
+        for copy in 0..2 { set_fat12_entry(&mut image[sector_range(1 + copy)], 8, 0xFFF); }
+        write_entry(&mut image[sector_range(3)], 2, b"KERNEL  CRC", 0x20, 8, 4);
+        image[cluster_range(8)][..4].copy_from_slice(&crc32::crc32(&content).to_le_bytes());
+    }
+
+    #[test_case]
+    fn read_file_checked_accepts_a_matching_checksum() {
+        let image: &mut [u8] = build_image();
+        add_kernel_checksum(image);
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        let mut out: [u8; 1300] = [0; 1300];
+        assert!(fs.read_file_checked(&mut file, &mut out, b"/kernel.crc") == Ok(1300));
+    }
+
+    #[test_case]
+    fn read_file_checked_rejects_a_corrupted_byte() {
+        let image: &mut [u8] = build_image();
+        add_kernel_checksum(image);
+        image[cluster_range(3)][100] ^= 0x01;
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        let mut out: [u8; 1300] = [0; 1300];
+        assert!(fs.read_file_checked(&mut file, &mut out, b"/kernel.crc") == Err(FsError::ChecksumMismatch));
+    }
+
+    /// Returns the 8.3 name of the numbered test file FILEnn.BIN.
+    fn numbered_name(n: usize) -> [u8; 11] {
+        let mut name: [u8; 11] = *b"FILE00  BIN";
+        name[4] = b'0' + (n / 10) as u8;
+        name[5] = b'0' + (n % 10) as u8;
+        name
+    }
+
+    #[test_case]
+    fn root_lookup_finds_the_entries_around_a_sector_boundary() {
+        // 32 root entries: the root directory takes sectors 3 and 4, the 17th
+        // entry is the first one of the second sector. Only the lookup is
+        // tested, the data clusters are shifted and not read.
+        let image: &mut [u8] = build_image();
+        image[17..19].copy_from_slice(&32u16.to_le_bytes());
+        image[sector_range(4)].fill(0);
+        for n in 0..17 {
+            let sector: usize = 3 + n / 16;
+            write_entry(&mut image[sector_range(sector)], n % 16, &numbered_name(n), 0x20, 10 + n as u16, 1);
+        }
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+
+        assert!(fs.get_file_from_absolute_path(b"/file15.bin").unwrap().first_cluster == 25);
+        assert!(fs.get_file_from_absolute_path(b"/file16.bin").unwrap().first_cluster == 26);
+        assert!(fs.get_file_from_absolute_path(b"/file00.bin").unwrap().first_cluster == 10);
+        assert!(fs.get_file_from_absolute_path(b"/file17.bin").is_none());
+    }
+
+    #[test_case]
+    fn directory_lookup_finds_the_entries_around_a_cluster_boundary() {
+        // DIR holds 16 entries in cluster 6 and the 17th in cluster 9
+        let image: &mut [u8] = build_image();
+        for copy in 0..2 {
+            set_fat12_entry(&mut image[sector_range(1 + copy)], 6, 9);
+            set_fat12_entry(&mut image[sector_range(1 + copy)], 9, 0xFFF);
+        }
+        for n in 0..17 {
+            let cluster: usize = if n < 16 { 6 } else { 9 };
+            write_entry(&mut image[cluster_range(cluster)], n % 16, &numbered_name(n), 0x20, 10 + n as u16, 1);
+        }
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+
+        assert!(fs.get_file_from_absolute_path(b"/dir/file15.bin").unwrap().first_cluster == 25);
+        assert!(fs.get_file_from_absolute_path(b"/dir/file16.bin").unwrap().first_cluster == 26);
+        assert!(fs.get_file_from_absolute_path(b"/dir/file17.bin").is_none());
+    }
+
+    #[test_case]
+    fn find_free_cluster_skips_the_bad_clusters() {
+        // Cluster 4 is the first free one: mark it bad on disk
+        let image: &mut [u8] = build_image();
+        for copy in 0..2 { set_fat12_entry(&mut image[sector_range(1 + copy)], 4, 0xFF7); }
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+        assert!(fs.find_free_cluster() == Some(8));
+
+        // The same through the driver
+        assert!(fs.mark_cluster_bad(8) == Some(()));
+        assert!(fs.find_free_cluster() == Some(9));
+    }
+
+    #[test_case]
+    fn file_read_range_starts_from_the_seeked_byte() {
+        // Replace the first KERNEL.BIN cluster content with its byte offsets
+        let image: &mut [u8] = build_image();
+        for (i, b) in image[cluster_range(2)].iter_mut().enumerate() { *b = i as u8; }
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        fs.file_seek(&mut file, 100);
+        let mut out: [u8; 10] = [0; 10];
+        assert!(fs.file_read_range(&mut file, &mut out) == 10);
+        assert!(out == core::array::from_fn(|i| 100 + i as u8));
+
+        // The next read continues right after the returned bytes
+        assert!(fs.file_read_range(&mut file, &mut out[..1]) == 1);
+        assert!(out[0] == 110);
+    }
+
+    #[test_case]
+    fn file_seek_follows_the_chain_past_the_first_cluster() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        // Second cluster (3), then across the jump from cluster 3 to 5
+        let mut out: [u8; 8] = [0; 8];
+        fs.file_seek(&mut file, 600);
+        assert!(fs.file_read_range(&mut file, &mut out) == 8);
+        assert!(&out == b"BBBBBBBB");
+        fs.file_seek(&mut file, 1020);
+        assert!(fs.file_read_range(&mut file, &mut out) == 8);
+        assert!(&out == b"BBBBCCCC");
+
+        // Seeking backwards restarts from the first cluster
+        fs.file_seek(&mut file, 4);
+        assert!(fs.file_read_range(&mut file, &mut out) == 8);
+        assert!(&out == b"AAAAAAAA");
+    }
+
+    #[test_case]
+    fn fat16_volumes_follow_16_bit_chains() {
+        let disk: MemoryDisk = MemoryDisk::new(build_fat16_image());
+        let mut fs: FS = FS::new(&disk);
+        assert!(fs.fat_type == FatType::Fat16);
+
+        assert!(fs.next_cluster(2) == 3);
+        assert!(fs.next_cluster(3) == 5);
+        assert!(fs.next_cluster(5) >= FS::FAT_END_OF_CHAIN);
+
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+        let mut out: [u8; 1300] = [0; 1300];
+        assert!(fs.read_file(&mut file, &mut out) == 1300);
+        assert!(out[..512].iter().all(|b| *b == b'A'));
+        assert!(out[512..1024].iter().all(|b| *b == b'B'));
+        assert!(out[1024..].iter().all(|b| *b == b'C'));
+    }
+
+    #[test_case]
+    fn entry_first_cluster_masks_the_high_word_on_fat16() {
+        // KERNEL.BIN is root entry 0, the high word is at offset 20
+        let image: &mut [u8] = build_fat16_image();
+        image[sector_range(3)][20..22].copy_from_slice(&0x1234u16.to_le_bytes());
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+        assert!(file.first_cluster == 2);
+        let mut out: [u8; 4] = [0; 4];
+        assert!(fs.read_file(&mut file, &mut out) == 4);
+        assert!(&out == b"AAAA");
+    }
+
+    #[test_case]
+    fn lookup_skips_a_deleted_entry_before_the_live_one() {
+        // Deleted KERNEL.BIN (cluster 4) first, the real one after DIR
+        let image: &mut [u8] = build_image();
+        let root: &mut [u8] = &mut image[sector_range(3)];
+        write_entry(root, 0, b"\xE5ERNEL  BIN", 0x20, 4, 512);
+        write_entry(root, 2, b"KERNEL  BIN", 0x20, 2, 1300);
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+
+        let file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+        assert!(file.first_cluster == 2);
+        assert!(file.size() == 1300);
+    }
+
+    #[test_case]
+    fn unmount_writes_back_the_dirty_buffers() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+
+        // Buffered changes only: a FAT entry and the KERNEL.BIN size
+        assert!(fs.mark_cluster_bad(4) == Some(()));
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+        file.metadata.file_size = 1000;
+        fs.file_update_entry(&file);
+
+        let mut sector: [u8; File::SECTOR_SIZE] = [0; File::SECTOR_SIZE];
+        assert!(disk.read(3, 1, sector.as_mut_ptr()));
+        assert!(sector[28..32] == 1300u32.to_le_bytes());
+
+        fs.unmount();
+
+        // Cluster 4 is even: its entry takes byte 6 and the low half of 7
+        assert!(disk.read(1, 1, sector.as_mut_ptr()));
+        assert!(sector[6] == 0xF7 && sector[7] & 0x0F == 0x0F);
+        assert!(disk.read(3, 1, sector.as_mut_ptr()));
+        assert!(sector[28..32] == 1000u32.to_le_bytes());
+    }
+
+    #[test_case]
+    fn file_write_grows_a_file_from_one_to_three_clusters() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/dir/file.txt").unwrap();
+
+        // The free clusters 4 and 8 are appended to cluster 7
+        let data: [u8; 1200] = [b'E'; 1200];
+        assert!(fs.file_write(&mut file, &data) == Some(1200));
+
+        let mut fs: FS = FS::new(&disk);
+        assert!(fs.next_cluster(7) == 4);
+        assert!(fs.next_cluster(4) == 8);
+        assert!(fs.next_cluster(8) >= FS::FAT_END_OF_CHAIN);
+
+        let mut file: File = fs.get_file_from_absolute_path(b"/dir/file.txt").unwrap();
+        assert!(file.size() == 1200);
+        let mut out: [u8; 1200] = [0; 1200];
+        assert!(fs.read_file(&mut file, &mut out) == 1200);
+        assert!(out == data);
+    }
+
+    #[test_case]
+    fn file_write_allocates_the_first_cluster_of_an_empty_file() {
+        let image: &mut [u8] = build_image();
+        write_entry(&mut image[sector_range(3)], 2, b"EMPTY   TXT", 0x20, 0, 0);
+        let disk: MemoryDisk = MemoryDisk::new(image);
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/empty.txt").unwrap();
+
+        assert!(fs.file_write(&mut file, b"not empty") == Some(9));
+
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/empty.txt").unwrap();
+        assert!(file.first_cluster == 4);
+        assert!(file.size() == 9);
+        assert!(fs.next_cluster(4) >= FS::FAT_END_OF_CHAIN);
+        let mut out: [u8; 16] = [0; 16];
+        assert!(fs.read_file(&mut file, &mut out) == 9);
+        assert!(&out[..9] == b"not empty");
+    }
+
+    #[test_case]
+    fn file_read_at_splits_clusters_larger_than_a_bios_read() {
+        // 128 sectors per cluster: KERNEL.BIN clusters 2 and 3 are contiguous
+        let image: &mut [u8] = build_image();
+        image[13] = 128;
+        image[19..21].copy_from_slice(&(DATA_SECTOR as u16 + 128 * 4).to_le_bytes());
+        let disk: RecordingDisk = RecordingDisk { disk: MemoryDisk::new(image), reads: Cell::new([(0, 0); 8]), count: Cell::new(0) };
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        // Nothing is loaded, the address is never written
+        assert!(fs.file_read_at(&mut file, 0x80000 as *const u8, 256) == 256 * File::SECTOR_SIZE);
+
+        // Every call fits the limit, and they cover the 2 clusters in order
+        let reads: [(u32, u8); 8] = disk.reads.get();
+        let mut lba: u32 = DATA_SECTOR as u32;
+        for (read_lba, count) in &reads[..disk.count.get()] {
+            assert!(*count as u16 <= FS::MAX_SECTORS_PER_READ);
+            assert!(*read_lba == lba);
+            lba += *count as u32;
+        }
+        assert!(lba == DATA_SECTOR as u32 + 256);
+    }
+
+    #[cfg(feature = "fs-handles")]
+    #[test_case]
+    fn open_handles_counts_the_files_until_closed() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+        let before: usize = fs.open_handles();
+
+        let kernel: File = fs.open_file(b"/kernel.bin").ok().unwrap();
+        let text: File = fs.open_file(b"/dir/file.txt").ok().unwrap();
+        let directory: File = fs.open_file(b"/dir").ok().unwrap();
+        assert!(fs.open_handles() == before + 3);
+        assert!(file::listed_handles().iter().flatten().any(|(name, cluster)| name == b"FILE    TXT" && *cluster == 7));
+
+        kernel.close();
+        text.close();
+        assert!(fs.open_handles() == before + 1);
+        directory.close();
+        assert!(fs.open_handles() == before);
+        assert!(!file::listed_handles().iter().flatten().any(|(name, _)| name == b"FILE    TXT"));
+    }
+
+    #[test_case]
+    fn free_bytes_is_restored_after_creating_and_deleting_a_file() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+
+        // 60 data clusters, KERNEL.BIN, DIR and FILE.TXT take 5 of them
+        let initial: u32 = fs.free_bytes().unwrap();
+        assert!(initial == 55 * File::SECTOR_SIZE as u32);
+
+        // Create: two clusters linked together, counted by the cache
+        let first: u16 = fs.allocate_cluster().unwrap();
+        let second: u16 = fs.allocate_cluster().unwrap();
+        assert!(fs.set_fat_entry(first, second) == Some(()));
+        assert!(fs.free_bytes() == Some(initial - 2 * File::SECTOR_SIZE as u32));
+
+        // Delete: both clusters are freed
+        assert!(fs.set_fat_entry(first, FS::FAT_FREE_CLUSTER) == Some(()));
+        assert!(fs.set_fat_entry(second, FS::FAT_FREE_CLUSTER) == Some(()));
+        assert!(fs.free_bytes() == Some(initial));
+
+        // The cached count matches a new scan of the FAT
+        fs.free_clusters = None;
+        assert!(fs.free_bytes() == Some(initial));
+    }
+
+    #[test_case]
+    fn bytes_remaining_ignores_the_slack_of_the_last_sector() {
+        let disk: MemoryDisk = MemoryDisk::new(build_image());
+        let mut fs: FS = FS::new(&disk);
+        let mut file: File = fs.get_file_from_absolute_path(b"/kernel.bin").unwrap();
+
+        // 1300 bytes: 2 full sectors and 276 bytes of the third one
+        assert!(file.size() == 1300);
+        assert!(file.bytes_remaining() == 1300);
+
+        let mut out: [u8; 600] = [0; 600];
+        assert!(fs.read_file(&mut file, &mut out) == 600);
+        assert!(file.bytes_remaining() == 700);
+
+        // Reads stop at the file size, not at the end of the last sector
+        assert!(fs.read_file(&mut file, &mut out) == 600);
+        assert!(fs.read_file(&mut file, &mut out) == 100);
+        assert!(file.bytes_remaining() == 0);
+        assert!(file.size() == 1300);
+    }
+}
@@ -16,23 +16,45 @@
     reason and print it to the screen when the panic_handler is triggered. */
 #![feature(panic_info_message)]
 
+/*  Replace the standard test harness, which needs std, with our own: the
+    #[test_case] functions are collected and given to testing::test_runner,
+    called through the generated test_main. */
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
 /* ==== MODULES ============================================================= */
 use core::panic::PanicInfo;
 use vga::{get_vga, Vga};
 use prints::ToString;
-use fs::{FS, file::File};
+use fs::{FS, FsError, disk::BiosDisk, file::File};
+use memory::MemoryMap;
 
 mod vga;    // Use VGA module
 mod pmio;   // Make PMIO module visible to VGA module
 mod prints;
+mod output;
 mod fs;
+mod memory;
+#[cfg(any(test, debug_assertions))]
+mod qemu;
+#[cfg(test)]
+mod testing;
 
 /* ==== CONSTANTS =========================================================== */
 /// Define kernel binary absolute path in the disk
 const KERNEL_PATH: &[u8] = b"/kernel/main.bin";
 
+/// CRC32 of the kernel binary, written by the Makefile when building the image.
+const KERNEL_CHECKSUM_PATH: &[u8] = b"/kernel/main.crc";
+
+/// If set, each kernel sector is read twice and compared before execution.
+/// More than doubles the loading time: only enable on flaky media.
+const VERIFY_KERNEL_READS: bool = false;
+
 /// Define function type as its interface - to be used for function pointers.
-type KernelStart = extern "C" fn() -> !;
+/// The kernel receives the BIOS memory map, it can't call the BIOS itself.
+type KernelStart = extern "C" fn(*const MemoryMap) -> !;
 
 /// Stack pointer starts from 0xFFFF, place kernel just above it.
 /// Could also directly define the pointer as '*const extern "C" fn()->!'.
@@ -64,42 +86,70 @@ const KERNEL_MEM_OFFSET: *const KernelStart = 0x10000 as *const KernelStart;
 pub extern "C" fn _rs_start(drive_number: u32) -> ! {
 
     /* ==== VGA DRIVER INIT ================================================= */
-    // Get VGA driver static instance, clear screen from BIOS and stage-1 text.
-    let vga: &mut Vga = get_vga();
+    // Until here, prints go through the BIOS teletype (the default sink).
+    // Get VGA driver static instance, clear screen from BIOS and stage-1 text,
+    // then print through it: it doesn't leave protected mode for each char.
+    let vga: &'static mut Vga = get_vga();
     vga.clear();
     vga.clear_cursor();
+    output::set_output_sink(vga);
+
+    // Test builds only run the tests, QEMU is closed at the end
+    #[cfg(test)]
+    test_main();
+
+    /* ==== MEMORY DETECTION ================================================ */
+    // Retrieve the memory map now, the kernel runs in 32pm only. The map is
+    // kept in this stack frame, which is never left: the kernel never returns.
+    let memory_map: MemoryMap = memory::detect_memory();
+    println!("Memory: ", memory_map.usable_kib(), " KiB usable");
 
     /* ==== FAT12 DRIVER INIT =============================================== */
     // Initialize Fat12 "driver" as mutable: reading would change its state
     // since we need to buffer root directories and FAT entries when needed.
-    let mut fat12: FS = FS::new(drive_number as u8);
+    // The boot disk is accessed through the BIOS.
+    let disk: BiosDisk = BiosDisk::new(drive_number as u8);
+    let mut fat12: FS = FS::new(&disk);
+    fat12.set_verify(VERIFY_KERNEL_READS);
+    println!("Volume: ", fat12.boot_sector().volume_label());
 
     /* ==== FILE READING ==================================================== */
     // Retrieve metadata of the Kernel file we need to load from full dir path
-    let mut file: File = match fat12.get_file_from_absolute_path(KERNEL_PATH) {
-        Some(f) => f,
-        None => panic!("File not found!")
+    let mut file: File = match fat12.open_file(KERNEL_PATH) {
+        Ok(f) => f,
+        Err(FsError::NotAbsolute) => panic!("Kernel path must be absolute!"),
+        Err(FsError::NotADirectory) => panic!("Kernel path goes through a file!"),
+        Err(FsError::NotFound) => panic!("Kernel file not found!"),
+        Err(FsError::PathTooDeep) => panic!("Kernel path is too deep!"),
+        Err(FsError::ChecksumMismatch) => panic!("Kernel checksum mismatch!")
     };
     println!("Succesfully read file at ", KERNEL_PATH);
 
-    // Define src and dst pointers - file buffer content and kernel fn pointer
-    let src = &file.buffer as *const u8;
-    let mut dst: *mut u8 = KERNEL_MEM_OFFSET as *mut u8;
-
-    // Load {count} bytes from disk to file buffer one sector at a time.
-    // Copy buffer content to kernel pointer; increment for next iteration.
+    // Load the kernel bytes at the kernel fn pointer, going through the file
+    // buffer one chunk at a time, and check them against the kernel CRC32:
+    // executing a kernel corrupted by a silent mis-read would run garbage.
     // ! file_read_at could be used to avoid memcpy, but we'd be limited at the
     // ! maximum real mode addressable memory (0xFFFFF, 1MB, with 20bit bus and
     // ! segmented model), breaking for KERNEL_OFFSET + KERNEL_SIZE < 0xFFFFF.
-    while !file.is_fully_read() {
-        let count: usize = fat12.file_read(&mut file);
-        unsafe {
-            core::ptr::copy_nonoverlapping(src, dst, count);
-            dst = dst.add(count);
-        };
+    // Only copy the actual file bytes, the last sector has slack space.
+    let kernel_size: usize = file.bytes_remaining() as usize;
+    let kernel: &mut [u8] = unsafe { core::slice::from_raw_parts_mut(KERNEL_MEM_OFFSET as *mut u8, kernel_size) };
+    match fat12.read_file_checked(&mut file, kernel, KERNEL_CHECKSUM_PATH) {
+        Ok(read) if read == kernel_size => { println!("Kernel checksum verified"); },
+        Ok(_) => panic!("Kernel file is truncated!"),
+        Err(FsError::ChecksumMismatch) => panic!("Kernel checksum mismatch!"),
+        Err(_) => panic!("Could not read the kernel checksum!")
     }
     
+    // The kernel file is fully loaded, no handle should be left open.
+    file.close();
+    #[cfg(feature = "fs-handles")]
+    if fat12.open_handles() != 0 { fat12.print_open_handles(); }
+
     /* ==== FILE EXECUTION ================================================== */
+    // Write back any pending FS change, the driver is not needed anymore.
+    fat12.unmount();
+
     // Stage-2 completed, start the kernel
     println!("Starting Kernel..!");
 
@@ -110,12 +160,13 @@ pub extern "C" fn _rs_start(drive_number: u32) -> ! {
 
     // Transmute defined pointer into function and call it - start the kernel.
     let _kernel_start: KernelStart = unsafe { core::mem::transmute(KERNEL_MEM_OFFSET) };
-    _kernel_start();
+    _kernel_start(&memory_map);
 }
 
 /* ==== PANIC HANDLER ======================================================= */
 /// "panic_handler" defines the method that is invoked when a panic occurs.
 ///  In a no_std environment we need to define it ourselves.
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
 
@@ -127,4 +178,9 @@ fn panic(_info: &PanicInfo) -> ! {
 }
 
 /* ==== TESTS =============================================================== */
-// TODO: custom framework for no_std testing
\ No newline at end of file
+/// Test builds report the failed test and close QEMU.
+#[cfg(test)]
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    testing::test_panic_handler(_info)
+}
\ No newline at end of file
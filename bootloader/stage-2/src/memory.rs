@@ -0,0 +1,66 @@
+/* ==== ASM EXTERN METHODS ================================================== */
+extern "C" {
+    fn _c_memory_detect(entries: *mut MemoryRegion, max_entries: u32) -> u32;
+}
+
+/* ==== TYPE DEFINITION ===================================================== */
+/** Entry of the BIOS memory map (INT 15,E820), describes a physical memory
+ *  region. The layout is shared with the kernel, which receives the map. */
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MemoryRegion {
+    pub base: u64,
+    pub length: u64,
+    pub region_type: u32,
+    /** ACPI 3.0 extended attributes: bit 0 unset means ignore the entry. */
+    pub attributes: u32
+}
+
+/** Physical memory map, as returned by the BIOS: regions may be unsorted
+ *  and overlapping. Only the first count regions are valid. */
+#[repr(C)]
+pub struct MemoryMap {
+    pub regions: [MemoryRegion; MemoryMap::MAX_REGIONS],
+    pub count: u32
+}
+
+/* ==== TYPE CONSTANTS ====================================================== */
+impl MemoryRegion {
+    /** Region type of the RAM free to use; all the other types are reserved
+     *  (ACPI tables, BIOS, memory mapped devices, bad memory...). */
+    pub const TYPE_USABLE: u32 = 1;
+}
+
+impl MemoryMap {
+    /** Maximum number of regions stored, the others are ignored. */
+    pub const MAX_REGIONS: usize = 32;
+}
+
+/* ==== METHODS ============================================================= */
+impl MemoryRegion {
+    /** Checks whether the region is free RAM that can be used. */
+    pub fn is_usable(&self) -> bool {
+        self.region_type == MemoryRegion::TYPE_USABLE && self.attributes & 1 != 0 && self.length != 0
+    }
+}
+
+impl MemoryMap {
+    /** Returns the valid regions of the map. */
+    pub fn regions(&self) -> &[MemoryRegion] { &self.regions[..self.count as usize] }
+
+    /** Returns the total size in KiB of the usable regions. */
+    pub fn usable_kib(&self) -> u32 {
+        self.regions().iter().filter(|r| r.is_usable()).map(|r| (r.length / 1024) as u32).sum()
+    }
+}
+
+/** Retrieves the physical memory map from the BIOS.
+ *  _c_memory_detect is implemented in ASM, it switches to 16rm, calls BIOS
+ *  INT 15,E820 until the last entry and returns to 32pm.
+ *! The map must be in the first MB of memory (the stack is).
+ *  If the BIOS doesn't support the call, the map is empty. */
+pub fn detect_memory() -> MemoryMap {
+    let mut map: MemoryMap = unsafe { core::mem::zeroed() };
+    map.count = unsafe { _c_memory_detect(map.regions.as_mut_ptr(), MemoryMap::MAX_REGIONS as u32) };
+    map
+}
@@ -0,0 +1,47 @@
+use crate::vga::Vga;
+
+/* ==== ASM EXTERN METHODS ================================================== */
+extern "C" {
+    fn _c_print_char(ascii: u8);
+}
+
+/* ==== STATIC INITIALIZATION AND SYNCHRONIZATION =========================== */
+/** BIOS teletype instance, the default sink: it works before the VGA driver
+ *  is initialized. */
+static mut BIOS_TELETYPE: BiosTeletype = BiosTeletype;
+
+/** Sink the print macros write to, changed with set_output_sink.
+ *! Using the sink is unsafe since it requires static multi-thread mutable
+ *! access, like the VGA instance. */
+static mut OUTPUT: &'static mut dyn Output = unsafe { &mut *core::ptr::addr_of_mut!(BIOS_TELETYPE) };
+
+/** Public method to get mutable reference to the current output sink. */
+pub fn get_output() -> &'static mut dyn Output { unsafe { &mut **core::ptr::addr_of_mut!(OUTPUT) } }
+
+/** Selects the sink print! and println! write to from now on, ex: the VGA
+ *  driver once it's initialized. */
+pub fn set_output_sink(output: &'static mut dyn Output) { unsafe { OUTPUT = output; } }
+
+/* ==== TYPE DEFINITION ===================================================== */
+/** Destination of the text printed by the print macros. The bytes are ASCII
+ *  characters: each sink must handle CR and LF, println! ends with "\r\n". */
+pub trait Output {
+    fn write_bytes(&mut self, s: &[u8]);
+}
+
+/** Prints through BIOS INT 10,E, switching to real mode for each character:
+ *  slow, but it doesn't depend on the driver state. */
+pub struct BiosTeletype;
+
+/* ==== TRAIT IMPLEMENTATIONS =============================================== */
+impl Output for BiosTeletype {
+    fn write_bytes(&mut self, s: &[u8]) {
+        for c in s.iter() {
+            unsafe { _c_print_char(*c) };
+        }
+    }
+}
+
+impl Output for Vga {
+    fn write_bytes(&mut self, s: &[u8]) { self.print(s); }
+}
@@ -13,6 +13,8 @@ pub enum PortMappedAddr {
     VgaRegisterIndexRW3,
     VgaRegisterIndexW2 = 0x3CE,
     VgaRegisterIndexRW2,    // Previous +1
+    QemuDebugConsole = 0xE9,
+    QemuDebugExit = 0xF4,
     // ...
 }
 
@@ -4,30 +4,34 @@
 // TODO: implement ToString trait for floats, doubles
 // TODO: implement number formatting for numbers (hex, binary...)
 
-/** Prints the given parameters as characters to the screen.
+/** Prints the given parameters as characters to the current output sink
+ *  (BIOS teletype or VGA, see output::set_output_sink).
  *  Supports strings, \[u8\] slices and unsigned integer types.
  *  This crate implements the ToString trait for these types. */
 #[macro_export]
 macro_rules! print {
     ($($arg:expr),*) => {
-        let vga = get_vga();
+        let output = $crate::output::get_output();
         {
             $(
+                // Print each argument right after converting it, the
+                // conversions share the same buffer (see ToString).
                 let s = $arg.to_string();
-                vga.print(s);
+                output.write_bytes(s);
             )*
         }
     };
 }
 
-/** Prints the given parameters as characters to the screen.
+/** Prints the given parameters as characters to the current output sink
+ *  (BIOS teletype or VGA, see output::set_output_sink).
  *  Supports strings, \[u8\] slices and unsigned integer types.
  *  This crate implements the ToString trait for these types.
  *  Creates a new line after printing. */
 #[macro_export]
 macro_rules! println {
     ($($arg:expr),*) => {
-        let vga = get_vga();
+        let output = $crate::output::get_output();
         {
             $(
                 // To avoid error "temporary value dropped while borrowed",
@@ -36,21 +40,72 @@ macro_rules! println {
                 // can use it as the owner of the reference.
                 let s = $arg;
                 let s = s.to_string();
-                vga.print(s);
+                output.write_bytes(s);
             )*
         }
-        vga.ln();
+        output.write_bytes(b"\r\n");
     };
 
 }
 
+/** Writes the given parameters as characters to the given &mut [u8] buffer
+ *  instead of the screen, one after the other, so that the formatted text
+ *  can be sent anywhere (VGA, serial, disk). Supports the same types as
+ *  print!. Returns the written part of the buffer, or None if the text
+ *  doesn't fit: in that case, the buffer content is not meaningful.
+ *  Ex: fmt!(&mut buffer, "LBA: ", lba, " - Count: ", count) */
+#[macro_export]
+macro_rules! fmt {
+    ($buffer:expr, $($arg:expr),*) => {{
+        let buffer: &mut [u8] = $buffer;
+        let mut length: usize = 0;
+        let mut fits: bool = true;
+        $(
+            // Copy each argument right after converting it, the
+            // conversions share the same buffer (see ToString).
+            let s = $arg;
+            let s = s.to_string();
+            if fits && length + s.len() <= buffer.len() {
+                buffer[length..length + s.len()].copy_from_slice(s);
+                length += s.len();
+            } else {
+                fits = false;
+            }
+        )*
+        if fits { Some(&buffer[..length]) } else { None }
+    }};
+}
+
 /* ==== TRAIT DEFINITION ==================================================== */
 /*  Define ToString trait so that we can implement a custom to_string function
     for each type we need to print with the print! macro.
     The trait is implement by defining a to_string method that returns a
-    reference to a static string. */
+    reference to a static string.
+    ! Numbers are all converted in the same static BUFFER: the returned slice
+    ! is only valid until the next conversion, which overwrites it. Use it
+    ! right away, never keep two of them: print! and println! print each
+    ! argument before converting the next one for this reason. */
 pub trait ToString { fn to_string(&self) -> &[u8]; }
-pub trait ToStringBase { fn to_string_base(&self, base: u8) -> &[u8]; }
+pub trait ToStringBase {
+    fn to_string_base(&self, base: u8) -> &[u8];
+
+    /** Hex representation padded to the full type width, without prefix.
+        Ex: 0xB8000u32 --> 000B8000. */
+    fn to_hex_no_prefix(&self) -> &[u8] { self.to_string_base(16) }
+
+    /** Hex representation padded to the full type width, with 0x prefix.
+        Ex: 0xB8000u32 --> 0x000B8000, 0u16 --> 0x0000.
+        The prefix is written in BUFFER right before the digits, which are
+        always placed at its end. */
+    fn to_hex(&self) -> &[u8] {
+        let start: usize = BUFFER_SIZE - self.to_hex_no_prefix().len() - 2;
+        unsafe {
+            BUFFER[start] = b'0';
+            BUFFER[start + 1] = b'x';
+            &BUFFER[start..]
+        }
+    }
+}
 
 /* ==== STATIC BUFFERS AND DIGITS =========================================== */
 /** List of possible digits for a number to print.
@@ -58,7 +113,8 @@ pub trait ToStringBase { fn to_string_base(&self, base: u8) -> &[u8]; }
 const DIGITS: &[u8] = b"0123456789ABCDEF";
 const BUFFER_SIZE: usize = 129;
 /** Static buffer to store ASCII representation without allocator.
-    Maximum size is 128 for u128 number (base 2), +1 for '-' if needed. */
+    Maximum size is 128 for u128 number (base 2), +1 for '-' if needed.
+    Shared by all the conversions, see the ToString trait. */
 static mut BUFFER: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
 
 /* ==== TRAIT IMPLEMENTATION FOR STRINGS ==================================== */
@@ -235,7 +291,10 @@ macro_rules! to_string_impl_int {
                 // value and cast to unsigned integer, keeping it as signed
                 // would break the divisions (-1 / base is not what we want...).
                 // -2: 0b11111110 --> ~ --> 0b00000001 --> + 1 --> 0b00000010: 2
-                let mut num: $uty = (!num + 1) as $uty;
+                // The complement is done on the unsigned value: for iN::MIN
+                // the magnitude doesn't fit in the signed type and +1 would
+                // overflow, while it does in the unsigned one (-128 --> 128).
+                let mut num: $uty = (!(num as $uty)).wrapping_add(1);
                 let base: $uty = base as $uty;
 
                 loop {
@@ -294,12 +353,13 @@ to_string_impl_int!(isize, usize);
 
 /* ==== TRAIT IMPLEMENTATION FOR POINTERS =================================== */
 /*  For pointers, the implementation is just the same as the unsigned integer
-    types, all it takes is a type cast. */
+    types, all it takes is a type cast. The impls are generic over the pointed
+    type; addresses are printed in hex with the 0x prefix (see to_hex). */
 #[macro_export]
 macro_rules! to_string_impl_ptr {
     // Match ty: type for which we are implementing the to_string method.
     ($ty:ty) => {
-        impl ToStringBase for $ty {
+        impl<T> ToStringBase for $ty {
             fn to_string_base(&self, base: u8) -> &[u8] {
                 // (*self as usize).to_string_base(base)
 
@@ -342,39 +402,69 @@ macro_rules! to_string_impl_ptr {
             }
         }
 
-        impl ToString for $ty {
+        impl<T> ToString for $ty {
             fn to_string(&self) -> &[u8] {
                 //(*self as usize).to_string()
 
                 //> ============================================================
-                self.to_string_base(10)
+                self.to_hex()
                 //> ============================================================
             }
         }
     };
 }
 
-to_string_impl_ptr!(*const u8);
-to_string_impl_ptr!(*const u16);
-to_string_impl_ptr!(*const u32);
-to_string_impl_ptr!(*const u64);
-to_string_impl_ptr!(*const u128);
-to_string_impl_ptr!(*const usize);
-to_string_impl_ptr!(*const i8);
-to_string_impl_ptr!(*const i16);
-to_string_impl_ptr!(*const i32);
-to_string_impl_ptr!(*const i64);
-to_string_impl_ptr!(*const i128);
-to_string_impl_ptr!(*const isize);
-to_string_impl_ptr!(*mut u8);
-to_string_impl_ptr!(*mut u16);
-to_string_impl_ptr!(*mut u32);
-to_string_impl_ptr!(*mut u64);
-to_string_impl_ptr!(*mut u128);
-to_string_impl_ptr!(*mut usize);
-to_string_impl_ptr!(*mut i8);
-to_string_impl_ptr!(*mut i16);
-to_string_impl_ptr!(*mut i32);
-to_string_impl_ptr!(*mut i64);
-to_string_impl_ptr!(*mut i128);
-to_string_impl_ptr!(*mut isize);
\ No newline at end of file
+to_string_impl_ptr!(*const T);
+to_string_impl_ptr!(*mut T);
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn i8_min_is_printed_in_every_base() {
+        assert!(i8::MIN.to_string_base(2) == b"10000000");
+        assert!(i8::MIN.to_string_base(10) == b"-128");
+        assert!(i8::MIN.to_string_base(16) == b"80");
+    }
+
+    #[test_case]
+    fn i32_min_is_printed_in_every_base() {
+        assert!(i32::MIN.to_string_base(2) == b"10000000000000000000000000000000");
+        assert!(i32::MIN.to_string_base(10) == b"-2147483648");
+        assert!(i32::MIN.to_string_base(16) == b"80000000");
+    }
+
+    #[test_case]
+    fn i64_min_is_printed_in_every_base() {
+        assert!(i64::MIN.to_string_base(2) == b"1000000000000000000000000000000000000000000000000000000000000000");
+        assert!(i64::MIN.to_string_base(10) == b"-9223372036854775808");
+        assert!(i64::MIN.to_string_base(16) == b"8000000000000000");
+    }
+
+    #[test_case]
+    fn minus_one_is_printed_in_every_base() {
+        assert!((-1i32).to_string_base(2) == b"11111111111111111111111111111111");
+        assert!((-1i32).to_string_base(10) == b"-1");
+        assert!((-1i32).to_string_base(16) == b"FFFFFFFF");
+    }
+
+    #[test_case]
+    fn to_hex_pads_zero_to_the_type_width() {
+        assert!(0u32.to_hex() == b"0x00000000");
+        assert!(0u16.to_hex() == b"0x0000");
+    }
+
+    #[test_case]
+    fn to_hex_prints_uppercase_digits() {
+        assert!(0xDEADBEEFu32.to_hex() == b"0xDEADBEEF");
+    }
+
+    #[test_case]
+    fn to_hex_prints_pointers_as_addresses() {
+        let hex: &[u8] = (0xB8000 as *const u16).to_hex();
+        assert!(hex.len() == 2 + 2 * core::mem::size_of::<usize>());
+        assert!(hex.starts_with(b"0x000") && hex.ends_with(b"000B8000"));
+    }
+}
@@ -0,0 +1,54 @@
+// QEMU helpers, only compiled for test and dev builds: on real hardware the
+// ports used here may belong to other devices.
+//
+// The isa-debug-exit device closes QEMU when a value is written to its port,
+// with (value << 1) | 1 as exit code: 0 can't be returned, so that a normal
+// QEMU exit (0) is never mistaken for a success.
+// QEMU must be started with "-device isa-debug-exit,iobase=0xf4,iosize=0x04".
+//
+// Stage-2 has no serial driver: the text is sent to the QEMU debug console
+// instead, one byte per character. QEMU must be started with "-debugcon
+// stdio" (or "-debugcon file:<path>") to get it.
+
+use crate::output::Output;
+use crate::pmio::{Port, PortMappedAddr};
+
+/* ==== STATIC INITIALIZATION =============================================== */
+/** Debug console instance, to be used as output sink. */
+#[allow(dead_code)]
+static mut DEBUG_CONSOLE: DebugConsole = DebugConsole;
+
+/** Returns reference to static debug console instance. */
+#[allow(dead_code)]
+pub fn get_debug_console() -> &'static mut DebugConsole { unsafe { &mut *core::ptr::addr_of_mut!(DEBUG_CONSOLE) } }
+
+/* ==== TYPE DEFINITION ===================================================== */
+/** Values written to isa-debug-exit. QEMU exits with 33 for Success and 35
+ *  for Failed: both are distinct from the codes of QEMU own errors (1). */
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11
+}
+
+/** Prints to the QEMU debug console (port 0xE9). Writes are ignored if the
+ *  console is not enabled. */
+pub struct DebugConsole;
+
+/* ==== TRAIT IMPLEMENTATIONS =============================================== */
+impl Output for DebugConsole {
+    fn write_bytes(&mut self, s: &[u8]) {
+        let port: Port = unsafe { Port::new(PortMappedAddr::QemuDebugConsole) };
+        for c in s.iter() { port.outb(*c); }
+    }
+}
+
+/* ==== METHODS ============================================================= */
+/** Closes QEMU with the given exit code. Does nothing (and returns) if the
+ *  isa-debug-exit device is not present. */
+#[allow(dead_code)]
+pub fn exit(code: QemuExitCode) {
+    unsafe { Port::new(PortMappedAddr::QemuDebugExit) }.outl(code as u32);
+}
@@ -0,0 +1,47 @@
+// Custom test framework: the standard one needs std, so the test harness is
+// replaced (custom_test_frameworks). Functions marked with #[test_case] are
+// collected by the compiler and given to test_runner, which runs them one
+// after the other and reports the results on the QEMU debug console.
+//
+// Once all the tests are run (or when one panics), QEMU is closed through
+// the isa-debug-exit device, so that the host gets the outcome as exit code
+// (see qemu::exit).
+
+use crate::{output, print, println, prints::ToString};
+use crate::qemu::{self, QemuExitCode};
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// A test that can be run by test_runner. Implemented for all the functions
+/// without arguments, so that #[test_case] can be used on plain functions.
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    /// Prints the test name, runs it and prints "ok" if it didn't panic.
+    fn run(&self) {
+        let name: &str = core::any::type_name::<T>();
+        print!(name, "... ");
+        self();
+        println!("ok");
+    }
+}
+
+/* ==== METHODS ============================================================= */
+/// Test harness entry point, called by the generated test_main. The output
+/// is redirected to the debug console, the screen is not visible to the host.
+pub fn test_runner(tests: &[&dyn Testable]) {
+    output::set_output_sink(qemu::get_debug_console());
+    println!("Running ", tests.len(), " tests");
+    for test in tests { test.run(); }
+    qemu::exit(QemuExitCode::Success);
+}
+
+/// Panic handler variant for the tests: the running test has failed, the
+/// message is reported and QEMU is closed with the failure code.
+pub fn test_panic_handler(info: &core::panic::PanicInfo) -> ! {
+    println!("FAILED");
+    println!("Error: ", info.message().unwrap().as_str().unwrap_or("Unknown"));
+    qemu::exit(QemuExitCode::Failed);
+    loop {}
+}
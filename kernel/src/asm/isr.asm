@@ -16,7 +16,7 @@
     global _c_isr_%1
 
     _c_isr_%1:
-        %if %1!=8 && %1!=10 && %1!=11 && %1!=12 && %1!=13 && %1!=14 && %1!=17 && %1!=21
+        %if %1!=8 && %1!=10 && %1!=11 && %1!=12 && %1!=13 && %1!=14 && %1!=17 && %1!=21 && %1!=29 && %1!=30
         push 0
         %endif
         push %1
@@ -36,7 +36,7 @@ extern _rs_isr_dispatcher
 
 ; Before calling ISR, CPU pushes some registers values to save its state.
 ; If ISR is called by lower privilege-level, the stack used is switched; stack informations are also pushed.
-; No ring change saves:         EFLAGS, CS, EIP, ErrorCode? (for Exceptions 8, 10, 11, 12, 13, 14 17, 21)
+; No ring change saves:         EFLAGS, CS, EIP, ErrorCode? (for Exceptions 8, 10, 11, 12, 13, 14, 17, 21, 29, 30)
 ; Ring change saves:   SS, ESP, EFLAGS, CS, EIP, ErrorCode?
 _c_isr_dispatcher:
     ; General use registers may be needed in case of exception or interrupt for further analysis
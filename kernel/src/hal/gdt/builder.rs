@@ -0,0 +1,64 @@
+use crate::hal::gdt::{entry::GdtEntry, descriptor::GdtDescriptor};
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// Assembles a GDT at runtime, one entry at a time. The default GDT (see
+/// Gdt::new) is built at compile time and used for normal boot: the builder
+/// is meant for experimentation or for layouts only known at runtime.
+#[allow(dead_code)]
+pub struct GdtBuilder {
+    /// Entries added so far; the first one is always the null entry.
+    entries: [GdtEntry; GdtBuilder::CAPACITY],
+    /// Number of valid entries, null entry included.
+    count: usize
+}
+
+/* ==== TYPE CONSTANTS ====================================================== */
+impl GdtBuilder {
+    /// Maximum number of entries, null entry included.
+    const CAPACITY: usize = 16;
+    /// Placeholder entry, also used as the null entry.
+    const NULL_ENTRY: GdtEntry = GdtEntry::new(0, 0, 0, 0);
+}
+
+/* ==== CONSTRUCTOR AND METHODS ============================================= */
+#[allow(dead_code)]
+impl GdtBuilder {
+    /// Initializes a builder only containing the null entry.
+    pub const fn new() -> Self {
+        Self { entries: [GdtBuilder::NULL_ENTRY; GdtBuilder::CAPACITY], count: 1 }
+    }
+
+    /// Appends a new entry (see GdtEntry::new) and returns its selector
+    /// offset: 8 for the first added entry, 16 for the second and so on.
+    /// The RPL bits are not set. Panics if the builder is full.
+    pub fn add_entry(&mut self, base: u32, limit: u32, access: u8, flags: u8) -> u16 {
+        if self.count >= GdtBuilder::CAPACITY { panic!("GDT builder is full!"); }
+        self.entries[self.count] = GdtEntry::new(base, limit, access, flags);
+        self.count += 1;
+        ((self.count - 1) * 8) as u16
+    }
+
+    /// Loads the built GDT and reloads the segment registers with the given
+    /// code and data selectors, which must have been returned by add_entry.
+    /// The CPU keeps reading the GDT after it's loaded (ex: when segment
+    /// registers are reloaded on iret), so the builder must live forever.
+    pub fn load(&'static self, code_selector: u16, data_selector: u16) {
+        let descriptor: GdtDescriptor = GdtDescriptor::new(&self.entries[..self.count]);
+        unsafe { super::_c_load_gdt(&descriptor, code_selector, data_selector); }
+    }
+}
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn add_entry_returns_consecutive_selectors() {
+        let mut builder: GdtBuilder = GdtBuilder::new();
+        assert!(builder.add_entry(0, 0xFFFFF, 0x9A, 0xC) == 8);
+        assert!(builder.add_entry(0, 0xFFFFF, 0x92, 0xC) == 16);
+        assert!(builder.add_entry(0, 0xFFFFF, 0xFA, 0xC) == 24);
+        assert!(builder.count == 4);
+    }
+}
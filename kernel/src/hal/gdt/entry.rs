@@ -58,6 +58,9 @@ impl GdtEntry {
     /** Data can be write to the data segment */
     pub const DATA_WRITE_ALLOWED: u8 = 0b000000_1_0;
 
+    /** System segment type of an available (not busy) 32 bit TSS */
+    pub const TSS_AVAILABLE_32BIT: u8 = 0b0000_1001;
+
     /** To be set used for read only segments, since CPU would try to set it 
         otherwise, causing a fault */
     pub const ACCESSED: u8 = 0b0000000_1;
@@ -1,7 +1,12 @@
 mod entry;
 mod descriptor;
+mod builder;
 use entry::GdtEntry;
 use self::descriptor::GdtDescriptor;
+use super::tss;
+
+#[allow(unused_imports)]
+pub use self::builder::GdtBuilder;
 
 /* ==== ASM EXTERN METHODS ================================================== */
 extern "C" {
@@ -12,7 +17,7 @@ extern "C" {
 /// Wrapper type used to better implement methods related to the GDT.
 pub struct Gdt {
     /// List of GDT entries that associate memory segments to flags and rules.
-    entries: [GdtEntry; 3]
+    entries: [GdtEntry; 6]
 }
 
 /* ==== CONSTRUCTOR AND METHODS ============================================= */
@@ -22,8 +27,15 @@ pub struct Gdt {
 impl Gdt {
     pub const CODE_SELECTOR: u16 = 1 * 8;
     pub const DATA_SELECTOR: u16 = 2 * 8;
+    /// User segments selectors, with the RPL (Requested Privilege Level,
+    /// bits 0-1) set to ring 3: to be used to far jump or iret to user mode.
+    #[allow(dead_code)] pub const USER_CODE_SELECTOR: u16 = (3 * 8) | 3;
+    #[allow(dead_code)] pub const USER_DATA_SELECTOR: u16 = (4 * 8) | 3;
+    pub const TSS_SELECTOR: u16 = 5 * 8;
 
-    /// Initialized GDT entries with 32pm code and data segments.
+    /// Initialized GDT entries with 32pm code and data segments, for both
+    /// the kernel (ring 0) and the user programs (ring 3).
+    /// The TSS entry is set on init: its address is not known at compile time.
     const fn new() -> Self {
         Self {
             entries: [
@@ -38,11 +50,30 @@ impl Gdt {
                 // 32pm data segment
                 GdtEntry::new(0, 0xFFFFFFFF,
                     or!(GdtEntry::VALID_SEGMENT, GdtEntry::PRIVILEGE_LEVEL_RING0, GdtEntry::DATA_SEGMENT, GdtEntry::DATA_WRITE_ALLOWED),
-                    or!(GdtEntry::FLAG_GRANULARITY, GdtEntry::FLAG_SEGMENT_32BIT))
+                    or!(GdtEntry::FLAG_GRANULARITY, GdtEntry::FLAG_SEGMENT_32BIT)),
+
+                // 32pm user code segment
+                GdtEntry::new(0, 0xFFFFFFFF,
+                    or!(GdtEntry::VALID_SEGMENT, GdtEntry::PRIVILEGE_LEVEL_RING3, GdtEntry::CODE_SEGMENT, GdtEntry::CODE_READ_ALLOWED),
+                    or!(GdtEntry::FLAG_GRANULARITY, GdtEntry::FLAG_SEGMENT_32BIT)),
+
+                // 32pm user data segment
+                GdtEntry::new(0, 0xFFFFFFFF,
+                    or!(GdtEntry::VALID_SEGMENT, GdtEntry::PRIVILEGE_LEVEL_RING3, GdtEntry::DATA_SEGMENT, GdtEntry::DATA_WRITE_ALLOWED),
+                    or!(GdtEntry::FLAG_GRANULARITY, GdtEntry::FLAG_SEGMENT_32BIT)),
+
+                // Task State Segment
+                GdtEntry::new(0, 0, 0, 0)
             ]
         }
     }
 
+    /// Describes the TSS at the given address, with byte granularity.
+    fn set_tss(&mut self, base: u32, size: u32) {
+        self.entries[(Gdt::TSS_SELECTOR / 8) as usize] = GdtEntry::new(base, size - 1,
+            or!(GdtEntry::VALID_SEGMENT, GdtEntry::PRIVILEGE_LEVEL_RING0, GdtEntry::TASK_SEGMENT, GdtEntry::TSS_AVAILABLE_32BIT), 0);
+    }
+
     /// Returns a descriptor that contains this GDT's size and address.
     fn get_descriptor(&self) -> GdtDescriptor {
         GdtDescriptor::new(&self.entries)
@@ -52,15 +83,20 @@ impl Gdt {
 /* ==== GDT DATA and INITIALIZATION ========================================= */
 /// Define new GDT entries - we already defined these in bootloader data, but
 /// that memory could be freed, so they are defined again in the kernel.
-///! Needs to be const, not static - why? Idk...
-const GDT: Gdt = Gdt::new();
+/// It's a static: the TSS entry is set on init, and the CPU writes the busy
+/// flag of the TSS entry when it's loaded, so the GDT can't be a temporary.
+static mut GDT: Gdt = Gdt::new();
 
 /// Defines and loads GDT Descriptor and sets segments for 32 bit protected
 /// flat memory model - the same already set in the stage-2, but free up the
 /// bootloader memory could be needed, so they are defined again.
 /// Other than that, we don't need the 16 bit real mode entries anymore.
+/// The TSS entry is also set, but it's only loaded later (see tss::load_tss).
 /// Descriptor doesn't need to be const: value is copied in the IDTR register.
 pub fn init() {
-    let descriptor: GdtDescriptor = GDT.get_descriptor();
+    let (tss_base, tss_size) = tss::tss_segment();
+    let gdt: &mut Gdt = unsafe { &mut *core::ptr::addr_of_mut!(GDT) };
+    gdt.set_tss(tss_base, tss_size);
+    let descriptor: GdtDescriptor = gdt.get_descriptor();
     unsafe { _c_load_gdt(&descriptor, Gdt::CODE_SELECTOR, Gdt::DATA_SELECTOR); }
 }
\ No newline at end of file
@@ -27,6 +27,15 @@ impl IdtEntry {
     }
 }
 
+/* ==== METHODS ============================================================= */
+impl IdtEntry {
+    /** Changes the privilege level required to call the routine with INT:
+     *  only ring 3 entries can be triggered by userland code. */
+    pub fn set_privilege_level(&mut self, ring: u8) {
+        self.flags = (self.flags & !IdtEntry::PRIVILEGE_LEVEL_RING3) | ring;
+    }
+}
+
 /* ==== IDT FLAGS =========================================================== */
 #[allow(dead_code)] impl IdtEntry {
     /** To be used for hardware multitasking. */
@@ -1,5 +1,123 @@
 use super::IsrStackFrame;
 
-pub fn handler(_data: *const IsrStackFrame) {
-    panic!("Division by zero!!");
-}
\ No newline at end of file
+/* ==== TYPE DEFINITION ===================================================== */
+/// What the Division Error handler does when a DIV/IDIV faults.
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum DividePolicy {
+    /// Halt the kernel - default, a division by zero is a bug.
+    Panic,
+    /// Set the quotient (EAX) to the given value and the remainder (EDX) to 0,
+    /// then resume execution from the instruction after the faulting one.
+    Continue(u32)
+}
+
+/// Policy applied by the handler, see [`set_divide_policy`].
+static mut DIVIDE_POLICY: DividePolicy = DividePolicy::Panic;
+
+/// Sets the policy applied to the following Division Errors.
+#[allow(dead_code)]
+pub fn set_divide_policy(policy: DividePolicy) {
+    unsafe { DIVIDE_POLICY = policy; }
+}
+
+/* ==== HANDLER ============================================================= */
+/// Division Error is a fault: the saved EIP points to the faulting DIV/IDIV,
+/// which would be executed again (faulting again) after IRET. To continue,
+/// the saved EIP is moved past the instruction, whose length is decoded.
+pub fn handler(data: *mut IsrStackFrame) {
+    let value: u32 = match unsafe { DIVIDE_POLICY } {
+        DividePolicy::Panic => panic!("Division by zero!!"),
+        DividePolicy::Continue(value) => value
+    };
+
+    let eip: u32 = unsafe { (*data).prev_eip };
+    let length: u32 = match instruction_length(eip as *const u8) {
+        Some(length) => length,
+        None => panic!("Division by zero!! (unknown instruction)")
+    };
+
+    unsafe {
+        (*data).pusha_eax = value;
+        (*data).pusha_edx = 0;
+        (*data).prev_eip = eip + length;
+    }
+}
+
+/// Returns the length of the DIV/IDIV instruction (F6/F7 /6 or /7) at the
+/// given address, including operand size and segment override prefixes.
+/// Returns None for other instructions or 16 bit addressing (0x67 prefix).
+fn instruction_length(eip: *const u8) -> Option<u32> {
+    let byte = |i: u32| unsafe { *eip.add(i as usize) };
+
+    // Skip prefixes: operand size and segment overrides
+    let mut length: u32 = 0;
+    while matches!(byte(length), 0x66 | 0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65) { length += 1; }
+
+    // Opcode: F6 (8 bit operand) or F7 (16/32 bit operand)
+    if !matches!(byte(length), 0xF6 | 0xF7) { return None; }
+    length += 1;
+
+    // ModRM: mod (7-6), reg (5-3, the opcode extension), rm (2-0)
+    let modrm: u8 = byte(length);
+    length += 1;
+    let (mode, reg, rm) = (modrm >> 6, (modrm >> 3) & 0b111, modrm & 0b111);
+    if reg != 6 && reg != 7 { return None; }
+
+    // SIB byte is present when rm is 100 with a memory operand; with mod 00,
+    // a SIB base of 101 means that a 32 bit displacement follows.
+    if mode != 0b11 && rm == 0b100 {
+        let sib: u8 = byte(length);
+        length += 1;
+        if mode == 0b00 && sib & 0b111 == 0b101 { length += 4; }
+    }
+
+    // Displacement: mod 01 is 8 bit, mod 10 (or 00 with rm 101) is 32 bit
+    length += match (mode, rm) {
+        (0b01, _) => 1,
+        (0b10, _) | (0b00, 0b101) => 4,
+        _ => 0
+    };
+    Some(length)
+}
+
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn continue_policy_resumes_after_the_div() {
+        set_divide_policy(DividePolicy::Continue(0xDEAD));
+        let (quotient, remainder): (u32, u32);
+        unsafe { core::arch::asm!("mov ecx, 0", "div ecx", inout("eax") 7 => quotient, inout("edx") 3 => remainder, out("ecx") _); }
+        set_divide_policy(DividePolicy::Panic);
+
+        assert!(quotient == 0xDEAD);
+        assert!(remainder == 0);
+    }
+
+    #[test_case]
+    fn instruction_length_decodes_register_operands() {
+        assert!(instruction_length([0xF7, 0xF1].as_ptr()) == Some(2));          // div ecx
+        assert!(instruction_length([0xF6, 0xF9].as_ptr()) == Some(2));          // idiv cl
+        assert!(instruction_length([0x66, 0xF7, 0xF1].as_ptr()) == Some(3));    // div cx
+    }
+
+    #[test_case]
+    fn instruction_length_decodes_memory_operands() {
+        assert!(instruction_length([0xF7, 0x75, 0x08].as_ptr()) == Some(3));                    // div [ebp+8]
+        assert!(instruction_length([0xF7, 0xB3, 0, 1, 0, 0].as_ptr()) == Some(6));              // div [ebx+0x100]
+        assert!(instruction_length([0xF7, 0x35, 0, 0x10, 0, 0].as_ptr()) == Some(6));           // div [0x1000]
+        assert!(instruction_length([0xF7, 0x3C, 0x24].as_ptr()) == Some(3));                    // idiv [esp]
+        assert!(instruction_length([0xF7, 0x34, 0x25, 0, 0x10, 0, 0].as_ptr()) == Some(7));     // div [0x1000] (SIB)
+        assert!(instruction_length([0x2E, 0xF7, 0x74, 0x24, 0x04].as_ptr()) == Some(5));        // div cs:[esp+4]
+    }
+
+    #[test_case]
+    fn instruction_length_rejects_other_instructions() {
+        assert!(instruction_length([0xF7, 0xE1].as_ptr()).is_none());   // mul ecx
+        assert!(instruction_length([0x90, 0x90].as_ptr()).is_none());   // nop
+    }
+}
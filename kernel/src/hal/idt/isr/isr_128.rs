@@ -0,0 +1,53 @@
+use super::IsrStackFrame;
+use crate::get_vga;
+
+/* ==== CALLING CONVENTION ================================================== */
+// Syscalls are requested with "int 0x80", the IDT entry has DPL 3 so that
+// ring 3 code can trigger it (other vectors cause a General Protection Fault).
+// - EAX: syscall number (index in SYSCALLS);
+// - EBX, ECX, EDX: first, second and third argument;
+// - EAX on return: result of the syscall, u32::MAX for unknown syscalls.
+// All the other registers are preserved.
+// Ex: write "Hi" - mov eax, 0; mov ebx, <ptr>; mov ecx, 2; int 0x80
+
+/// Syscall implementation interface: takes EBX, ECX, EDX, returns EAX.
+type Syscall = fn(u32, u32, u32) -> u32;
+
+/// Interrupt number used to request a syscall.
+pub const SYSCALL_INTERRUPT: u8 = 0x80;
+
+/// Value returned in EAX for unknown syscall numbers (-1).
+const SYSCALL_UNKNOWN: u32 = u32::MAX;
+
+/// Syscall implementations, indexed by syscall number.
+const SYSCALLS: [Syscall; 1] = [
+    write       // 0
+];
+
+/* ==== HANDLER ============================================================= */
+/// Reads the syscall number and the arguments from the saved registers and
+/// dispatches the call. The result is written to the saved EAX: popa in the
+/// ASM stub restores it to the caller.
+pub fn handler(data: *mut IsrStackFrame) {
+    let (number, ebx, ecx, edx) = unsafe { ((*data).pusha_eax, (*data).pusha_ebx, (*data).ecx, (*data).pusha_edx) };
+    let result: u32 = match SYSCALLS.get(number as usize) {
+        Some(syscall) => syscall(ebx, ecx, edx),
+        None => SYSCALL_UNKNOWN
+    };
+    unsafe { (*data).pusha_eax = result; }
+}
+
+/* ==== SYSCALLS ============================================================ */
+/// 0 - write(ptr, len): prints len bytes starting from ptr to the screen.
+/// Returns the number of printed bytes.
+/// ! There's no paging yet: the pointer is not validated.
+fn write(ptr: u32, len: u32, _: u32) -> u32 {
+    let s: &[u8] = unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) };
+
+    // The caller may be kernel code interrupted while printing
+    match get_vga().try_lock() {
+        Some(mut vga) => vga.print(s),
+        None => crate::vga::defer(s)
+    }
+    len
+}
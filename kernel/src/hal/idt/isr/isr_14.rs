@@ -0,0 +1,36 @@
+use super::IsrStackFrame;
+use crate::{get_vga, println_irq, ToString, prints::ToStringBase};
+
+/* ==== ERROR CODE BITS ===================================================== */
+/// Set if the page was present (protection violation), unset if not present.
+const ERROR_PRESENT: u32 = 1 << 0;
+/// Set if the access was a write, unset if it was a read.
+const ERROR_WRITE: u32 = 1 << 1;
+/// Set if the access was made in user mode (ring 3).
+const ERROR_USER: u32 = 1 << 2;
+/// Set if a reserved bit was set in a paging structure entry.
+const ERROR_RESERVED: u32 = 1 << 3;
+/// Set if the access was an instruction fetch.
+const ERROR_INSTRUCTION_FETCH: u32 = 1 << 4;
+
+/* ==== HANDLER ============================================================= */
+/// Page Fault is a fault: the saved EIP points to the faulting instruction,
+/// the CPU pushes an error code describing the access (the ISR stub for 14
+/// doesn't push the dummy one) and stores the accessed address in CR2.
+/// There's no way to recover yet (no swapping, no demand paging): the fault
+/// is described and the kernel halts.
+pub fn handler(data: *mut IsrStackFrame) {
+    let address: u32;
+    unsafe { core::arch::asm!("mov {}, cr2", out(reg) address); }
+    let error: u32 = unsafe { (*data).error };
+    let eip: u32 = unsafe { (*data).prev_eip };
+
+    let access: &str = if error & ERROR_INSTRUCTION_FETCH != 0 { "instruction fetch" } else if error & ERROR_WRITE != 0 { "write" } else { "read" };
+    let cause: &str = if error & ERROR_PRESENT != 0 { "protection violation" } else { "not-present" };
+    let mode: &str = if error & ERROR_USER != 0 { ", user" } else { "" };
+    let reserved: &str = if error & ERROR_RESERVED != 0 { ", reserved bit set" } else { "" };
+
+    println_irq!("Page fault at ", address.to_hex(), " (", access, ", ", cause, mode, reserved, ")");
+    println_irq!("Faulting instruction at ", eip.to_hex());
+    panic!("Page fault!");
+}
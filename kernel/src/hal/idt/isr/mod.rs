@@ -1,17 +1,25 @@
 mod isrs;
 mod isr_0;
+mod isr_14;
+mod isr_128;
 
-use crate::{get_vga, println, ToString};
+pub use self::isr_0::{DividePolicy, set_divide_policy};
+
+use crate::{get_vga, println, println_irq, ToString, prints::ToStringBase};
+use crate::hal::{interrupts, pic};
 
 /* ==== TYPE DEFINITION ===================================================== */
 /// The CPU and the ASM-defined dispatcher push to the stack some informations.
 /// The argument given to the Rust dispatcher is a pointer to the stack frame
 /// containing these informations, mapped here.
+/// The CPU only pushes an error code for the exceptions 8, 10-14, 17, 21, 29
+/// and 30: for all the other vectors (exceptions, IRQs, software interrupts)
+/// the ASM stub pushes a dummy 0, so that this layout is always valid.
 #[repr(C, packed)]
 pub struct IsrStackFrame {
     // Dispatcher pushed data
     pub ds: u32,
-    pub pusha_edi: u32, pusha_esi: u32, pusha_ebp: u32, pusha_esp: u32, pusha_ebx: u32, pusha_edx: u32, ecx: u32, pusha_eax: u32,
+    pub pusha_edi: u32, pusha_esi: u32, pusha_ebp: u32, pusha_esp: u32, pusha_ebx: u32, pub pusha_edx: u32, ecx: u32, pub pusha_eax: u32,
 
     // CPU pushed data
     pub interrupt: u32,
@@ -22,8 +30,63 @@ pub struct IsrStackFrame {
     pub prev_esp: u32, prev_ss: u32
 }
 
-/// ISR Handler interface
-pub type Isr = fn(*const IsrStackFrame) -> ();
+/// ISR Handler interface - the frame can be modified by the handler, the
+/// changes are restored to the registers when returning from the interrupt.
+pub type Isr = fn(*mut IsrStackFrame) -> ();
+
+/* ==== TYPE METHODS ======================================================== */
+impl IsrStackFrame {
+    /// Prints the state saved when the interrupt was received, in hex.
+    /// The fields are copied first: the struct is packed, references to
+    /// its fields could be unaligned.
+    pub fn print(&self) {
+        let (interrupt, error) = (self.interrupt, self.error);
+        let (eip, cs, eflags) = (self.prev_eip, self.prev_cs, self.prev_eflags);
+        let (eax, ebx, ecx, edx) = (self.pusha_eax, self.pusha_ebx, self.ecx, self.pusha_edx);
+        let (esi, edi, ebp, esp) = (self.pusha_esi, self.pusha_edi, self.pusha_ebp, self.pusha_esp);
+        let ds = self.ds;
+        println!("Interrupt ", interrupt, " - error code ", error.to_hex());
+        println!("EIP=", eip.to_hex(), " CS=", cs.to_hex(), " EFLAGS=", eflags.to_hex());
+        println!("EAX=", eax.to_hex(), " EBX=", ebx.to_hex(), " ECX=", ecx.to_hex(), " EDX=", edx.to_hex());
+        println!("ESI=", esi.to_hex(), " EDI=", edi.to_hex(), " EBP=", ebp.to_hex(), " ESP=", esp.to_hex());
+        println!("DS=", ds.to_hex());
+    }
+}
+
+/* ==== CPU EXCEPTIONS ====================================================== */
+/// Names of the CPU exceptions (interrupts 0-31), reserved ones included.
+const EXCEPTION_NAMES: [&str; 32] = [
+    "Divide Error", "Debug", "Non-Maskable Interrupt", "Breakpoint",
+    "Overflow", "Bound Range Exceeded", "Invalid Opcode", "Device Not Available",
+    "Double Fault", "Coprocessor Segment Overrun", "Invalid TSS", "Segment Not Present",
+    "Stack-Segment Fault", "General Protection Fault", "Page Fault", "Reserved",
+    "x87 Floating-Point Exception", "Alignment Check", "Machine Check", "SIMD Floating-Point Exception",
+    "Virtualization Exception", "Control Protection Exception", "Reserved", "Reserved",
+    "Reserved", "Reserved", "Reserved", "Reserved",
+    "Hypervisor Injection Exception", "VMM Communication Exception", "Security Exception", "Reserved"
+];
+
+/// Prints the name of the given CPU exception and its error code. The
+/// exceptions 10-13 push a segment selector error code, which is decoded:
+/// - bit 0: the exception was caused by an external event;
+/// - bits 1-2: descriptor table of the selector (0 GDT, 1/3 IDT, 2 LDT);
+/// - bits 3-15: index of the selector in the table.
+///
+/// 8 (Double Fault) and 17 (Alignment Check) always push 0, 14 (Page Fault)
+/// is decoded by its handler.
+fn print_exception(interrupt: u8, error: u32) {
+    println_irq!("Exception: ", EXCEPTION_NAMES[interrupt as usize], " (", interrupt, ")");
+
+    match interrupt {
+        10..=13 if error != 0 => {
+            let table: &str = match (error >> 1) & 0b11 { 0 => "GDT", 2 => "LDT", _ => "IDT" };
+            let external: &str = if error & 1 != 0 { " - external" } else { "" };
+            println_irq!("Error code: ", error, " - ", table, " selector ", (error >> 3) & 0x1FFF, external);
+        },
+        8 | 10..=14 | 17 | 21 | 29 | 30 => println_irq!("Error code: ", error),
+        _ => {}
+    }
+}
 
 /* ==== DISPATCHER ========================================================== */
 /// Extern method esposed to the linker and called by the assembly module that
@@ -31,11 +94,24 @@ pub type Isr = fn(*const IsrStackFrame) -> ();
 /// if there is no actual implementation (this method would throw an error).
 /// This method then dispatches the interrupt and calls the correct handler.
 /// Optionally, the actual handler can be directly linked to the _c_isr_<n>.
-#[no_mangle] pub extern "C" fn _rs_isr_dispatcher(data: *const IsrStackFrame) {
+/// For the remapped IRQs, the EOI is sent to the PIC after the handler
+/// returns: handlers must not send it themselves.
+#[no_mangle] pub extern "C" fn _rs_isr_dispatcher(data: *mut IsrStackFrame) {
     let interrupt =  unsafe { (*data).interrupt } as u8;
     let error =  unsafe { (*data).error };
-    println!("Interrupt received: ", interrupt);
-    println!("Error received: ", error);
+    let irq: Option<u8> = pic::vector_to_irq(interrupt);
+
+    // Keep track of the frame, so that a panic shows the interrupted state.
+    // Interrupts can nest: restore the previous frame on return.
+    let prev_frame: *const IsrStackFrame = unsafe { FAULT_FRAME };
+    unsafe { FAULT_FRAME = data; }
+
+    // IRQs (ex: timer ticks) and syscalls are frequent, only trace the others
+    if (interrupt as usize) < EXCEPTION_NAMES.len() {
+        print_exception(interrupt, error);
+    } else if irq.is_none() && interrupt != isr_128::SYSCALL_INTERRUPT {
+        println_irq!("Interrupt received: ", interrupt);
+    }
 
     // Try to retrieve handler for received interrupt
     let handler: *const Isr = unsafe { ISRS[interrupt as usize] };
@@ -47,12 +123,29 @@ pub type Isr = fn(*const IsrStackFrame) -> ();
 
     let handler: Isr = unsafe { core::mem::transmute(handler) };
     handler(data);
+
+    // Without the EOI, the PIC stops forwarding IRQs of the same or lower
+    // priority: the timer would only tick once.
+    if let Some(irq) = irq { pic::send_eoi(irq); }
+
+    unsafe { FAULT_FRAME = prev_frame; }
+}
+
+/// Frame of the interrupt being handled, null outside of the ISRs.
+static mut FAULT_FRAME: *const IsrStackFrame = core::ptr::null();
+
+/// Returns the frame of the interrupt being handled, if any: a panic raised
+/// by a handler can show the faulting EIP and CS instead of the panic site.
+#[cfg_attr(test, allow(dead_code))]
+pub fn fault_frame() -> Option<&'static IsrStackFrame> {
+    unsafe { FAULT_FRAME.as_ref() }
 }
 
 /* ==== ISRS DATA and INITIALIZATION ======================================== */
-/// Internal mapping between interrupts and handlers.
+/// Internal mapping between interrupts and handlers, to be changed through
+/// register_handler and unregister_handler.
 /// If the handler pointer for the received interrupt is null, panic.
-pub static mut ISRS: [*const Isr; 256] = unsafe { core::mem::zeroed() };
+static mut ISRS: [*const Isr; 256] = unsafe { core::mem::zeroed() };
 
 /// Initializes IDT with ASM method pointers and map specific handlers and
 /// their associated interrupts.
@@ -60,5 +153,80 @@ pub fn init(idt: &mut super::Idt) {
     isrs::init(idt);
 
     // Division by 0
-    unsafe { ISRS[0] = isr_0::handler as *const Isr; }
-}
\ No newline at end of file
+    register_handler(0, isr_0::handler);
+
+    // Page Fault
+    register_handler(14, isr_14::handler);
+
+    // Syscalls, can be requested from ring 3
+    idt.set_privilege_level(isr_128::SYSCALL_INTERRUPT, super::entry::IdtEntry::PRIVILEGE_LEVEL_RING3);
+    register_handler(isr_128::SYSCALL_INTERRUPT, isr_128::handler);
+}
+
+/* ==== HANDLERS REGISTRATION =============================================== */
+/// Associates the given handler to the given interrupt, replacing the
+/// previous one if any. The handler is called by the dispatcher with a
+/// pointer to the IsrStackFrame of the interrupt (*mut IsrStackFrame): the
+/// changes it makes to the frame are restored to the registers on return.
+/// Interrupts are disabled during the update (restoring the previous state
+/// at the end), so that the dispatcher never reads a half-written entry.
+pub fn register_handler(interrupt: u8, handler: Isr) {
+    set_handler(interrupt, handler as *const Isr);
+}
+
+/// Removes the handler associated to the given interrupt, if any: receiving
+/// the interrupt will cause a panic again, as for unhandled interrupts.
+#[allow(dead_code)]
+pub fn unregister_handler(interrupt: u8) {
+    set_handler(interrupt, core::ptr::null());
+}
+
+/// Writes the ISRS entry of the given interrupt with interrupts disabled.
+fn set_handler(interrupt: u8, handler: *const Isr) {
+    interrupts::without_interrupts(|| unsafe { ISRS[interrupt as usize] = handler; });
+}
+
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interrupt, error code and EIP found in the frame by record_frame.
+    static mut RECORDED: (u32, u32, u32) = (0, 0, 0);
+
+    /// Handler that stores the frame content. For the Division Error, the
+    /// saved EIP is moved past the faulting `div ecx` (2 bytes).
+    fn record_frame(data: *mut IsrStackFrame) {
+        unsafe {
+            RECORDED = ((*data).interrupt, (*data).error, (*data).prev_eip);
+            if (*data).interrupt == 0 { (*data).prev_eip += 2; }
+        }
+    }
+
+    #[test_case]
+    fn breakpoint_frame_points_after_the_int3() {
+        // Breakpoint is a trap: the saved EIP is the next instruction
+        register_handler(3, record_frame);
+        let after: u32;
+        unsafe { core::arch::asm!("int 3", "2:", "lea {0}, [2b]", out(reg) after); }
+        unregister_handler(3);
+
+        let (interrupt, error, eip) = unsafe { RECORDED };
+        assert!(interrupt == 3 && error == 0);
+        assert!(eip == after);
+    }
+
+    #[test_case]
+    fn divide_error_frame_points_at_the_div() {
+        // Division Error is a fault: the saved EIP is the faulting DIV
+        register_handler(0, record_frame);
+        let at: u32;
+        unsafe { core::arch::asm!("lea {0}, [2f]", "mov ecx, 0", "2:", "div ecx", out(reg) at, inout("eax") 1 => _, out("ecx") _, out("edx") _); }
+        register_handler(0, isr_0::handler);
+
+        let (interrupt, error, eip) = unsafe { RECORDED };
+        assert!(interrupt == 0 && error == 0);
+        assert!(eip == at);
+    }
+}
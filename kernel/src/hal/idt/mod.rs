@@ -26,7 +26,7 @@
 
 mod entry;
 mod descriptor;
-mod isr;
+pub mod isr;
 
 use core::mem::zeroed;
 
@@ -52,6 +52,11 @@ impl Idt {
         self.entries[interrupt as usize] = entry;
     }
 
+    /// Changes the privilege level required to trigger the given interrupt.
+    fn set_privilege_level(&mut self, interrupt: u8, ring: u8) {
+        self.entries[interrupt as usize].set_privilege_level(ring);
+    }
+
     /// Returns a descriptor that contains this IDT's size and address.
     fn get_descriptor(&self) -> IdtDescriptor {
         IdtDescriptor::new(&self.entries)
@@ -0,0 +1,48 @@
+// Maskable interrupts (IRQs) are only delivered when the IF flag (bit 9) of
+// EFLAGS is set. Critical sections shared with an interrupt handler (ex: the
+// ISRS table, the tick counter, a ring buffer) must run with IF cleared, or
+// the handler could observe a half-written value.
+// CPU exceptions and NMIs are not affected by the IF flag.
+
+/* ==== CONSTANTS =========================================================== */
+/// Interrupt enable flag of the EFLAGS register.
+const EFLAGS_IF: u32 = 1 << 9;
+
+/* ==== METHODS ============================================================= */
+/// Enables maskable interrupts (sti).
+pub fn enable() {
+    unsafe { core::arch::asm!("sti", options(nomem, nostack)); }
+}
+
+/// Disables maskable interrupts (cli).
+pub fn disable() {
+    unsafe { core::arch::asm!("cli", options(nomem, nostack)); }
+}
+
+/// Enables maskable interrupts and halts until the next one (sti; hlt).
+/// sti only takes effect after the following instruction: an interrupt that
+/// arrives in between wakes hlt up, instead of being served right before it
+/// and leaving the CPU halted until the next one.
+pub fn enable_and_halt() {
+    unsafe { core::arch::asm!("sti", "hlt", options(nomem, nostack)); }
+}
+
+/// Returns whether maskable interrupts are currently enabled.
+#[allow(dead_code)]
+pub fn are_enabled() -> bool {
+    let eflags: u32;
+    unsafe { core::arch::asm!("pushfd", "pop {}", out(reg) eflags, options(nomem, preserves_flags)); }
+    eflags & EFLAGS_IF != 0
+}
+
+/// Runs the given closure with interrupts disabled, then restores the
+/// previous state: nested calls don't enable interrupts too early, and calls
+/// made while interrupts are already disabled (ex: from a handler) leave
+/// them disabled.
+pub fn without_interrupts<F: FnOnce() -> R, R>(f: F) -> R {
+    let eflags: u32;
+    unsafe { core::arch::asm!("pushfd", "pop {}", "cli", out(reg) eflags); }
+    let result: R = f();
+    if eflags & EFLAGS_IF != 0 { enable(); }
+    result
+}
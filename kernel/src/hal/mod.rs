@@ -1,7 +1,15 @@
-mod gdt;
-mod idt;
+pub mod gdt;
+pub mod idt;
+pub mod interrupts;
+pub mod pic;
+pub mod pit;
+pub mod registers;
+pub mod tss;
 
 pub fn init() {
     gdt::init();
+    tss::load_tss();
     idt::init();
+    pic::init();
+    pit::init();
 }
\ No newline at end of file
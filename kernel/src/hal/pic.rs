@@ -0,0 +1,137 @@
+// The 8259 PIC (Programmable Interrupt Controller) forwards hardware IRQs to
+// the CPU as interrupts. PCs have two of them chained together: the slave PIC
+// handles IRQs 8-15 and is connected to the IRQ 2 line of the master PIC.
+//
+// By default the master PIC maps IRQs 0-7 to interrupts 0x08-0x0F, which are
+// reserved for CPU exceptions in protected mode (ex: a timer tick would look
+// like a Double Fault). Both PICs are remapped right after the exceptions:
+// IRQs 0-7 to 0x20-0x27, IRQs 8-15 to 0x28-0x2F.
+//
+// Once an IRQ has been handled, an EOI (End Of Interrupt) command must be sent
+// to the PIC, or it won't forward IRQs of the same or lower priority anymore.
+// IRQs from the slave PIC also pass through the master: both need the EOI.
+//
+// PIC anatomy: https://wiki.osdev.org/8259_PIC
+
+use crate::pmio::{io_wait, Port, PortMappedAddr};
+
+/* ==== PIC CONSTANTS ======================================================= */
+/// Interrupt number of the first IRQ of the master PIC (IRQ 0).
+pub const MASTER_OFFSET: u8 = 0x20;
+/// Interrupt number of the first IRQ of the slave PIC (IRQ 8).
+pub const SLAVE_OFFSET: u8 = 0x28;
+/// Number of IRQ lines handled by each PIC.
+const IRQS_PER_PIC: u8 = 8;
+/// IRQ line of the master PIC the slave PIC is connected to.
+const CASCADE_IRQ: u8 = 2;
+
+/// Initialization command, ICW4 will be sent (ICW1).
+const ICW1_INIT: u8 = 0x11;
+/// 8086/88 mode (ICW4).
+const ICW4_8086: u8 = 0x01;
+/// End Of Interrupt command.
+const COMMAND_EOI: u8 = 0x20;
+
+/* ==== PORTS =============================================================== */
+fn master_command() -> Port { unsafe { Port::new(PortMappedAddr::PicMasterCommand) } }
+fn master_data() -> Port { unsafe { Port::new(PortMappedAddr::PicMasterData) } }
+fn slave_command() -> Port { unsafe { Port::new(PortMappedAddr::PicSlaveCommand) } }
+fn slave_data() -> Port { unsafe { Port::new(PortMappedAddr::PicSlaveData) } }
+
+/* ==== INITIALIZATION ====================================================== */
+/// Remaps the master and slave PICs IRQs to MASTER_OFFSET and SLAVE_OFFSET.
+/// All IRQs are masked, except the cascade line: drivers unmask the IRQs
+/// they handle once their handler is registered.
+/// Each initialization word is followed by an io_wait: on fast CPUs, old
+/// PICs may not be done with a word when the next one is sent.
+pub fn init() {
+    // ICW1: start initialization sequence, the PICs wait for 3 more words
+    master_command().outb(ICW1_INIT); io_wait();
+    slave_command().outb(ICW1_INIT); io_wait();
+
+    // ICW2: interrupt number of the first IRQ
+    master_data().outb(MASTER_OFFSET); io_wait();
+    slave_data().outb(SLAVE_OFFSET); io_wait();
+
+    // ICW3: master has the slave on IRQ 2 (bitmask), slave identity is 2
+    master_data().outb(1 << CASCADE_IRQ); io_wait();
+    slave_data().outb(CASCADE_IRQ); io_wait();
+
+    // ICW4: 8086 mode
+    master_data().outb(ICW4_8086); io_wait();
+    slave_data().outb(ICW4_8086); io_wait();
+
+    // Mask all IRQs but the cascade one (1 = masked)
+    master_data().outb(!(1 << CASCADE_IRQ));
+    slave_data().outb(0xFF);
+}
+
+/* ==== PUBLIC METHODS ====================================================== */
+/// Returns the IRQ line associated with the given interrupt number, if the
+/// interrupt number is in the remapped IRQs range.
+pub fn vector_to_irq(vector: u8) -> Option<u8> {
+    if !(MASTER_OFFSET..SLAVE_OFFSET + IRQS_PER_PIC).contains(&vector) { return None; }
+    Some(vector - MASTER_OFFSET)
+}
+
+/// Sends the End Of Interrupt command for the given interrupt number (not the
+/// IRQ line), see send_eoi. Interrupt numbers outside the IRQs range are
+/// ignored.
+#[allow(dead_code)]
+pub fn eoi(vector: u8) {
+    if let Some(irq) = vector_to_irq(vector) { send_eoi(irq); }
+}
+
+/// Sends the End Of Interrupt command for the given IRQ line (0-15): IRQs
+/// 8-15 also need the EOI to be sent to the slave PIC.
+/// Called by the ISR dispatcher after the IRQ handler returns.
+pub fn send_eoi(irq: u8) {
+    let (master, slave): (bool, bool) = eoi_targets(irq);
+    if slave { slave_command().outb(COMMAND_EOI); }
+    if master { master_command().outb(COMMAND_EOI); }
+}
+
+/// Returns which PICs need the EOI for the given IRQ line, as (master,
+/// slave): the master always does, the slave only for its own IRQs (8-15).
+fn eoi_targets(irq: u8) -> (bool, bool) {
+    (true, irq >= IRQS_PER_PIC)
+}
+
+/// Enables the given IRQ line (0-15), so that the PIC forwards it.
+#[allow(dead_code)]
+pub fn unmask_irq(irq: u8) {
+    let (port, line) = if irq < IRQS_PER_PIC { (master_data(), irq) } else { (slave_data(), irq - IRQS_PER_PIC) };
+    port.outb(port.inb() & !(1 << line));
+}
+
+/// Disables the given IRQ line (0-15), so that the PIC ignores it.
+#[allow(dead_code)]
+pub fn mask_irq(irq: u8) {
+    let (port, line) = if irq < IRQS_PER_PIC { (master_data(), irq) } else { (slave_data(), irq - IRQS_PER_PIC) };
+    port.outb(port.inb() | (1 << line));
+}
+
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn master_irqs_send_the_eoi_to_the_master_only() {
+        assert!(vector_to_irq(MASTER_OFFSET).map(eoi_targets) == Some((true, false)));
+        assert!(vector_to_irq(MASTER_OFFSET + 7).map(eoi_targets) == Some((true, false)));
+    }
+
+    #[test_case]
+    fn slave_irqs_send_the_eoi_to_both_pics() {
+        assert!(vector_to_irq(SLAVE_OFFSET).map(eoi_targets) == Some((true, true)));
+        assert!(vector_to_irq(SLAVE_OFFSET + 4).map(eoi_targets) == Some((true, true)));
+    }
+
+    #[test_case]
+    fn vectors_outside_the_irqs_get_no_eoi() {
+        assert!(vector_to_irq(MASTER_OFFSET - 1).is_none());
+        assert!(vector_to_irq(SLAVE_OFFSET + IRQS_PER_PIC).is_none());
+    }
+}
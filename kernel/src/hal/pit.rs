@@ -0,0 +1,117 @@
+// The 8253/8254 PIT (Programmable Interval Timer) is an oscillator running at
+// 1.193182 MHz, whose frequency is divided by a 16 bit value (the divisor)
+// for each of its three channels. Channel 0 output is connected to the IRQ 0
+// line of the master PIC: each time the counter reaches 0, a tick is fired.
+//
+// The channel is programmed by sending a mode byte to the command port, then
+// the divisor to the channel data port (low byte first, then high byte).
+// A divisor of 0 means 65536: the BIOS default, about 18.2 ticks per second.
+//
+// PIT anatomy: https://wiki.osdev.org/Programmable_Interval_Timer
+
+use crate::pmio::{Port, PortMappedAddr};
+use super::{idt::isr::{self, IsrStackFrame}, interrupts, pic};
+
+/* ==== PIT CONSTANTS ======================================================= */
+/// Frequency of the PIT oscillator, in Hz.
+const BASE_FREQUENCY: u32 = 1_193_182;
+/// Frequency set by init: a tick every 10ms.
+const DEFAULT_FREQUENCY: u32 = 100;
+/// IRQ line the channel 0 is connected to.
+const TIMER_IRQ: u8 = 0;
+
+/// Channel 0, access mode low byte/high byte, mode 3 (square wave), binary.
+const COMMAND_CHANNEL_0_SQUARE_WAVE: u8 = 0x36;
+
+/* ==== PORTS =============================================================== */
+fn command() -> Port { unsafe { Port::new(PortMappedAddr::PitCommand) } }
+fn channel_0_data() -> Port { unsafe { Port::new(PortMappedAddr::PitChannel0Data) } }
+
+/* ==== TIMER STATE ========================================================= */
+/// Number of ticks since init. Never wraps in practice (u64).
+static mut TICKS: u64 = 0;
+/// Current channel 0 frequency, used to convert ticks to time.
+static mut FREQUENCY: u32 = DEFAULT_FREQUENCY;
+
+/* ==== INITIALIZATION ====================================================== */
+/// Sets the timer to DEFAULT_FREQUENCY, registers the tick counter handler
+/// and unmasks the timer IRQ. Ticks are counted once interrupts are enabled.
+pub fn init() {
+    set_frequency(DEFAULT_FREQUENCY);
+    isr::register_handler(pic::MASTER_OFFSET + TIMER_IRQ, handler);
+    pic::unmask_irq(TIMER_IRQ);
+}
+
+/// IRQ 0 handler: counts the ticks. The EOI is sent by the dispatcher.
+fn handler(_frame: *mut IsrStackFrame) {
+    unsafe { TICKS += 1; }
+}
+
+/* ==== PUBLIC METHODS ====================================================== */
+/// Programs channel 0 to fire the given number of ticks per second.
+/// The divisor is clamped to the 16 bit range: frequencies below ~19 Hz are
+/// rounded up to 18.2 Hz, frequencies above the base one to the base one.
+pub fn set_frequency(hz: u32) {
+    let divisor: u32 = divisor(hz);
+
+    // A divisor of 0x10000 is sent as 0, which the PIT reads as 65536
+    command().outb(COMMAND_CHANNEL_0_SQUARE_WAVE);
+    channel_0_data().outb(divisor as u8);
+    channel_0_data().outb((divisor >> 8) as u8);
+
+    unsafe { FREQUENCY = BASE_FREQUENCY / divisor; }
+}
+
+/// Returns the channel divisor for the given frequency, clamped to the range
+/// the PIT accepts (1 to 65536).
+fn divisor(hz: u32) -> u32 {
+    (BASE_FREQUENCY / core::cmp::max(hz, 1)).clamp(1, 0x10000)
+}
+
+/// Returns the number of ticks since init. The counter is 64 bit, so it's
+/// read with interrupts disabled (restoring the previous state at the end):
+/// a tick between the reads of the two halves would return a wrong value.
+pub fn ticks() -> u64 {
+    interrupts::without_interrupts(|| unsafe { TICKS })
+}
+
+/// Busy-waits for at least the given number of milliseconds, rounded up to
+/// the next tick. Interrupts must be enabled, or the ticks never increase.
+#[allow(dead_code)]
+pub fn sleep(ms: u32) {
+    let frequency: u64 = unsafe { FREQUENCY } as u64;
+    let target: u64 = ticks() + (ms as u64 * frequency).div_ceil(1000);
+    while ticks() < target { core::hint::spin_loop(); }
+}
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn divisor_divides_the_base_frequency() {
+        assert!(divisor(DEFAULT_FREQUENCY) == 11931);
+        assert!(divisor(1000) == 1193);
+    }
+
+    #[test_case]
+    fn divisor_is_clamped_to_the_pit_range() {
+        assert!(divisor(0) == 0x10000);
+        assert!(divisor(1) == 0x10000);
+        assert!(divisor(18) == 0x10000);
+        assert!(divisor(19) == 62799);
+        assert!(divisor(BASE_FREQUENCY) == 1);
+        assert!(divisor(u32::MAX) == 1);
+    }
+
+    #[test_case]
+    fn handler_counts_a_tick() {
+        // Interrupts disabled: the real timer can't tick in between
+        interrupts::without_interrupts(|| {
+            let before: u64 = ticks();
+            handler(core::ptr::null_mut());
+            assert!(ticks() == before + 1);
+        });
+    }
+}
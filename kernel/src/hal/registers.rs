@@ -0,0 +1,55 @@
+// Register dump for post-mortem debugging: the values are captured where
+// the dump is requested (ex: the panic handler), so they describe the state
+// of the caller, not of the code that caused the error. For CPU exceptions,
+// the state saved in the IsrStackFrame is more useful (see isr::fault_frame).
+
+use crate::{get_vga, println, ToString, prints::ToStringBase};
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// Snapshot of the general purpose, flags and segment registers.
+pub struct Registers {
+    pub eax: u32, pub ebx: u32, pub ecx: u32, pub edx: u32,
+    pub esi: u32, pub edi: u32, pub ebp: u32, pub esp: u32,
+    pub eflags: u32,
+    pub cs: u16, pub ds: u16, pub es: u16, pub fs: u16, pub gs: u16, pub ss: u16
+}
+
+/* ==== TYPE METHODS ======================================================== */
+impl Registers {
+    /// Reads the current value of the registers. EAX-EDX are read first, so
+    /// that they are not yet overwritten by the other reads.
+    #[inline(always)]
+    pub fn capture() -> Registers {
+        let (eax, ebx, ecx, edx): (u32, u32, u32, u32);
+        let (esi, edi, ebp, esp): (u32, u32, u32, u32);
+        let eflags: u32;
+        let (cs, ds, es, fs, gs, ss): (u16, u16, u16, u16, u16, u16);
+        unsafe {
+            core::arch::asm!("", out("eax") eax, out("ecx") ecx, out("edx") edx, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {}, ebx", out(reg) ebx, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {}, esi", out(reg) esi, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {}, edi", out(reg) edi, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {}, ebp", out(reg) ebp, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {}, esp", out(reg) esp, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("pushfd", "pop {}", out(reg) eflags, options(nomem, preserves_flags));
+            core::arch::asm!("mov {0:x}, cs", "mov {1:x}, ds", "mov {2:x}, es", out(reg) cs, out(reg) ds, out(reg) es, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {0:x}, fs", "mov {1:x}, gs", "mov {2:x}, ss", out(reg) fs, out(reg) gs, out(reg) ss, options(nomem, nostack, preserves_flags));
+        }
+        Registers { eax, ebx, ecx, edx, esi, edi, ebp, esp, eflags, cs, ds, es, fs, gs, ss }
+    }
+
+    /// Prints all the registers in hex, four per line.
+    pub fn print(&self) {
+        println!("EAX=", self.eax.to_hex(), " EBX=", self.ebx.to_hex(), " ECX=", self.ecx.to_hex(), " EDX=", self.edx.to_hex());
+        println!("ESI=", self.esi.to_hex(), " EDI=", self.edi.to_hex(), " EBP=", self.ebp.to_hex(), " ESP=", self.esp.to_hex());
+        println!("EFLAGS=", self.eflags.to_hex());
+        println!("CS=", self.cs.to_hex(), " DS=", self.ds.to_hex(), " ES=", self.es.to_hex(), " FS=", self.fs.to_hex(), " GS=", self.gs.to_hex(), " SS=", self.ss.to_hex());
+    }
+}
+
+/* ==== METHODS ============================================================= */
+/// Captures and prints the current register state.
+#[inline(always)]
+pub fn dump_registers() {
+    Registers::capture().print();
+}
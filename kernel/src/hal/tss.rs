@@ -0,0 +1,70 @@
+// The TSS (Task State Segment) was designed for hardware multitasking, which
+// is not used. In protected mode it is still required for ring transitions:
+// when an interrupt (ex: a syscall) is received while running ring 3 code,
+// the CPU switches to the ring 0 stack described by the SS0 and ESP0 fields.
+//
+// The TSS is described by an entry in the GDT, its selector is loaded in the
+// Task Register with the LTR instruction.
+//
+// TSS anatomy: https://wiki.osdev.org/Task_State_Segment
+
+use core::mem::size_of;
+use super::gdt::Gdt;
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// 32 bit TSS layout. Only the ring 0 stack fields and the I/O map base are
+/// used, the others are only meaningful for hardware task switching.
+#[repr(C, packed)]
+pub struct Tss {
+    prev_tss: u32,
+    esp0: u32, ss0: u32,    // Stack loaded when switching to ring 0
+    esp1: u32, ss1: u32,
+    esp2: u32, ss2: u32,
+    cr3: u32, eip: u32, eflags: u32,
+    eax: u32, ecx: u32, edx: u32, ebx: u32, esp: u32, ebp: u32, esi: u32, edi: u32,
+    es: u32, cs: u32, ss: u32, ds: u32, fs: u32, gs: u32,
+    ldt: u32,
+    trap: u16,
+    iomap_base: u16         // Offset of the I/O permission bitmap
+}
+
+/* ==== CONSTRUCTOR ========================================================= */
+impl Tss {
+    /// Initializes an empty TSS with the kernel data segment as ring 0 stack
+    /// segment. The I/O map base points past the end of the segment: there's
+    /// no I/O permission bitmap, ring 3 code can't access any port.
+    const fn new() -> Self {
+        Self {
+            prev_tss: 0,
+            esp0: 0, ss0: Gdt::DATA_SELECTOR as u32,
+            esp1: 0, ss1: 0, esp2: 0, ss2: 0,
+            cr3: 0, eip: 0, eflags: 0,
+            eax: 0, ecx: 0, edx: 0, ebx: 0, esp: 0, ebp: 0, esi: 0, edi: 0,
+            es: 0, cs: 0, ss: 0, ds: 0, fs: 0, gs: 0,
+            ldt: 0, trap: 0,
+            iomap_base: size_of::<Tss>() as u16
+        }
+    }
+}
+
+/* ==== TSS DATA and INITIALIZATION ========================================= */
+/// The only TSS, shared by all the tasks: ESP0 is updated on task switch.
+static mut TSS: Tss = Tss::new();
+
+/// Returns the address and the size of the TSS, to be described in the GDT.
+pub fn tss_segment() -> (u32, u32) {
+    (core::ptr::addr_of!(TSS) as u32, size_of::<Tss>() as u32)
+}
+
+/// Loads the TSS selector in the Task Register. The GDT containing the TSS
+/// entry must be already loaded; the CPU marks the entry as busy.
+pub fn load_tss() {
+    unsafe { core::arch::asm!("ltr ax", in("ax") Gdt::TSS_SELECTOR, options(nostack, preserves_flags)); }
+}
+
+/// Sets the stack the CPU switches to when an interrupt is received while
+/// running ring 3 code. It must be set before entering ring 3.
+#[allow(dead_code)]
+pub fn set_kernel_stack(esp: u32) {
+    unsafe { (*core::ptr::addr_of_mut!(TSS)).esp0 = esp; }
+}
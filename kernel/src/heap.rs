@@ -0,0 +1,140 @@
+// Kernel heap: a fixed virtual memory region, backed by frames taken from
+// the frame allocator, managed by a free list allocator. Registered as the
+// global allocator, it makes the alloc crate (Box, Vec, String) usable.
+//
+// The free blocks are kept sorted by address in a linked list, each block
+// stores its size and the next block in its first bytes. Allocations take
+// the first block big enough (first fit), freed blocks are merged with the
+// adjacent free ones. At first, the list is a single block: the whole heap.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::mem::{align_of, size_of};
+use crate::{get_vga, println, ToString, prints::ToStringBase};
+use crate::hal::interrupts::without_interrupts;
+use crate::memory::{self, FRAME_SIZE};
+use crate::paging::{self, PAGE_WRITABLE};
+
+/* ==== CONSTANTS =========================================================== */
+/// Virtual address of the heap start, out of the identity mapped memory.
+const HEAP_START: u32 = 0xD000_0000;
+/// Size in bytes of the heap, mapped on init.
+const HEAP_SIZE: u32 = 1024 * 1024;
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// Header of a free block, stored in the block itself.
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock
+}
+
+/// Free list allocator. The list head is a dummy block of size 0.
+struct Heap {
+    head: FreeBlock
+}
+
+/// Heap wrapper shared as global allocator: the list is only changed with
+/// interrupts disabled, so that a handler can't see it half updated.
+pub struct KernelHeap {
+    heap: UnsafeCell<Heap>
+}
+
+// There's a single CPU and the heap is only accessed with interrupts off.
+unsafe impl Sync for KernelHeap {}
+
+/* ==== HEAP METHODS ======================================================== */
+impl Heap {
+    /// Returns the size and alignment actually used for the given layout:
+    /// each block must be able to store a FreeBlock once it's freed.
+    fn block_layout(layout: &Layout) -> (usize, usize) {
+        let align: usize = core::cmp::max(layout.align(), align_of::<FreeBlock>());
+        let size: usize = core::cmp::max(layout.size(), size_of::<FreeBlock>());
+        let size: usize = (size + align_of::<FreeBlock>() - 1) & !(align_of::<FreeBlock>() - 1);
+        (size, align)
+    }
+
+    /// Adds the given memory region to the free list, keeping it sorted by
+    /// address and merging it with the adjacent free blocks.
+    /// Regions smaller than a FreeBlock are lost.
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        if size < size_of::<FreeBlock>() { return; }
+
+        // Find the last block placed before the region
+        let mut prev: *mut FreeBlock = &mut self.head;
+        while !(*prev).next.is_null() && ((*prev).next as usize) < addr {
+            prev = (*prev).next;
+        }
+
+        let block: *mut FreeBlock = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next: (*prev).next });
+        (*prev).next = block;
+
+        // Merge with the next block, then with the previous one
+        let next: *mut FreeBlock = (*block).next;
+        if !next.is_null() && addr + (*block).size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+        if !core::ptr::eq(prev, &self.head) && prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        }
+    }
+
+    /// Takes the first free block that can hold the given size with the
+    /// given alignment. The unused space before and after the allocation is
+    /// given back to the free list.
+    unsafe fn allocate(&mut self, size: usize, align: usize) -> *mut u8 {
+        let mut prev: *mut FreeBlock = &mut self.head;
+        while !(*prev).next.is_null() {
+            let block: *mut FreeBlock = (*prev).next;
+            let block_start: usize = block as usize;
+            let block_end: usize = block_start + (*block).size;
+
+            let start: usize = (block_start + align - 1) & !(align - 1);
+            let end: usize = start + size;
+            if end > block_end { prev = block; continue; }
+
+            // Remove the block, then free the unused parts
+            (*prev).next = (*block).next;
+            self.add_free_region(block_start, start - block_start);
+            self.add_free_region(end, block_end - end);
+            return start as *mut u8;
+        }
+        core::ptr::null_mut()
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    /// Returns null if there's no free block big enough, or if the heap is
+    /// not initialized yet.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = Heap::block_layout(&layout);
+        without_interrupts(|| (*self.heap.get()).allocate(size, align))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Heap::block_layout(&layout);
+        without_interrupts(|| (*self.heap.get()).add_free_region(ptr as usize, size));
+    }
+}
+
+/* ==== HEAP DATA and INITIALIZATION ======================================== */
+/// Global allocator used by the alloc crate. The free list is empty until
+/// init is called: allocations fail (and panic) before memory init.
+#[global_allocator]
+static HEAP: KernelHeap = KernelHeap {
+    heap: UnsafeCell::new(Heap { head: FreeBlock { size: 0, next: core::ptr::null_mut() } })
+};
+
+/// Maps the heap region to newly allocated frames and adds it to the free
+/// list. The frame allocator and paging must be initialized.
+pub fn init() {
+    for page in (HEAP_START..HEAP_START + HEAP_SIZE).step_by(FRAME_SIZE as usize) {
+        let frame: u32 = match memory::alloc_frame() { Some(f) => f, None => panic!("Out of memory!") };
+        paging::map_page(page, frame, PAGE_WRITABLE);
+    }
+
+    without_interrupts(|| unsafe { (*HEAP.heap.get()).add_free_region(HEAP_START as usize, HEAP_SIZE as usize) });
+    println!("Heap: ", HEAP_SIZE / 1024, " KiB at ", HEAP_START.to_hex());
+}
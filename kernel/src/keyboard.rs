@@ -0,0 +1,398 @@
+use crate::pmio::Port;
+use crate::pmio::PortMappedAddr;
+use crate::hal::{interrupts, pic, idt::isr::{self, IsrStackFrame}};
+
+/* ==== STATIC INITIALIZATION =============================================== */
+/** Crate static keyboard instance, only accessed through module functions.
+ *! Using the keyboard is unsafe since it requires static mutable access. */
+static mut KEYBOARD: Keyboard = Keyboard::new();
+
+/** Returns mutable reference to static keyboard instance. */
+fn get_keyboard() -> &'static mut Keyboard { unsafe { &mut *core::ptr::addr_of_mut!(KEYBOARD) } }
+
+/* ==== TYPE DEFINITION ===================================================== */
+struct Keyboard {
+    /** PS/2 port used to send device commands and read their responses. */
+    data_port: Port<u8>,
+    /** PS/2 port used to read the controller state. */
+    status_port: Port<u8>,
+
+    /** Lock keys state, mirrored on the keyboard LEDs. */
+    caps_lock: bool,
+    num_lock: bool,
+    scroll_lock: bool,
+    /** Set by the IRQ handler when a lock key is toggled: the LEDs are
+     *  updated later, outside of the handler (see update_pending_leds). */
+    leds_pending: bool,
+
+    /** Shift keys state, set while they are held down. */
+    left_shift: bool,
+    right_shift: bool,
+
+    /** Ring buffer of the characters typed and not read yet. The IRQ handler
+     *  only moves the head and read_char only the tail, so they don't need
+     *  to be synchronized. One slot is left empty to tell full from empty. */
+    buffer: [u8; Keyboard::BUFFER_SIZE],
+    head: usize,
+    tail: usize,
+
+    /** Sends a byte to the device and returns its response, if any: through
+     *  the PS/2 ports, or a stand-in without a controller (see the tests). */
+    exchange: fn(&Keyboard, u8) -> Option<u8>
+}
+
+/* ==== SCANCODE TABLES ===================================================== */
+/** ASCII characters of the Set 1 make codes, up to the space bar (0x39).
+ *  Keys without a character (Ctrl, Shift, Alt...) are 0. */
+const SCANCODE_ASCII: [u8; 0x3A] = [
+    0, 0x1B, b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'0', b'-', b'=', 0x08, b'\t',
+    b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', b'o', b'p', b'[', b']', b'\n', 0, b'a', b's',
+    b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', b'\'', b'`', 0, b'\\', b'z', b'x', b'c', b'v',
+    b'b', b'n', b'm', b',', b'.', b'/', 0, b'*', 0, b' '
+];
+
+/** ASCII characters of the Set 1 make codes while Shift is held down. */
+const SCANCODE_ASCII_SHIFT: [u8; 0x3A] = [
+    0, 0x1B, b'!', b'@', b'#', b'$', b'%', b'^', b'&', b'*', b'(', b')', b'_', b'+', 0x08, b'\t',
+    b'Q', b'W', b'E', b'R', b'T', b'Y', b'U', b'I', b'O', b'P', b'{', b'}', b'\n', 0, b'A', b'S',
+    b'D', b'F', b'G', b'H', b'J', b'K', b'L', b':', b'"', b'~', 0, b'|', b'Z', b'X', b'C', b'V',
+    b'B', b'N', b'M', b'<', b'>', b'?', 0, b'*', 0, b' '
+];
+
+/* ==== TYPE CONSTANTS ====================================================== */
+impl Keyboard {
+    /** Device command: the following byte sent is the LEDs bitmask. */
+    const COMMAND_SET_LEDS: u8 = 0xED;
+    /** Device response: the previous byte has been received. */
+    const RESPONSE_ACK: u8 = 0xFA;
+    /** Device response: the previous byte must be sent again. */
+    const RESPONSE_RESEND: u8 = 0xFE;
+
+    /** Status register bit set if the controller output buffer is full. */
+    const STATUS_OUTPUT_FULL: u8 = 0b01;
+    /** Status register bit set if the controller input buffer is full. */
+    const STATUS_INPUT_FULL: u8 = 0b10;
+
+    /** LEDs bitmask values for the set LEDs command. */
+    const LED_SCROLL_LOCK: u8 = 0b001;
+    const LED_NUM_LOCK: u8 = 0b010;
+    const LED_CAPS_LOCK: u8 = 0b100;
+
+    /** Scancode bit set for break codes (key released). */
+    const SCANCODE_BREAK: u8 = 0x80;
+
+    /** Make codes (Set 1) of the shift keys. */
+    const SCANCODE_LEFT_SHIFT: u8 = 0x2A;
+    const SCANCODE_RIGHT_SHIFT: u8 = 0x36;
+
+    /** Make codes (Set 1) of the lock keys. */
+    const SCANCODE_CAPS_LOCK: u8 = 0x3A;
+    const SCANCODE_NUM_LOCK: u8 = 0x45;
+    const SCANCODE_SCROLL_LOCK: u8 = 0x46;
+
+    /** Maximum number of status polls before giving up on the controller.
+     *  Waiting forever would hang the kernel if the controller misbehaves. */
+    const MAX_POLLS: u32 = 100_000;
+    /** Maximum number of times a byte is sent if the device asks to resend. */
+    const MAX_RESENDS: u8 = 3;
+
+    /** IRQ line the keyboard is connected to. */
+    const IRQ: u8 = 1;
+    /** Size of the typed characters ring buffer. */
+    const BUFFER_SIZE: usize = 64;
+}
+
+/* ==== STATIC TYPE METHODS ================================================= */
+impl Keyboard {
+    /** Declare constructor as 'const' in order to declare static instances. */
+    const fn new() -> Keyboard {
+        Keyboard {
+            data_port: unsafe { Port::<u8>::new(PortMappedAddr::Ps2Data) },
+            status_port: unsafe { Port::<u8>::new(PortMappedAddr::Ps2StatusCommand) },
+            caps_lock: false,
+            num_lock: false,
+            scroll_lock: false,
+            leds_pending: false,
+            left_shift: false,
+            right_shift: false,
+            buffer: [0; Keyboard::BUFFER_SIZE],
+            head: 0,
+            tail: 0,
+            exchange: Keyboard::port_exchange
+        }
+    }
+}
+
+/* ==== PRIVATE TYPE METHODS ================================================ */
+impl Keyboard {
+    /** Waits until the controller input buffer can be written.
+     *  Returns false if the buffer is still full after MAX_POLLS reads. */
+    fn wait_input_empty(&self) -> bool {
+        for _ in 0..Keyboard::MAX_POLLS {
+            if self.status_port.read() & Keyboard::STATUS_INPUT_FULL == 0 { return true; }
+        }
+        false
+    }
+
+    /** Waits until the controller output buffer contains a byte and reads it.
+     *  Returns None if nothing is received after MAX_POLLS reads. */
+    fn read_response(&self) -> Option<u8> {
+        for _ in 0..Keyboard::MAX_POLLS {
+            if self.status_port.read() & Keyboard::STATUS_OUTPUT_FULL != 0 {
+                return Some(self.data_port.read());
+            }
+        }
+        None
+    }
+
+    /** Writes a byte to the PS/2 data port and waits for the response.
+     *  Returns None if the controller doesn't accept the byte or respond. */
+    fn port_exchange(&self, value: u8) -> Option<u8> {
+        if !self.wait_input_empty() { return None; }
+        self.data_port.write(value);
+        self.read_response()
+    }
+
+    /** Sends a byte to the keyboard and waits for its ACK, sending it again
+     *  if the device requests it. Returns false if no ACK is received. */
+    fn send(&self, value: u8) -> bool {
+        for _ in 0..Keyboard::MAX_RESENDS {
+            match (self.exchange)(self, value) {
+                Some(Keyboard::RESPONSE_ACK) => return true,
+                Some(Keyboard::RESPONSE_RESEND) => continue,
+                _ => return false
+            }
+        }
+        false
+    }
+
+    /** Translates a make code to ASCII, using the Shift and Caps Lock state
+     *  (Caps Lock only affects letters). Returns None for keys without a
+     *  character. */
+    fn translate(&self, scancode: u8) -> Option<u8> {
+        let unshifted: u8 = *SCANCODE_ASCII.get(scancode as usize)?;
+        let mut shift: bool = self.left_shift || self.right_shift;
+        if unshifted.is_ascii_lowercase() && self.caps_lock { shift = !shift; }
+
+        let ascii: u8 = if shift { SCANCODE_ASCII_SHIFT[scancode as usize] } else { unshifted };
+        if ascii == 0 { return None; }
+        Some(ascii)
+    }
+
+    /** Adds a character to the ring buffer; if it's full, it's discarded. */
+    fn push(&mut self, ascii: u8) {
+        let next: usize = (self.head + 1) % Keyboard::BUFFER_SIZE;
+        if next == self.tail { return; }
+        self.buffer[self.head] = ascii;
+        self.head = next;
+    }
+
+    /** Returns the bitmask of the set LEDs command for the given lock
+     *  keys state: each LED is on if its lock is active. */
+    const fn led_mask(caps: bool, num: bool, scroll: bool) -> u8 {
+        let mut mask: u8 = 0;
+        if caps { mask |= Keyboard::LED_CAPS_LOCK; }
+        if num { mask |= Keyboard::LED_NUM_LOCK; }
+        if scroll { mask |= Keyboard::LED_SCROLL_LOCK; }
+        mask
+    }
+
+    /** Sends the set LEDs command followed by the current lock keys state,
+     *  if the IRQ handler has toggled a lock key since the last update. */
+    fn update_pending_leds(&mut self) {
+        if !self.leds_pending { return; }
+        self.leds_pending = false;
+        set_leds(self.caps_lock, self.num_lock, self.scroll_lock);
+    }
+}
+
+/* ==== PUBLIC METHODS ====================================================== */
+/** Turns the Caps/Num/Scroll Lock LEDs on or off by sending the 0xED command
+ *  and the LEDs bitmask to the keyboard, waiting for the ACK of both.
+ *  Returns false if the controller doesn't respond or doesn't acknowledge.
+ *  Interrupts are disabled meanwhile, so that the keyboard IRQ handler
+ *  doesn't consume the ACK bytes.
+ *! Busy-waits for the ACKs: never call from interrupt handlers. */
+pub fn set_leds(caps: bool, num: bool, scroll: bool) -> bool {
+    let mask: u8 = Keyboard::led_mask(caps, num, scroll);
+    let keyboard: &Keyboard = get_keyboard();
+    interrupts::without_interrupts(|| keyboard.send(Keyboard::COMMAND_SET_LEDS) && keyboard.send(mask))
+}
+
+/** Handles a scancode received from the keyboard: if it is the make code of
+ *  a lock key, the lock state is toggled. The LEDs are updated by the next
+ *  read_char call, since waiting for the keyboard ACKs in the IRQ handler
+ *  would keep the interrupts disabled for too long. */
+pub fn handle_scancode(scancode: u8) {
+    let keyboard: &mut Keyboard = get_keyboard();
+    match scancode {
+        Keyboard::SCANCODE_CAPS_LOCK => keyboard.caps_lock = !keyboard.caps_lock,
+        Keyboard::SCANCODE_NUM_LOCK => keyboard.num_lock = !keyboard.num_lock,
+        Keyboard::SCANCODE_SCROLL_LOCK => keyboard.scroll_lock = !keyboard.scroll_lock,
+        _ => return
+    }
+    keyboard.leds_pending = true;
+}
+
+/** Registers the IRQ 1 handler and unmasks the keyboard IRQ: from now on,
+ *  typed characters are collected and can be read with read_char. */
+pub fn init() {
+    isr::register_handler(pic::MASTER_OFFSET + Keyboard::IRQ, irq_handler);
+    pic::unmask_irq(Keyboard::IRQ);
+}
+
+/** IRQ 1 handler: reads the scancode, updates the modifiers state and adds
+ *  the typed character, if any, to the buffer. The EOI is sent by the
+ *  dispatcher. */
+fn irq_handler(_frame: *mut IsrStackFrame) {
+    let keyboard: &mut Keyboard = get_keyboard();
+    let scancode: u8 = keyboard.data_port.read();
+
+    // Responses to the LED commands are not scancodes: set_leds polls for
+    // them, the IRQ raised meanwhile only finds them (or a stale copy) here.
+    if scancode == Keyboard::RESPONSE_ACK || scancode == Keyboard::RESPONSE_RESEND { return; }
+
+    // Shift keys are tracked on both make and break codes
+    let pressed: bool = scancode & Keyboard::SCANCODE_BREAK == 0;
+    match scancode & !Keyboard::SCANCODE_BREAK {
+        Keyboard::SCANCODE_LEFT_SHIFT => keyboard.left_shift = pressed,
+        Keyboard::SCANCODE_RIGHT_SHIFT => keyboard.right_shift = pressed,
+        _ if pressed => {
+            handle_scancode(scancode);
+            if let Some(ascii) = keyboard.translate(scancode) { keyboard.push(ascii); }
+        },
+        _ => {}
+    }
+}
+
+/** Returns the oldest typed character not read yet, if any. The LEDs update
+ *  requested by the IRQ handler, if any, is sent first. */
+pub fn read_char() -> Option<u8> {
+    let keyboard: &mut Keyboard = get_keyboard();
+    keyboard.update_pending_leds();
+    if keyboard.tail == keyboard.head { return None; }
+    let ascii: u8 = keyboard.buffer[keyboard.tail];
+    keyboard.tail = (keyboard.tail + 1) % Keyboard::BUFFER_SIZE;
+    Some(ascii)
+}
+
+/** Waits until a key with a character is pressed and returns it, halting
+ *  the CPU between the checks: the characters are collected by the IRQ
+ *  handler. Interrupts are enabled while waiting and left enabled. Keys
+ *  without a character (Shift, lock keys...) don't end the wait. */
+pub fn wait_keypress() -> u8 {
+    loop {
+        // Check with interrupts disabled: a key typed between the check and
+        // the halt wakes it up (see enable_and_halt).
+        interrupts::disable();
+        if let Some(ascii) = read_char() {
+            interrupts::enable();
+            return ascii;
+        }
+        interrupts::enable_and_halt();
+    }
+}
+
+/** Returns whether Caps Lock is currently active. */
+#[allow(dead_code)]
+pub fn is_caps_lock() -> bool { get_keyboard().caps_lock }
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn led_mask_sets_a_bit_for_each_active_lock() {
+        assert!(Keyboard::led_mask(false, false, false) == 0b000);
+        assert!(Keyboard::led_mask(false, false, true) == 0b001);
+        assert!(Keyboard::led_mask(false, true, false) == 0b010);
+        assert!(Keyboard::led_mask(false, true, true) == 0b011);
+        assert!(Keyboard::led_mask(true, false, false) == 0b100);
+        assert!(Keyboard::led_mask(true, false, true) == 0b101);
+        assert!(Keyboard::led_mask(true, true, false) == 0b110);
+        assert!(Keyboard::led_mask(true, true, true) == 0b111);
+    }
+
+    /** Bytes sent through fake_exchange, resends it asks for before giving
+     *  RESPONSE, and RESPONSE itself (None for a silent controller). */
+    static mut SENT: [u8; 8] = [0; 8];
+    static mut SENT_COUNT: usize = 0;
+    static mut RESENDS: usize = 0;
+    static mut RESPONSE: Option<u8> = None;
+
+    /** Stand-in for the PS/2 ports: records the byte and responds. */
+    fn fake_exchange(_keyboard: &Keyboard, value: u8) -> Option<u8> {
+        unsafe {
+            SENT[SENT_COUNT] = value;
+            SENT_COUNT += 1;
+            if RESENDS > 0 {
+                RESENDS -= 1;
+                return Some(Keyboard::RESPONSE_RESEND);
+            }
+            RESPONSE
+        }
+    }
+
+    /** Makes the keyboard exchange bytes with fake_exchange, from scratch. */
+    fn use_fake_exchange(resends: usize, response: Option<u8>) {
+        unsafe {
+            SENT_COUNT = 0;
+            RESENDS = resends;
+            RESPONSE = response;
+        }
+        get_keyboard().exchange = fake_exchange;
+    }
+
+    /** Returns the bytes sent to fake_exchange so far. */
+    fn sent() -> ([u8; 8], usize) { unsafe { (SENT, SENT_COUNT) } }
+
+    /** Restores the PS/2 ports and the default lock keys state. */
+    fn restore_keyboard() {
+        let keyboard: &mut Keyboard = get_keyboard();
+        keyboard.exchange = Keyboard::port_exchange;
+        keyboard.caps_lock = false;
+        keyboard.leds_pending = false;
+    }
+
+    #[test_case]
+    fn caps_toggle_sends_the_led_command_and_mask() {
+        use_fake_exchange(0, Some(Keyboard::RESPONSE_ACK));
+
+        // The LEDs are updated by the next read, outside of the IRQ handler
+        handle_scancode(Keyboard::SCANCODE_CAPS_LOCK);
+        assert!(sent().1 == 0);
+        read_char();
+        let (bytes, count) = sent();
+        assert!(bytes[..count] == [Keyboard::COMMAND_SET_LEDS, Keyboard::LED_CAPS_LOCK]);
+
+        // Toggled again: the LED is turned off
+        handle_scancode(Keyboard::SCANCODE_CAPS_LOCK);
+        read_char();
+        let (bytes, count) = sent();
+        assert!(bytes[2..count] == [Keyboard::COMMAND_SET_LEDS, 0]);
+        restore_keyboard();
+    }
+
+    #[test_case]
+    fn set_leds_sends_a_byte_again_on_resend() {
+        use_fake_exchange(1, Some(Keyboard::RESPONSE_ACK));
+        assert!(set_leds(true, false, false));
+        let (bytes, count) = sent();
+        assert!(bytes[..count] == [Keyboard::COMMAND_SET_LEDS, Keyboard::COMMAND_SET_LEDS, Keyboard::LED_CAPS_LOCK]);
+        restore_keyboard();
+    }
+
+    #[test_case]
+    fn set_leds_gives_up_without_an_ack() {
+        // A silent controller, then one that keeps asking to resend
+        use_fake_exchange(0, None);
+        assert!(!set_leds(true, false, false));
+        assert!(sent().1 == 1);
+
+        use_fake_exchange(usize::MAX, None);
+        assert!(!set_leds(true, false, false));
+        assert!(sent().1 == Keyboard::MAX_RESENDS as usize);
+        restore_keyboard();
+    }
+}
@@ -3,8 +3,13 @@
 #![no_main]
 #![feature(panic_info_message)]
 #![recursion_limit = "256"]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 /* ==== MODULES ============================================================= */
+extern crate alloc;
+
 use core::panic::PanicInfo;
 use vga::get_vga;
 use prints::ToString;
@@ -13,36 +18,128 @@ mod vga;    // Use VGA module
 mod pmio;   // Make PMIO module visible to VGA module
 mod prints;
 mod hal;
+mod keyboard;
+mod serial;
+mod memory;
+mod paging;
+mod heap;
+#[cfg(any(test, debug_assertions))]
+mod qemu;
+#[cfg(test)]
+mod testing;
 
 /* ==== ENTRY POINT ========================================================= */
-#[no_mangle] pub extern "C" fn _rs_start() -> ! {
+// Called by stage-2, which never returns: the memory map lives in its stack
+// frame (in the reserved first MB) for the whole kernel lifetime.
+#[no_mangle] pub extern "C" fn _rs_start(memory_map: &'static memory::MemoryMap) -> ! {
 
     // Clear text and cursor from stage-2
     get_vga().clear_screen();
 
+    // Log to the serial port too, it can be captured by the emulator
+    serial::init();
+    serial_println!("Kernel starting..!");
+
     // Load kernel GDT and IDT
     hal::init();
+    keyboard::init();
+
+    // Track the free physical memory, the map is read from the stage-2 stack
+    memory::init(memory_map);
+
+    // Identity map the first MBs and enable paging
+    paging::init();
+
+    // The heap is backed by paged memory: alloc is only usable from here on
+    heap::init();
+
+    // Test builds only run the tests, QEMU is closed at the end
+    #[cfg(test)]
+    test_main();
+
+    // Keep a status line at the bottom, the log scrolls above it
+    get_vga().set_scroll_region(0, 23);
+    get_vga().print_at(0, 24, b" Cotezzo OS - kernel running", 0x70);
 
     // TODO: something...
     println!("Kernel starting..!");
     
     //unsafe { core::arch::asm!( "int 63" ); }
     //unsafe { core::arch::asm!( "int 201" ); }
-    unsafe { core::arch::asm!( "mov ecx, 0", "div ecx" ); }
+    // Trap the division by zero and resume with a sentinel quotient
+    hal::idt::isr::set_divide_policy(hal::idt::isr::DividePolicy::Continue(0xFFFF_FFFF));
+    let quotient: u32;
+    unsafe { core::arch::asm!( "mov ecx, 0", "div ecx", inout("eax") 1 => quotient, out("ecx") _, out("edx") _ ); }
+    println!("Division by zero skipped, quotient: ", quotient);
 
-    // Do nothing until the end of time - 'never' (!) return type
-    loop {}
+    // Start receiving IRQs: the timer starts ticking
+    hal::interrupts::enable();
+
+    // Wait for interrupts until the end of time - 'never' (!) return type.
+    // The ticks are printed here, numbers can't be safely converted in ISRs.
+    // Typed characters are echoed to the screen.
+    loop {
+        unsafe { core::arch::asm!("hlt"); }
+        get_vga().lock().print_at(60, 24, hal::pit::ticks().to_string(), 0x70);
+
+        while let Some(ascii) = keyboard::read_char() {
+            let mut vga = get_vga().lock();
+            if ascii == b'\n' { vga.ln(); continue; }
+            let color: u8 = vga.color();
+            vga.print_char(ascii, color);
+        }
+    }
 }
 
 /* ==== PANIC HANDLER ======================================================= */
+/// Prints the panic reason, in red to stand out from the log, then dumps the
+/// interrupted state if the panic comes from an ISR, the current registers
+/// otherwise.
+fn print_panic(message: &str, frame: Option<&hal::idt::isr::IsrStackFrame>) {
+    printc!(vga::Color::Red, "Panic: ", message, "\r\n");
+    match frame {
+        Some(frame) => frame.print(),
+        None => hal::registers::dump_registers()
+    }
+}
+
+#[cfg(not(test))]
 #[panic_handler] fn panic(_info: &PanicInfo) -> ! {
 
-    // Print panic reason
-    println!("Panic: ", _info.message().unwrap().as_str().unwrap_or("Unknown"));
-    
+    // The panic could come from a handler that interrupted a print: the lock
+    // would never be released, take it anyway.
+    unsafe { get_vga().force_unlock(); }
+
+    let message: &str = _info.message().unwrap().as_str().unwrap_or("Unknown");
+    print_panic(message, hal::idt::isr::fault_frame());
+    serial_println!("Panic: ", message);
+
     // Do nothing until the end of time - 'never' (!) return type
     loop {}
 }
 
 /* ==== TESTS =============================================================== */
-// TODO: custom framework for no_std testing
\ No newline at end of file
+/// Test builds report the failed test on the serial port and close QEMU.
+#[cfg(test)]
+#[panic_handler] fn panic(_info: &PanicInfo) -> ! {
+    testing::test_panic_handler(_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn panic_report_dumps_the_frame_after_the_colored_message() {
+        let mut buffer: [u16; 80 * 25] = [0; 80 * 25];
+        vga::with_test_vga(&mut buffer, || {
+            let frame: hal::idt::isr::IsrStackFrame = unsafe { core::mem::zeroed() };
+            print_panic("Test", Some(&frame));
+
+            let vga: &mut vga::Vga = get_vga();
+            assert!(vga.read_at(0, 0) == (b'P', 0x04));
+            assert!(vga.read_at(0, 1).0 == b'I' && vga.read_at(0, 2).0 == b'E');
+            assert!(vga.read_at(0, 5).0 == b'D');
+        });
+    }
+}
@@ -0,0 +1,224 @@
+// Physical memory management: the RAM is split in 4 KiB frames, tracked by a
+// bitmap (1 bit per frame, set if the frame is used). The usable regions are
+// taken from the BIOS memory map (INT 15,E820) retrieved by stage-2, since
+// the BIOS can't be called from 32pm.
+//
+// The first MB is always reserved: it contains the IVT and BIOS data, the
+// stage-2 (and the memory map itself), the stack, the VGA buffer (0xB8000)
+// and the BIOS ROM. The kernel image, loaded at 0x10000, is reserved as well.
+//
+// Memory map anatomy: https://wiki.osdev.org/Detecting_Memory_(x86)
+
+use crate::{get_vga, println, ToString};
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// Physical memory address.
+pub type PhysAddr = u32;
+
+/// Entry of the BIOS memory map (INT 15,E820), describes a physical memory
+/// region. The layout is shared with stage-2, which retrieves the map.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MemoryRegion {
+    pub base: u64,
+    pub length: u64,
+    pub region_type: u32,
+    /// ACPI 3.0 extended attributes: bit 0 unset means ignore the entry.
+    pub attributes: u32
+}
+
+/// Physical memory map, as returned by the BIOS: regions may be unsorted
+/// and overlapping. Only the first count regions are valid.
+#[repr(C)]
+pub struct MemoryMap {
+    pub regions: [MemoryRegion; MemoryMap::MAX_REGIONS],
+    pub count: u32
+}
+
+/* ==== CONSTANTS =========================================================== */
+impl MemoryRegion {
+    /// Region type of the RAM free to use; all the other types are reserved.
+    pub const TYPE_USABLE: u32 = 1;
+}
+
+impl MemoryMap {
+    /// Maximum number of regions, must match the stage-2 value.
+    pub const MAX_REGIONS: usize = 32;
+}
+
+/// Size in bytes of a physical frame (and of a page).
+pub const FRAME_SIZE: u32 = 4096;
+
+/// Physical memory past this address is ignored: it keeps the bitmap small.
+const MAX_MEMORY: u64 = 256 * 1024 * 1024;
+/// Number of frames tracked by the bitmap.
+const MAX_FRAMES: usize = (MAX_MEMORY / FRAME_SIZE as u64) as usize;
+
+/// Frames below this address are always reserved (see module description).
+const LOW_MEMORY_END: u32 = 0x100000;
+/// Address the kernel image is loaded at by stage-2.
+const KERNEL_START: u32 = 0x10000;
+
+/* ==== ALLOCATOR DATA ====================================================== */
+// Address of the last byte of the kernel image, defined in link.ld.
+extern "C" {
+    static end: u8;
+}
+
+/// Frames bitmap, 1 bit per frame: set if the frame is used or reserved.
+/// Initialized in init, .bss is not guaranteed to be zeroed.
+static mut FRAMES: [u32; MAX_FRAMES / 32] = [0; MAX_FRAMES / 32];
+/// Index of the bitmap word to start the next search from.
+static mut NEXT_WORD: usize = 0;
+/// Number of free frames.
+static mut FREE_FRAMES: usize = 0;
+
+/* ==== METHODS ============================================================= */
+impl MemoryRegion {
+    /// Checks whether the region is free RAM that can be used.
+    pub fn is_usable(&self) -> bool {
+        self.region_type == MemoryRegion::TYPE_USABLE && self.attributes & 1 != 0 && self.length != 0
+    }
+}
+
+impl MemoryMap {
+    /// Returns the valid regions of the map.
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions[..core::cmp::min(self.count as usize, MemoryMap::MAX_REGIONS)]
+    }
+}
+
+/// Initializes the frame allocator with the usable regions of the given map.
+/// All the frames start as used: only the frames fully contained in a usable
+/// region are freed, then the first MB and the kernel image are reserved
+/// again (reserved regions could overlap usable ones).
+pub fn init(map: &MemoryMap) {
+    unsafe {
+        FRAMES = [u32::MAX; MAX_FRAMES / 32];
+        FREE_FRAMES = 0;
+        NEXT_WORD = 0;
+    }
+
+    for region in map.regions().iter().filter(|r| r.is_usable()) {
+        let start: u64 = region.base.div_ceil(FRAME_SIZE as u64);
+        let stop: u64 = core::cmp::min(region.base + region.length, MAX_MEMORY) / FRAME_SIZE as u64;
+        for frame in start..stop { set_frame(frame as usize, false); }
+    }
+
+    reserve(0, LOW_MEMORY_END);
+    reserve(KERNEL_START, kernel_end());
+
+    println!("Memory: ", free_frames() * FRAME_SIZE as usize / 1024, " KiB free");
+}
+
+/// Returns the address right after the kernel image.
+fn kernel_end() -> PhysAddr {
+    unsafe { &end as *const u8 as u32 }
+}
+
+/// Marks the frames overlapping the given address range as used.
+fn reserve(start: PhysAddr, stop: PhysAddr) {
+    for frame in (start / FRAME_SIZE)..stop.div_ceil(FRAME_SIZE) {
+        if (frame as usize) < MAX_FRAMES { set_frame(frame as usize, true); }
+    }
+}
+
+/// Updates the bitmap bit of the given frame and the free frames count.
+fn set_frame(frame: usize, used: bool) {
+    unsafe {
+        let mask: u32 = 1 << (frame % 32);
+        let was_used: bool = FRAMES[frame / 32] & mask != 0;
+        if used == was_used { return; }
+        if used { FRAMES[frame / 32] |= mask; FREE_FRAMES -= 1; }
+        else { FRAMES[frame / 32] &= !mask; FREE_FRAMES += 1; }
+    }
+}
+
+/// Allocates a physical frame and returns its address, or None if there is
+/// no free memory left. The frame content is not cleared.
+pub fn alloc_frame() -> Option<PhysAddr> {
+    unsafe {
+        let words: usize = MAX_FRAMES / 32;
+        for i in 0..words {
+            let word: usize = (NEXT_WORD + i) % words;
+            if FRAMES[word] == u32::MAX { continue; }
+
+            let frame: usize = word * 32 + FRAMES[word].trailing_ones() as usize;
+            set_frame(frame, true);
+            NEXT_WORD = word;
+            return Some(frame as u32 * FRAME_SIZE);
+        }
+    }
+    None
+}
+
+/// Releases a frame returned by alloc_frame. Panics if the address is not
+/// frame aligned or if the frame is already free (double free).
+#[allow(dead_code)]
+pub fn free_frame(addr: PhysAddr) {
+    let frame: usize = (addr / FRAME_SIZE) as usize;
+    if !addr.is_multiple_of(FRAME_SIZE) || frame >= MAX_FRAMES { panic!("Invalid frame address!"); }
+    if unsafe { FRAMES[frame / 32] } & (1 << (frame % 32)) == 0 { panic!("Frame already free!"); }
+    set_frame(frame, false);
+}
+
+/// Returns the number of free frames.
+pub fn free_frames() -> usize {
+    unsafe { FREE_FRAMES }
+}
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Copy of the bitmap, to release the frames taken by the tests.
+    static mut SAVED_FRAMES: [u32; MAX_FRAMES / 32] = [0; MAX_FRAMES / 32];
+
+    /// Runs f and restores the allocator state from before the call.
+    fn preserving_frames<F: FnOnce()>(f: F) {
+        let saved_free: usize = free_frames();
+        let saved_next: usize = unsafe { NEXT_WORD };
+        unsafe { SAVED_FRAMES = FRAMES; }
+        f();
+        unsafe {
+            FRAMES = SAVED_FRAMES;
+            FREE_FRAMES = saved_free;
+            NEXT_WORD = saved_next;
+        }
+    }
+
+    #[test_case]
+    fn reserved_frames_are_never_allocated() {
+        preserving_frames(|| {
+            let free: usize = free_frames();
+            let mut allocated: usize = 0;
+            while let Some(addr) = alloc_frame() {
+                assert!(addr >= LOW_MEMORY_END);
+                assert!(addr / FRAME_SIZE != 0xB8000 / FRAME_SIZE);
+                assert!(addr + FRAME_SIZE <= KERNEL_START || addr >= kernel_end());
+                allocated += 1;
+            }
+            assert!(allocated == free);
+            assert!(free_frames() == 0);
+        });
+    }
+
+    #[test_case]
+    fn alloc_and_free_round_trip() {
+        preserving_frames(|| {
+            let free: usize = free_frames();
+            let addr: PhysAddr = alloc_frame().unwrap();
+            assert!(addr.is_multiple_of(FRAME_SIZE));
+            assert!(free_frames() == free - 1);
+
+            free_frame(addr);
+            assert!(free_frames() == free);
+
+            // The released frame is the first free one again
+            assert!(alloc_frame() == Some(addr));
+            free_frame(addr);
+            assert!(free_frames() == free);
+        });
+    }
+}
@@ -0,0 +1,154 @@
+// Two level paging (32 bit, 4 KiB pages): a virtual address is split in the
+// page directory index (bits 22-31), the page table index (bits 12-21) and
+// the offset in the page (bits 0-11). The directory and the tables have 1024
+// 4 byte entries each, the address of the directory is loaded in CR3 and
+// paging is enabled by setting the PG bit (31) of CR0.
+//
+// The first 4 MB are identity mapped (virtual = physical address), so that
+// the kernel, its stack and the VGA buffer keep working once paging is on.
+// The first page is left unmapped: null pointer accesses cause a Page Fault.
+//
+// The last directory entry points to the directory itself (recursive
+// mapping): once paging is enabled, the page table of the directory entry N
+// is accessible at 0xFFC00000 + N * 4096, and the directory at 0xFFFFF000.
+// Tables can then be allocated anywhere in the physical memory.
+//
+// Paging anatomy: https://wiki.osdev.org/Paging
+
+use crate::memory::{self, PhysAddr, FRAME_SIZE};
+
+/* ==== CONSTANTS =========================================================== */
+/// The page is present: accessing a not present page causes a Page Fault.
+pub const PAGE_PRESENT: u32 = 1 << 0;
+/// The page can be written, it's read only otherwise.
+pub const PAGE_WRITABLE: u32 = 1 << 1;
+/// The page can be accessed by ring 3 code.
+#[allow(dead_code)]
+pub const PAGE_USER: u32 = 1 << 2;
+
+/// Number of entries of the page directory and of the page tables.
+const ENTRIES: usize = 1024;
+/// Mask of the physical address stored in the entries.
+const ADDRESS_MASK: u32 = 0xFFFF_F000;
+/// Size of the identity mapped memory, starting from 0.
+const IDENTITY_MAP_END: u32 = 4 * 1024 * 1024;
+/// Index of the recursive directory entry.
+const RECURSIVE_ENTRY: usize = ENTRIES - 1;
+/// Virtual address of the page tables, through the recursive entry.
+const RECURSIVE_TABLES: u32 = 0xFFC0_0000;
+/// Virtual address of the page directory, through the recursive entry.
+const RECURSIVE_DIRECTORY: u32 = 0xFFFF_F000;
+/// Paging enable bit of CR0.
+const CR0_PAGING: u32 = 1 << 31;
+
+/* ==== PAGING DATA ========================================================= */
+/// Physical address of the page directory.
+static mut DIRECTORY: PhysAddr = 0;
+/// Whether paging is enabled: tables are accessed through the recursive
+/// entry if it is, through their physical address otherwise.
+static mut ENABLED: bool = false;
+
+/* ==== METHODS ============================================================= */
+/// Builds the page directory with the identity mapped memory, loads it in
+/// CR3 and enables paging. The frame allocator must be initialized.
+pub fn init() {
+    let directory: PhysAddr = alloc_table();
+    unsafe {
+        DIRECTORY = directory;
+        *(directory as *mut u32).add(RECURSIVE_ENTRY) = directory | PAGE_PRESENT | PAGE_WRITABLE;
+    }
+
+    for page in (FRAME_SIZE..IDENTITY_MAP_END).step_by(FRAME_SIZE as usize) {
+        map_page(page, page, PAGE_WRITABLE);
+    }
+
+    unsafe {
+        core::arch::asm!("mov cr3, {}", in(reg) directory, options(nostack, preserves_flags));
+        core::arch::asm!("mov {0}, cr0", "or {0}, {1}", "mov cr0, {0}", out(reg) _, in(reg) CR0_PAGING, options(nostack));
+        ENABLED = true;
+    }
+}
+
+/// Maps the page containing the given virtual address to the frame
+/// containing the given physical address, with the given flags (the page is
+/// always present). The page table is allocated if needed.
+/// Panics if there's no memory left for the page table.
+pub fn map_page(virt: u32, phys: PhysAddr, flags: u32) {
+    let directory: *mut u32 = directory();
+    let (dir_index, table_index) = indexes(virt);
+
+    unsafe {
+        let dir_entry: *mut u32 = directory.add(dir_index);
+        if *dir_entry & PAGE_PRESENT == 0 {
+            // The user flag of the directory entry must be set too
+            let table: PhysAddr = alloc_table_entry(dir_index, flags & PAGE_USER);
+            *dir_entry = table | PAGE_PRESENT | PAGE_WRITABLE | (flags & PAGE_USER);
+        }
+
+        *table(dir_index).add(table_index) = (phys & ADDRESS_MASK) | (flags & !ADDRESS_MASK) | PAGE_PRESENT;
+        invalidate(virt);
+    }
+}
+
+/// Removes the mapping of the page containing the given virtual address:
+/// accessing it causes a Page Fault. The frame is not released, and the
+/// page table is kept even if empty.
+#[allow(dead_code)]
+pub fn unmap_page(virt: u32) {
+    let (dir_index, table_index) = indexes(virt);
+    unsafe {
+        if *directory().add(dir_index) & PAGE_PRESENT == 0 { return; }
+        *table(dir_index).add(table_index) = 0;
+        invalidate(virt);
+    }
+}
+
+/// Returns the directory and table indexes of the given virtual address.
+fn indexes(virt: u32) -> (usize, usize) {
+    ((virt >> 22) as usize, ((virt >> 12) as usize) & (ENTRIES - 1))
+}
+
+/// Returns a pointer to the page directory.
+fn directory() -> *mut u32 {
+    unsafe { if ENABLED { RECURSIVE_DIRECTORY as *mut u32 } else { DIRECTORY as *mut u32 } }
+}
+
+/// Returns a pointer to the page table of the given directory entry, which
+/// must be present.
+fn table(dir_index: usize) -> *mut u32 {
+    unsafe {
+        if ENABLED { return (RECURSIVE_TABLES + dir_index as u32 * FRAME_SIZE) as *mut u32; }
+        (*directory().add(dir_index) & ADDRESS_MASK) as *mut u32
+    }
+}
+
+/// Allocates and clears a frame for the page directory, before paging is
+/// enabled (the frame is accessed through its physical address).
+fn alloc_table() -> PhysAddr {
+    let frame: PhysAddr = match memory::alloc_frame() { Some(f) => f, None => panic!("Out of memory!") };
+    unsafe { core::ptr::write_bytes(frame as *mut u32, 0, ENTRIES); }
+    frame
+}
+
+/// Allocates a page table for the given directory entry and clears it. If
+/// paging is enabled, the table is temporarily added to the directory to
+/// access it through the recursive entry.
+fn alloc_table_entry(dir_index: usize, user: u32) -> PhysAddr {
+    if unsafe { !ENABLED } { return alloc_table(); }
+
+    let frame: PhysAddr = match memory::alloc_frame() { Some(f) => f, None => panic!("Out of memory!") };
+    unsafe {
+        *directory().add(dir_index) = frame | PAGE_PRESENT | PAGE_WRITABLE | user;
+        let table: *mut u32 = table(dir_index);
+        invalidate(table as u32);
+        core::ptr::write_bytes(table, 0, ENTRIES);
+    }
+    frame
+}
+
+/// Flushes the TLB entry of the given virtual address, if paging is enabled.
+fn invalidate(virt: u32) {
+    if unsafe { ENABLED } {
+        unsafe { core::arch::asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags)); }
+    }
+}
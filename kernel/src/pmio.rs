@@ -1,4 +1,5 @@
 use core::arch::asm;
+use core::marker::PhantomData;
 
 /* ==== PORT MAPPED ADDRESSES =============================================== */
 #[allow(dead_code)]
@@ -13,62 +14,145 @@ pub enum PortMappedAddr {
     VgaRegisterIndexRW3,
     VgaRegisterIndexW2 = 0x3CE,
     VgaRegisterIndexRW2,    // Previous +1
+    Ps2Data = 0x60,
+    Ps2StatusCommand = 0x64,
+    PicMasterCommand = 0x20,
+    PicMasterData,          // Previous +1
+    PicSlaveCommand = 0xA0,
+    PicSlaveData,           // Previous +1
+    PitChannel0Data = 0x40,
+    PitCommand = 0x43,
+    Com1Data = 0x3F8,
+    Com1InterruptEnable,    // Previous +1
+    Com1FifoControl,        // Previous +1
+    Com1LineControl,        // Previous +1
+    Com1ModemControl,       // Previous +1
+    Com1LineStatus,         // Previous +1
+    PostDiagnostic = 0x80,
+    QemuDebugExit = 0xF4,
     // ...
 }
 
+/* ===== PUBLIC METHODS ===================================================== */
+/** Waits for a short time (1-4 microseconds) by writing to an unused port.
+ *  Legacy devices (ex: the PIC) can be slower than the CPU and need some
+ *  time between consecutive commands.
+ *  Port 0x80 is used by the BIOS to output POST (Power-On Self-Test)
+ *  diagnostic codes: after boot nothing reads it, so writing is harmless. */
+pub fn io_wait() {
+    unsafe { Port::<u8>::new(PortMappedAddr::PostDiagnostic) }.write(0);
+}
+
 /* ===== TYPE DEFINITION ==================================================== */
-pub struct Port {
-    port: u16
+/** Port of the given value width: u8 (default), u16 or u32. The width only
+ *  affects read and write, the concrete methods below work on any Port. */
+pub struct Port<T: PortValue = u8> {
+    port: u16,
+    value: PhantomData<T>
+}
+
+/** Values that can be transferred with a single IN/OUT instruction. The
+ *  trait is sealed: only u8, u16 and u32 can implement it. */
+pub trait PortValue: Copy + private::Sealed {
+    /** Reads a value from the given port. */
+    unsafe fn read_from(port: u16) -> Self;
+    /** Sends the given value to the given port. */
+    unsafe fn write_to(port: u16, value: Self);
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/* ===== PORT VALUE WIDTHS ================================================== */
+/*  Declare ASM instruction to be executed.
+    "in" puts the variable into the DX register.
+    "out" stores the register into variable after execution.
+    "inout" puts the value into the AL register; since the 'out' instruction
+    could modify AL's value, using the "inout" and discarding the final
+    value (=> _) clarifies the expected behaviour.
+    The value register (AL, AX, EAX) selects the width of the transfer. */
+impl PortValue for u8 {
+    unsafe fn read_from(port: u16) -> u8 {
+        let value: u8;
+        asm!( "in al, dx", in("dx") port, out("al") value );
+        value
+    }
+    unsafe fn write_to(port: u16, value: u8) {
+        asm!( "out dx, al", in("dx") port, inout("al") value => _ );
+    }
+}
+
+impl PortValue for u16 {
+    unsafe fn read_from(port: u16) -> u16 {
+        let value: u16;
+        asm!( "in ax, dx", in("dx") port, out("ax") value );
+        value
+    }
+    unsafe fn write_to(port: u16, value: u16) {
+        asm!( "out dx, ax", in("dx") port, inout("ax") value => _ );
+    }
+}
+
+impl PortValue for u32 {
+    unsafe fn read_from(port: u16) -> u32 {
+        let value: u32;
+        asm!( "in eax, dx", in("dx") port, out("eax") value );
+        value
+    }
+    unsafe fn write_to(port: u16, value: u32) {
+        asm!( "out dx, eax", in("dx") port, inout("eax") value => _ );
+    }
 }
 
 /* ===== STATIC TYPE METHODS ================================================ */
-impl Port {
+impl<T: PortValue> Port<T> {
     /** Creates a new Port instance for the specified Address.
      *! Marked as unsafe since using Port I/O might result in unexpected
      *! behaviour. This responsability is given to the constructor caller. */
-    pub const unsafe fn new(port: PortMappedAddr) -> Port {
-        Port { port: port as u16 }
+    pub const unsafe fn new(port: PortMappedAddr) -> Port<T> {
+        Port { port: port as u16, value: PhantomData }
     }
 }
 
 /* ===== PUBLIC TYPE METHODS ================================================ */
-/*  Declare ASM instruction to be executed.
-    "in" puts the variable into the DX register.
-    "out" stores the register into variable after execution.
-    "inout" puts the value into the AL register; since the 'out' instruction
-    could modify AL's value, using the "inout" and discarding the final
-    value (=> _) clarifies the expected behaviour. */
 #[allow(dead_code)]
-impl Port {
+impl<T: PortValue> Port<T> {
+    /** Reads a value of the port width. */
+    pub fn read(&self) -> T {
+        unsafe { T::read_from(self.port) }
+    }
+    /** Sends a value of the port width. */
+    pub fn write(&self, value: T) {
+        unsafe { T::write_to(self.port, value) }
+    }
+
     /** Sends the input u8 value to the Port. */
     pub fn outb(&self, value: u8) {
-        unsafe { asm!( "out dx, al", in("dx") self.port, inout("al") value => _ ); }
+        unsafe { u8::write_to(self.port, value) }
     }
     /** Sends the input u16 value to the Port. */
     pub fn outw(&self, value: u16) {
-        unsafe { asm!( "out dx, ax", in("dx") self.port, inout("ax") value => _ ); }
+        unsafe { u16::write_to(self.port, value) }
     }
     /** Sends the input u32 value to the Port. */
     pub fn outl(&self, value: u32) {
-        unsafe { asm!( "out dx, eax", in("dx") self.port, inout("eax") value => _ ); }
+        unsafe { u32::write_to(self.port, value) }
     }
 
     /** Reads an u8 value from the Port. */
     pub fn inb(&self) -> u8 {
-        let value: u8;
-        unsafe { asm!( "in al, dx", in("dx") self.port, out("al") value ); }
-        value
+        unsafe { u8::read_from(self.port) }
     }
     /** Reads an u16 value from the Port. */
     pub fn inw(&self) -> u16 {
-        let value: u16;
-        unsafe { asm!( "in ax, dx", in("dx") self.port, out("ax") value ); }
-        value
+        unsafe { u16::read_from(self.port) }
     }
     /** Reads an u32 value from the Port. */
     pub fn inl(&self) -> u32 {
-        let value: u32;
-        unsafe { asm!( "in eax, dx", in("dx") self.port, out("eax") value ); }
-        value
+        unsafe { u32::read_from(self.port) }
     }
-}
\ No newline at end of file
+}
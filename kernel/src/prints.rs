@@ -10,9 +10,13 @@
 #[macro_export]
 macro_rules! print {
     ($($arg:expr),*) => {
-        let vga = get_vga();
+        // The guard is scoped to the macro: it's released right after the
+        // print instead of at the end of the calling block.
         {
+            let mut vga = get_vga().lock();
             $(
+                // Print each argument right after converting it, the
+                // conversions share the same buffer (see ToString).
                 let s = $arg.to_string();
                 vga.print(s);
             )*
@@ -27,8 +31,8 @@ macro_rules! print {
 #[macro_export]
 macro_rules! println {
     ($($arg:expr),*) => {
-        let vga = get_vga();
         {
+            let mut vga = get_vga().lock();
             $(
                 // To avoid error "temporary value dropped while borrowed",
                 // when using a reference returned from a method as a parameter,
@@ -38,19 +42,123 @@ macro_rules! println {
                 let s = s.to_string();
                 vga.print(s);
             )*
+            vga.ln();
         }
-        vga.ln();
     };
 
 }
 
+/** Like print!, but prints with the given foreground color (a vga::Color),
+ *  keeping the current background, then restores the previous colors.
+ *  Each character cell stores an attribute byte next to its ASCII code: the
+ *  lower 4 bits are the foreground color, the upper 4 the background one
+ *  (the highest bit may blink instead, depending on the VGA mode).
+ *  Ex: printc!(Color::Yellow, "Warning: ", count, " sectors skipped\r\n") */
+#[macro_export]
+macro_rules! printc {
+    ($color:expr, $($arg:expr),*) => {
+        {
+            let mut vga = get_vga().lock();
+            let (previous_fg, previous_bg) = vga.colors();
+            vga.set_color($color, previous_bg);
+            $(
+                let s = $arg;
+                let s = s.to_string();
+                vga.print(s);
+            )*
+            vga.set_color(previous_fg, previous_bg);
+        }
+    };
+}
+
+/** Like println!, but safe to use in interrupt handlers: it never waits for
+ *  the VGA lock. If the interrupted code is printing, the output is deferred
+ *  and printed as soon as the interrupted print is completed.
+ *! Numbers are still converted in the shared buffer (see ToString): if the
+ *! interrupted code is between a conversion and its print, it prints this
+ *! number instead. Print numbers from handlers only when debugging. */
+#[macro_export]
+macro_rules! println_irq {
+    ($($arg:expr),*) => {
+        match get_vga().try_lock() {
+            Some(mut vga) => {
+                $(
+                    let s = $arg;
+                    let s = s.to_string();
+                    vga.print(s);
+                )*
+                vga.ln();
+            },
+            None => {
+                $(
+                    let s = $arg;
+                    let s = s.to_string();
+                    $crate::vga::defer(s);
+                )*
+                $crate::vga::defer(b"\r\n");
+            }
+        }
+    };
+}
+
+/** Writes the given parameters as characters to the given &mut [u8] buffer
+ *  instead of the screen, one after the other, so that the formatted text
+ *  can be sent anywhere (VGA, serial, disk). Supports the same types as
+ *  print!. Returns the written part of the buffer, or None if the text
+ *  doesn't fit: in that case, the buffer content is not meaningful.
+ *  Ex: fmt!(&mut buffer, "LBA: ", lba, " - Count: ", count) */
+#[macro_export]
+macro_rules! fmt {
+    ($buffer:expr, $($arg:expr),*) => {{
+        let buffer: &mut [u8] = $buffer;
+        let mut length: usize = 0;
+        let mut fits: bool = true;
+        $(
+            // Copy each argument right after converting it, the
+            // conversions share the same buffer (see ToString).
+            let s = $arg;
+            let s = s.to_string();
+            if fits && length + s.len() <= buffer.len() {
+                buffer[length..length + s.len()].copy_from_slice(s);
+                length += s.len();
+            } else {
+                fits = false;
+            }
+        )*
+        if fits { Some(&buffer[..length]) } else { None }
+    }};
+}
+
 /* ==== TRAIT DEFINITION ==================================================== */
 /*  Define ToString trait so that we can implement a custom to_string function
     for each type we need to print with the print! macro.
     The trait is implement by defining a to_string method that returns a
-    reference to a static string. */
+    reference to a static string.
+    ! Numbers are all converted in the same static BUFFER: the returned slice
+    ! is only valid until the next conversion, which overwrites it. Use it
+    ! right away, never keep two of them: print! and println! print each
+    ! argument before converting the next one for this reason. */
 pub trait ToString { fn to_string(&self) -> &[u8]; }
-pub trait ToStringBase { fn to_string_base(&self, base: u8) -> &[u8]; }
+pub trait ToStringBase {
+    fn to_string_base(&self, base: u8) -> &[u8];
+
+    /** Hex representation padded to the full type width, without prefix.
+        Ex: 0xB8000u32 --> 000B8000. */
+    fn to_hex_no_prefix(&self) -> &[u8] { self.to_string_base(16) }
+
+    /** Hex representation padded to the full type width, with 0x prefix.
+        Ex: 0xB8000u32 --> 0x000B8000, 0u16 --> 0x0000.
+        The prefix is written in BUFFER right before the digits, which are
+        always placed at its end. */
+    fn to_hex(&self) -> &[u8] {
+        let start: usize = BUFFER_SIZE - self.to_hex_no_prefix().len() - 2;
+        unsafe {
+            BUFFER[start] = b'0';
+            BUFFER[start + 1] = b'x';
+            &BUFFER[start..]
+        }
+    }
+}
 
 /* ==== STATIC BUFFERS AND DIGITS =========================================== */
 /** List of possible digits for a number to print.
@@ -58,7 +166,8 @@ pub trait ToStringBase { fn to_string_base(&self, base: u8) -> &[u8]; }
 const DIGITS: &[u8] = b"0123456789ABCDEF";
 const BUFFER_SIZE: usize = 129;
 /** Static buffer to store ASCII representation without allocator.
-    Maximum size is 128 for u128 number (base 2), +1 for '-' if needed. */
+    Maximum size is 128 for u128 number (base 2), +1 for '-' if needed.
+    Shared by all the conversions, see the ToString trait. */
 static mut BUFFER: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
 
 /* ==== TRAIT IMPLEMENTATION FOR STRINGS ==================================== */
@@ -235,7 +344,10 @@ macro_rules! to_string_impl_int {
                 // value and cast to unsigned integer, keeping it as signed
                 // would break the divisions (-1 / base is not what we want...).
                 // -2: 0b11111110 --> ~ --> 0b00000001 --> + 1 --> 0b00000010: 2
-                let mut num: $uty = (!num + 1) as $uty;
+                // The complement is done on the unsigned value: for iN::MIN
+                // the magnitude doesn't fit in the signed type and +1 would
+                // overflow, while it does in the unsigned one (-128 --> 128).
+                let mut num: $uty = (!(num as $uty)).wrapping_add(1);
                 let base: $uty = base as $uty;
 
                 loop {
@@ -294,12 +406,13 @@ to_string_impl_int!(isize, usize);
 
 /* ==== TRAIT IMPLEMENTATION FOR POINTERS =================================== */
 /*  For pointers, the implementation is just the same as the unsigned integer
-    types, all it takes is a type cast. */
+    types, all it takes is a type cast. The impls are generic over the pointed
+    type; addresses are printed in hex with the 0x prefix (see to_hex). */
 #[macro_export]
 macro_rules! to_string_impl_ptr {
     // Match ty: type for which we are implementing the to_string method.
     ($ty:ty) => {
-        impl ToStringBase for $ty {
+        impl<T> ToStringBase for $ty {
             fn to_string_base(&self, base: u8) -> &[u8] {
                 // (*self as usize).to_string_base(base)
 
@@ -342,39 +455,97 @@ macro_rules! to_string_impl_ptr {
             }
         }
 
-        impl ToString for $ty {
+        impl<T> ToString for $ty {
             fn to_string(&self) -> &[u8] {
                 //(*self as usize).to_string()
 
                 //> ============================================================
-                self.to_string_base(10)
+                self.to_hex()
                 //> ============================================================
             }
         }
     };
 }
 
-to_string_impl_ptr!(*const u8);
-to_string_impl_ptr!(*const u16);
-to_string_impl_ptr!(*const u32);
-to_string_impl_ptr!(*const u64);
-to_string_impl_ptr!(*const u128);
-to_string_impl_ptr!(*const usize);
-to_string_impl_ptr!(*const i8);
-to_string_impl_ptr!(*const i16);
-to_string_impl_ptr!(*const i32);
-to_string_impl_ptr!(*const i64);
-to_string_impl_ptr!(*const i128);
-to_string_impl_ptr!(*const isize);
-to_string_impl_ptr!(*mut u8);
-to_string_impl_ptr!(*mut u16);
-to_string_impl_ptr!(*mut u32);
-to_string_impl_ptr!(*mut u64);
-to_string_impl_ptr!(*mut u128);
-to_string_impl_ptr!(*mut usize);
-to_string_impl_ptr!(*mut i8);
-to_string_impl_ptr!(*mut i16);
-to_string_impl_ptr!(*mut i32);
-to_string_impl_ptr!(*mut i64);
-to_string_impl_ptr!(*mut i128);
-to_string_impl_ptr!(*mut isize);
\ No newline at end of file
+to_string_impl_ptr!(*const T);
+to_string_impl_ptr!(*mut T);
+
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vga::{get_vga, with_test_vga, Vga};
+
+    #[test_case]
+    fn fmt_writes_arguments_one_after_the_other() {
+        let mut buffer: [u8; 32] = [0; 32];
+        let lba: u32 = 1234;
+        assert!(fmt!(&mut buffer, "LBA: ", lba, " - Count: ", 2u8) == Some(b"LBA: 1234 - Count: 2".as_slice()));
+    }
+
+    #[test_case]
+    fn fmt_returns_none_if_the_text_does_not_fit() {
+        let mut buffer: [u8; 4] = [0; 4];
+        assert!(fmt!(&mut buffer, "12", 345u16).is_none());
+    }
+
+    #[test_case]
+    fn i8_min_is_printed_in_every_base() {
+        assert!(i8::MIN.to_string_base(2) == b"10000000");
+        assert!(i8::MIN.to_string_base(10) == b"-128");
+        assert!(i8::MIN.to_string_base(16) == b"80");
+    }
+
+    #[test_case]
+    fn i32_min_is_printed_in_every_base() {
+        assert!(i32::MIN.to_string_base(2) == b"10000000000000000000000000000000");
+        assert!(i32::MIN.to_string_base(10) == b"-2147483648");
+        assert!(i32::MIN.to_string_base(16) == b"80000000");
+    }
+
+    #[test_case]
+    fn i64_min_is_printed_in_every_base() {
+        assert!(i64::MIN.to_string_base(2) == b"1000000000000000000000000000000000000000000000000000000000000000");
+        assert!(i64::MIN.to_string_base(10) == b"-9223372036854775808");
+        assert!(i64::MIN.to_string_base(16) == b"8000000000000000");
+    }
+
+    #[test_case]
+    fn minus_one_is_printed_in_every_base() {
+        assert!((-1i32).to_string_base(2) == b"11111111111111111111111111111111");
+        assert!((-1i32).to_string_base(10) == b"-1");
+        assert!((-1i32).to_string_base(16) == b"FFFFFFFF");
+    }
+
+    #[test_case]
+    fn to_hex_pads_zero_to_the_type_width() {
+        assert!(0u32.to_hex() == b"0x00000000");
+        assert!(0u16.to_hex() == b"0x0000");
+    }
+
+    #[test_case]
+    fn to_hex_prints_uppercase_digits() {
+        assert!(0xDEADBEEFu32.to_hex() == b"0xDEADBEEF");
+    }
+
+    #[test_case]
+    fn to_hex_prints_pointers_as_addresses() {
+        let hex: &[u8] = (0xB8000 as *const u16).to_hex();
+        assert!(hex.len() == 2 + 2 * core::mem::size_of::<usize>());
+        assert!(hex.starts_with(b"0x000") && hex.ends_with(b"000B8000"));
+    }
+
+    #[test_case]
+    fn println_prints_each_number_before_converting_the_next() {
+        let mut buffer: [u16; 80 * 25] = [0; 80 * 25];
+        with_test_vga(&mut buffer, || {
+            // Both conversions use the shared buffer
+            let (a, b): (u32, u32) = (12, 345);
+            println!("a=", a, " b=", b);
+
+            let vga: &mut Vga = get_vga();
+            let line: [u8; 10] = core::array::from_fn(|x| vga.read_at(x, 0).0);
+            assert!(&line == b"a=12 b=345");
+        });
+    }
+}
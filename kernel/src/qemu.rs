@@ -0,0 +1,28 @@
+// QEMU helpers, only compiled for test and dev builds: on real hardware the
+// ports used here may belong to other devices.
+//
+// The isa-debug-exit device closes QEMU when a value is written to its port,
+// with (value << 1) | 1 as exit code: 0 can't be returned, so that a normal
+// QEMU exit (0) is never mistaken for a success.
+// QEMU must be started with "-device isa-debug-exit,iobase=0xf4,iosize=0x04".
+
+use crate::pmio::{Port, PortMappedAddr};
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// Values written to isa-debug-exit. QEMU exits with 33 for Success and 35
+/// for Failed: both are distinct from the codes of QEMU own errors (1).
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11
+}
+
+/* ==== METHODS ============================================================= */
+/// Closes QEMU with the given exit code. Does nothing (and returns) if the
+/// isa-debug-exit device is not present.
+#[allow(dead_code)]
+pub fn exit(code: QemuExitCode) {
+    unsafe { Port::<u32>::new(PortMappedAddr::QemuDebugExit) }.write(code as u32);
+}
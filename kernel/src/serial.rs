@@ -0,0 +1,115 @@
+// Serial port driver (16550 UART) for the first port, COM1. QEMU can redirect
+// it to a file or to the terminal (-serial file:<path> / -serial stdio): it
+// gives a log that can be scrolled and saved, independent of the screen.
+// Only output is supported, by polling: the UART interrupts are disabled.
+//
+// UART anatomy: https://wiki.osdev.org/Serial_Ports
+
+use crate::pmio::Port;
+use crate::pmio::PortMappedAddr;
+
+/* ==== PUBLIC MACROS ======================================================= */
+/** Writes the given parameters as characters to the serial port, then a new
+ *  line. Supports the same types as println! (see ToString). */
+#[macro_export]
+macro_rules! serial_println {
+    ($($arg:expr),*) => {
+        $(
+            // Write each argument right after converting it, the
+            // conversions share the same buffer (see ToString).
+            let s = $arg;
+            let s = s.to_string();
+            $crate::serial::write_str(s);
+        )*
+        $crate::serial::write_str(b"\r\n");
+    };
+}
+
+/* ==== STATIC INITIALIZATION =============================================== */
+/** Crate static serial port instance, only accessed through module functions. */
+static mut SERIAL: Serial = Serial::new();
+
+/** Returns reference to static serial port instance. */
+fn get_serial() -> &'static Serial { unsafe { &*core::ptr::addr_of!(SERIAL) } }
+
+/* ==== TYPE DEFINITION ===================================================== */
+struct Serial {
+    /** Transmit buffer, or divisor low byte while DLAB is set. */
+    data_port: Port,
+    /** Interrupt enable register, or divisor high byte while DLAB is set. */
+    interrupt_enable_port: Port,
+    /** FIFO control register. */
+    fifo_control_port: Port,
+    /** Line control register: data bits, parity, stop bits and DLAB. */
+    line_control_port: Port,
+    /** Modem control register: DTR, RTS and OUT2 lines. */
+    modem_control_port: Port,
+    /** Line status register: the transmit buffer state. */
+    line_status_port: Port
+}
+
+/* ==== TYPE CONSTANTS ====================================================== */
+impl Serial {
+    /** Divisor of the 115200 base baud rate: 3 is 38400 baud. */
+    const BAUD_DIVISOR: u16 = 3;
+    /** DLAB (Divisor Latch Access Bit): data and interrupt enable registers
+     *  are used to set the divisor while it's set. */
+    const LINE_DLAB: u8 = 0x80;
+    /** 8 data bits, no parity, 1 stop bit (8N1). */
+    const LINE_8N1: u8 = 0x03;
+    /** Enable and clear the FIFOs, 14 bytes interrupt threshold. */
+    const FIFO_ENABLE_CLEAR_14: u8 = 0xC7;
+    /** DTR and RTS set, OUT2 set (needed by some machines for the IRQ). */
+    const MODEM_DTR_RTS_OUT2: u8 = 0x0B;
+    /** Line status bit set when the transmit buffer is empty. */
+    const STATUS_TRANSMIT_EMPTY: u8 = 0x20;
+}
+
+/* ==== CONSTRUCTOR ========================================================= */
+impl Serial {
+    const fn new() -> Self {
+        unsafe {
+            Self {
+                data_port: Port::new(PortMappedAddr::Com1Data),
+                interrupt_enable_port: Port::new(PortMappedAddr::Com1InterruptEnable),
+                fifo_control_port: Port::new(PortMappedAddr::Com1FifoControl),
+                line_control_port: Port::new(PortMappedAddr::Com1LineControl),
+                modem_control_port: Port::new(PortMappedAddr::Com1ModemControl),
+                line_status_port: Port::new(PortMappedAddr::Com1LineStatus)
+            }
+        }
+    }
+}
+
+/* ==== PUBLIC METHODS ====================================================== */
+/** Initializes COM1: interrupts disabled, 38400 baud, 8N1, FIFO enabled. */
+pub fn init() {
+    let serial: &Serial = get_serial();
+    serial.interrupt_enable_port.outb(0x00);
+
+    // Set the baud rate divisor, low byte first
+    serial.line_control_port.outb(Serial::LINE_DLAB);
+    serial.data_port.outb(Serial::BAUD_DIVISOR as u8);
+    serial.interrupt_enable_port.outb((Serial::BAUD_DIVISOR >> 8) as u8);
+
+    // Clearing DLAB gives the data and interrupt enable registers back
+    serial.line_control_port.outb(Serial::LINE_8N1);
+    serial.fifo_control_port.outb(Serial::FIFO_ENABLE_CLEAR_14);
+    serial.modem_control_port.outb(Serial::MODEM_DTR_RTS_OUT2);
+}
+
+/** Writes a byte, waiting for the transmit buffer to be empty. */
+pub fn write_byte(b: u8) {
+    let serial: &Serial = get_serial();
+    while serial.line_status_port.inb() & Serial::STATUS_TRANSMIT_EMPTY == 0 { core::hint::spin_loop(); }
+    serial.data_port.outb(b);
+}
+
+/** Writes all the given bytes. Line feeds are sent as CR LF, as terminals
+ *  expect. */
+pub fn write_str(s: &[u8]) {
+    for b in s.iter() {
+        if *b == b'\n' { write_byte(b'\r'); }
+        write_byte(*b);
+    }
+}
@@ -0,0 +1,55 @@
+// Custom test framework: the standard one needs std, so the test harness is
+// replaced (custom_test_frameworks). Functions marked with #[test_case] are
+// collected by the compiler and given to test_runner, which runs them one
+// after the other and reports the results on the serial port.
+//
+// Once all the tests are run (or when one panics), QEMU is closed through
+// the isa-debug-exit device, so that the host gets the outcome as exit code
+// (see qemu::exit).
+
+use crate::{serial_println, ToString};
+use crate::qemu::{self, QemuExitCode};
+
+/* ==== TYPE DEFINITION ===================================================== */
+/// A test that can be run by test_runner. Implemented for all the functions
+/// without arguments, so that #[test_case] can be used on plain functions.
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    /// Prints the test name, runs it and prints "ok" if it didn't panic.
+    fn run(&self) {
+        crate::serial::write_str(core::any::type_name::<T>().as_bytes());
+        crate::serial::write_str(b"... ");
+        self();
+        serial_println!("ok");
+    }
+}
+
+/* ==== METHODS ============================================================= */
+/// Test harness entry point, called by the generated test_main.
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running ", tests.len(), " tests");
+    for test in tests { test.run(); }
+    qemu::exit(QemuExitCode::Success);
+}
+
+/// Panic handler variant for the tests: the running test has failed, the
+/// message is reported and QEMU is closed with the failure code.
+pub fn test_panic_handler(info: &core::panic::PanicInfo) -> ! {
+    serial_println!("FAILED");
+    serial_println!("Error: ", info.message().unwrap().as_str().unwrap_or("Unknown"));
+    qemu::exit(QemuExitCode::Failed);
+    loop {}
+}
+
+
+/* ==== TESTS =============================================================== */
+/// Always fails, to check that a failing test is reported and QEMU exits with
+/// the failure code. Only built with the "failing-test" feature.
+#[cfg(feature = "failing-test")]
+#[test_case]
+fn failing_test_exits_with_failure() {
+    panic!("This test fails on purpose");
+}
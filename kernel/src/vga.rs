@@ -1,64 +1,284 @@
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
 use crate::pmio::Port;
 use crate::pmio::PortMappedAddr;
+use crate::keyboard;
+use crate::hal::interrupts::without_interrupts;
 
 /* ==== STATIC INITIALIZATION AND SYNCHRONIZATION =========================== */
 /** Crate static VGA instance to access mutably using the public get_vga method.
  *! Using VGA is unsafe since it requires static multi-thread mutable access. */
-static mut VGA: Vga = Vga::new();
+static mut VGA: Vga = Vga::new(Vga::BUFFER_START as *mut u16);
 
 /** Public method to get mutable reference to static VGA instance.
  *! Using VGA is unsafe since it requires static multi-thread mutable access. */
 pub fn get_vga() -> &'static mut Vga { unsafe { &mut VGA } }
 
+/** Set while a VgaGuard exists: printing code holds it for the whole print.
+ *  Interrupt handlers must never spin on it, since the interrupted code could
+ *  be the one holding it: they use try_lock and defer their output instead. */
+static VGA_LOCK: AtomicBool = AtomicBool::new(false);
+
+/** Size of the buffer storing the output deferred by interrupt handlers. */
+const DEFERRED_SIZE: usize = 256;
+/** Output that couldn't be printed since the lock was held, and its length.
+ *  It's printed when the lock is released; exceeding bytes are discarded. */
+static mut DEFERRED: [u8; DEFERRED_SIZE] = [0; DEFERRED_SIZE];
+static mut DEFERRED_LEN: usize = 0;
+
+/** Stores the given string in the deferred output buffer, to be printed as
+ *  soon as the VGA lock is released. Used by the println_irq! macro. */
+pub fn defer(s: &[u8]) {
+    for c in s.iter() {
+        unsafe {
+            if DEFERRED_LEN >= DEFERRED_SIZE { return; }
+            DEFERRED[DEFERRED_LEN] = *c;
+            DEFERRED_LEN += 1;
+        }
+    }
+}
+
+/* ==== LOCK GUARD ========================================================== */
+/** Grants access to the VGA instance while holding the VGA lock.
+ *  The lock is released (and deferred output printed) when dropped. */
+pub struct VgaGuard {
+    vga: &'static mut Vga
+}
+
+impl Deref for VgaGuard {
+    type Target = Vga;
+    fn deref(&self) -> &Vga { self.vga }
+}
+
+impl DerefMut for VgaGuard {
+    fn deref_mut(&mut self) -> &mut Vga { self.vga }
+}
+
+impl Drop for VgaGuard {
+    /** Prints the output deferred while the lock was held, then releases it.
+     *  Interrupts are disabled (restoring the previous state at the end) so
+     *  that no handler can defer something between the flush and the reset.
+     *  Paging is bypassed meanwhile: a deferred line feed could fill the page,
+     *  and the prompt would wait for a key that can't arrive without IRQs. */
+    fn drop(&mut self) {
+        without_interrupts(|| unsafe {
+            let paging: bool = self.vga.paging;
+            self.vga.paging = false;
+
+            let color: u8 = self.vga.color();
+            let deferred: &[u8] = &*core::ptr::slice_from_raw_parts(core::ptr::addr_of!(DEFERRED) as *const u8, DEFERRED_LEN);
+            for c in deferred.iter() { self.vga.print_char(*c, color); }
+
+            self.vga.paging = paging;
+            DEFERRED_LEN = 0;
+            VGA_LOCK.store(false, Ordering::Release);
+        });
+    }
+}
+
+/** Runs the given closure with the static instance writing to the given
+ *  buffer instead of the screen, then restores it: the code printing
+ *  through get_vga (macros, lock, deferred output) can be checked in memory.
+ *  The buffer must hold a whole 80x25 screen. */
+#[cfg(test)]
+pub fn with_test_vga<F: FnOnce()>(buffer: &mut [u16], f: F) {
+    if buffer.len() < Vga::MAX_CHARACTERS_LINE * Vga::DEFAULT_LINES { panic!("Test VGA buffer is smaller than the screen!"); }
+    let previous: Vga = core::mem::replace(get_vga(), Vga::new(buffer.as_mut_ptr()));
+    f();
+    *get_vga() = previous;
+}
+
 /* ==== TYPE DEFINITION ===================================================== */
+/** Text mode palette: the attribute byte of each character holds the
+ *  foreground color in the lower 4 bits and the background one in the upper
+ *  4 (the highest bit may blink instead, depending on the VGA mode). */
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black = 0, Blue = 1, Green = 2, Cyan = 3,
+    Red = 4, Magenta = 5, Brown = 6, LightGray = 7,
+    DarkGray = 8, LightBlue = 9, LightGreen = 10, LightCyan = 11,
+    LightRed = 12, Pink = 13, Yellow = 14, White = 15
+}
+
 pub struct Vga {
+    /** Address of the first cell of the text buffer written by this instance:
+        BUFFER_START for the hardware one, any memory for the others. */
+    buffer_start: *mut u16,
     /** This variable stores the current absolute VGA buffer position at
         which the characters will be printed.
-        The value is initialized at VGA_BUFFER_START (0xB8000). */
+        The value is initialized at the buffer start. */
     buffer_position: *mut u16,
+    /** First and last lines (inclusive) of the scroll region: line feeds on
+        the last line only shift the lines in this region, the others are
+        left untouched. The whole screen is used by default. */
+    scroll_top: usize,
+    scroll_bottom: usize,
     /** VGA port used to write register index. */
     register_index_w_3_port: Port,
     /** VGA port used to read/write register specified at the index port. */
-    register_index_rw_3_port: Port
+    register_index_rw_3_port: Port,
+    /** Set if the cursor registers have been probed and respond correctly.
+        When unset, cursor operations are skipped. */
+    cursor_available: bool,
+    /** Set if the cursor must follow the buffer position after each print. */
+    cursor_sync: bool,
+    /** Number of lines of the current text mode (25 or 50). */
+    lines: usize,
+    /** Colors used by print and the print macros. */
+    fg: Color,
+    bg: Color,
+    /** Set if the output must pause with a "more" prompt every screenful. */
+    paging: bool,
+    /** Line feeds since the last pause (or clear), used when paging. */
+    paged_lines: usize,
+    /** Waits for the key that ends a paging pause: the keyboard one, or a
+        stand-in for the instances writing to plain memory (see the tests). */
+    key_wait: fn() -> u8
 }
 
 /* ==== TYPE CONSTANTS ====================================================== */
 impl Vga {
-    /** VGA text buffer start address; used to initialize buffer position. */
-    const BUFFER_START: *const u16 = 0xB8000 as *const u16;
-    /** VGA text buffer end address; used to check maximum buffer position. */
-    const BUFFER_END: *const u16 = (0xB8000 + Vga::MAX_CHARACTERS_SCREEN*2 - 1) as *const u16;
+    /** VGA text buffer start address, mapped to the screen by the hardware. */
+    pub const BUFFER_START: *const u16 = 0xB8000 as *const u16;
     
-    /** Maximum number of lines supported by VGA. */
-    const MAX_LINES: usize = 25;
+    /** Address where the font plane is mapped while accessing it. */
+    const FONT_START: *const u8 = 0xA0000 as *const u8;
+    /** Bytes reserved to each glyph in the font plane (only 16 are used). */
+    const FONT_GLYPH_SIZE: usize = 32;
+
+    /** Number of lines of the default 80x25 text mode. */
+    const DEFAULT_LINES: usize = 25;
     /** Maximum number of characters per line supported by VGA. */
     const MAX_CHARACTERS_LINE: usize = 80;
-    /** Maximum number of characters per screen supported by VGA. */
-    const MAX_CHARACTERS_SCREEN: usize = Vga::MAX_CHARACTERS_LINE * Vga::MAX_LINES;
+    /** Columns between tab stops. */
+    const TAB_WIDTH: usize = 8;
 }
 
 /*//! ==== WIP METHODS ====================================================== */
 impl Vga {
     pub fn clear_cursor(&self) -> () {
+        if !self.cursor_available { return; }
         self.register_index_w_3_port.outb(0x0A);
         self.register_index_rw_3_port.outb(0x20);
     }
-    // TODO: implement cursor handling methods
+
+    /** Shows the cursor, with the shape going from the start scanline to the
+     *  end one (0-15, top to bottom of the character cell). Ex: 14-15 is the
+     *  usual underline, 0-15 a full block. Reserved register bits are kept. */
+    #[allow(dead_code)]
+    pub fn enable_cursor(&self, start: u8, end: u8) {
+        if !self.cursor_available { return; }
+        self.register_index_w_3_port.outb(0x0A);
+        let current: u8 = self.register_index_rw_3_port.inb();
+        self.register_index_rw_3_port.outb((current & 0xC0) | (start & 0x1F));
+        self.register_index_w_3_port.outb(0x0B);
+        let current: u8 = self.register_index_rw_3_port.inb();
+        self.register_index_rw_3_port.outb((current & 0xE0) | (end & 0x1F));
+    }
+
+    /** Moves the cursor to the given column and line of the screen. */
+    pub fn set_cursor(&self, x: usize, y: usize) {
+        if x >= Vga::MAX_CHARACTERS_LINE || y >= self.lines { panic!("VGA cursor out of the screen!"); }
+        if !self.cursor_available { return; }
+        self.write_cursor_location((y * Vga::MAX_CHARACTERS_LINE + x) as u16);
+    }
 
     pub fn clear_screen(&mut self) {
+        self.probe_cursor();
         self.clear();
         self.clear_cursor();
     }
+
+    /** Checks that the cursor registers are actually writable: on some
+     *  emulated or headless setups writes are ignored or read back different.
+     *  A known location is written and read back, the previous one restored.
+     *  The outcome is stored and returned: if false, cursor operations are
+     *  skipped and a software cursor should be used instead. */
+    pub fn probe_cursor(&mut self) -> bool {
+        const PROBE_LOCATION: u16 = 0x0102;
+
+        let previous: u16 = self.read_cursor_location();
+        self.write_cursor_location(PROBE_LOCATION);
+        let read: u16 = self.read_cursor_location();
+        self.write_cursor_location(previous);
+
+        self.cursor_available = Vga::cursor_probe_matches(PROBE_LOCATION, read);
+        self.cursor_available
+    }
+
+    /** Returns whether the cursor probe read back the written location. */
+    const fn cursor_probe_matches(written: u16, read: u16) -> bool { written == read }
+
+    /** Reads the cursor location from registers 0x0E (high) and 0x0F (low). */
+    fn read_cursor_location(&self) -> u16 {
+        self.register_index_w_3_port.outb(0x0E);
+        let high: u8 = self.register_index_rw_3_port.inb();
+        self.register_index_w_3_port.outb(0x0F);
+        let low: u8 = self.register_index_rw_3_port.inb();
+        ((high as u16) << 8) | low as u16
+    }
+
+    /** Writes the cursor location to registers 0x0E (high) and 0x0F (low). */
+    fn write_cursor_location(&self, location: u16) {
+        self.register_index_w_3_port.outb(0x0E);
+        self.register_index_rw_3_port.outb((location >> 8) as u8);
+        self.register_index_w_3_port.outb(0x0F);
+        self.register_index_rw_3_port.outb(location as u8);
+    }
 }
 
 /* ==== STATIC TYPE METHODS ================================================= */
 impl Vga {
-    /** Declare constructor as 'const' in order to declare static instances. */
-    const fn new() -> Vga {
+    /** Declare constructor as 'const' in order to declare static instances.
+     *  The instance writes to the given buffer, which must hold a whole
+     *  screen of cells (80x25, or 80x50 after set_mode_80x50): BUFFER_START
+     *  for the screen (see get_vga), or a plain array to run the printing
+     *  logic and inspect the result in memory (see the tests). */
+    pub const fn new(buffer: *mut u16) -> Vga {
         Vga {
-            buffer_position: Vga::BUFFER_START as *mut u16,
+            buffer_start: buffer,
+            buffer_position: buffer,
+            scroll_top: 0,
+            scroll_bottom: Vga::DEFAULT_LINES - 1,
             register_index_w_3_port: unsafe { Port::new(PortMappedAddr::VgaRegisterIndexW3) },
-            register_index_rw_3_port: unsafe { Port::new(PortMappedAddr::VgaRegisterIndexRW3) }
+            register_index_rw_3_port: unsafe { Port::new(PortMappedAddr::VgaRegisterIndexRW3) },
+            cursor_available: false,
+            cursor_sync: false,
+            lines: Vga::DEFAULT_LINES,
+            fg: Color::Green,
+            bg: Color::Black,
+            paging: false,
+            paged_lines: 0,
+            key_wait: keyboard::wait_keypress
+        }
+    }
+}
+
+/* ==== LOCKING METHODS ===================================================== */
+impl Vga {
+    /** Waits for the VGA lock to be free, then takes it.
+     *! Never call from interrupt handlers, use try_lock instead. */
+    pub fn lock(&'static mut self) -> VgaGuard {
+        while VGA_LOCK.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        VgaGuard { vga: self }
+    }
+
+    /** Releases the VGA lock even if a guard still holds it.
+     *! Only to be used when the holder will never resume (panic handler). */
+    #[cfg_attr(test, allow(dead_code))]
+    pub unsafe fn force_unlock(&self) {
+        VGA_LOCK.store(false, Ordering::Release);
+    }
+
+    /** Takes the VGA lock if it is free, returns None otherwise. */
+    pub fn try_lock(&'static mut self) -> Option<VgaGuard> {
+        match VGA_LOCK.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Some(VgaGuard { vga: self }),
+            Err(_) => None
         }
     }
 }
@@ -67,36 +287,109 @@ impl Vga {
 #[allow(dead_code)]
 impl Vga {
     /** Writes a character at current VGA buffer position, incrementing it.
-     *  TODO: handle special characters
-     *  TODO: handle relative position > MAX_CHARACTERS (wrap? scroll?) */
+     *  Writing past the end of the last line of the scroll region scrolls it.
+     *  Control characters: \n, \r, \t (spaces up to the next multiple of
+     *  TAB_WIDTH columns) and \b (clears the previous cell). */
     pub fn print_char(&mut self, ascii: u8, color: u8) {
-        // Handle \n character: go to new line instead of printing.
-        if ascii == b'\n' {
-            self.line_feed();
-            return;
-        }
+        match ascii {
+            // Handle \n character: go to new line instead of printing.
+            b'\n' => self.line_feed(),
+            b'\r' => self.carriage_return(),
+            b'\t' => {
+                // Always print at least one space, as terminals do
+                let spaces: usize = Vga::TAB_WIDTH - self.get_current_column() % Vga::TAB_WIDTH;
+                for _ in 0..spaces { self.print_char(b' ', color); }
+            },
+            b'\x08' => self.backspace(color),
+            _ => {
+                // Concat color and ascii byte in character word
+                let character: u16 = ((color as u16) * 256) + ascii as u16;
+                unsafe { *self.buffer_position = character; }
+                self.buffer_position = unsafe { self.buffer_position.add(1) };
 
-        if ascii == b'\r' {
-            self.carriage_return();
-            return;
+                // Check if the buffer position is overflowing the maximum buffer size
+                self.check_buffer_position();
+            }
         }
 
-        // Concat color and ascii byte in character word
-        let character: u16 = ((color as u16) * 256) + ascii as u16;
-        unsafe { *self.buffer_position = character; }
-        self.buffer_position = unsafe { self.buffer_position.add(1) };
+        self.sync_cursor();
+    }
 
-        // Check if the buffer position is overflowing the maximum buffer size
-        self.check_buffer_position();
+    /** Enables or disables moving the cursor to the buffer position after
+     *  each printed character, to show where the next one will be written.
+     *  Disabled by default: each move takes four port writes. */
+    pub fn set_cursor_sync(&mut self, enabled: bool) {
+        self.cursor_sync = enabled;
+        self.sync_cursor();
     }
 
     /** Writes ASCII string at current VGA buffer position, incrementing it. */
     pub fn print(&mut self, s: &[u8]) -> () {
+        let color: u8 = self.color();
         for c in s.iter() {
-            self.print_char(*c, 0x02);
+            self.print_char(*c, color);
         }
     }
 
+    /** Writes the string at the given column and line with the given color
+     *  attribute, leaving the buffer position (and the cursor) untouched,
+     *  for status bars and menus. Bytes are written as glyphs, control
+     *  characters included; the part past the end of the line is clipped. */
+    pub fn print_at(&mut self, x: usize, y: usize, s: &[u8], color: u8) {
+        if x >= Vga::MAX_CHARACTERS_LINE || y >= self.lines { panic!("VGA position out of the screen!"); }
+
+        let start: *mut u16 = unsafe { self.buffer_start.add(y * Vga::MAX_CHARACTERS_LINE + x) };
+        let count: usize = core::cmp::min(s.len(), Vga::MAX_CHARACTERS_LINE - x);
+        for (i, c) in s.iter().take(count).enumerate() {
+            unsafe { *start.add(i) = ((color as u16) * 256) + *c as u16; }
+        }
+    }
+
+    /** Fills the rectangle with the given top left corner (column, line),
+     *  width and height with spaces of the given color attribute, leaving the
+     *  buffer position untouched. The parts outside the screen are clipped. */
+    pub fn clear_region(&mut self, x: usize, y: usize, w: usize, h: usize, color: u8) {
+        let x_end: usize = core::cmp::min(x.saturating_add(w), Vga::MAX_CHARACTERS_LINE);
+        let y_end: usize = core::cmp::min(y.saturating_add(h), self.lines);
+
+        for line in y..y_end {
+            for column in x..x_end {
+                unsafe { *self.buffer_start.add(line * Vga::MAX_CHARACTERS_LINE + column) = ((color as u16) * 256) + b' ' as u16; }
+            }
+        }
+    }
+
+    /** Returns the ASCII byte and the color attribute stored in the cell at
+     *  the given column and line, to inspect what's on screen. */
+    pub fn read_at(&self, x: usize, y: usize) -> (u8, u8) {
+        if x >= Vga::MAX_CHARACTERS_LINE || y >= self.lines { panic!("VGA position out of the screen!"); }
+
+        let character: u16 = unsafe { *self.buffer_start.add(y * Vga::MAX_CHARACTERS_LINE + x) };
+        (character as u8, (character >> 8) as u8)
+    }
+
+    /** Sets the colors used by print (and the print macros) from now on. */
+    pub fn set_color(&mut self, fg: Color, bg: Color) {
+        self.fg = fg;
+        self.bg = bg;
+    }
+
+    /** Returns the attribute byte of the current colors. */
+    pub fn color(&self) -> u8 { ((self.bg as u8) << 4) | self.fg as u8 }
+
+    /** Returns the current foreground and background colors, to restore them
+     *  after a temporary change. */
+    pub fn colors(&self) -> (Color, Color) { (self.fg, self.bg) }
+
+    /** Prints the string with the given colors, then restores the previous
+     *  ones. */
+    pub fn with_color(&mut self, fg: Color, bg: Color, s: &[u8]) {
+        let (previous_fg, previous_bg) = (self.fg, self.bg);
+        self.set_color(fg, bg);
+        self.print(s);
+        self.set_color(previous_fg, previous_bg);
+    }
+
     /** Prints string (see 'print()'), then moves buffer to new line. */
     pub fn println(&mut self, s: &[u8]) {
         self.print(s);
@@ -115,48 +408,159 @@ impl Vga {
         // Move buffer position at the start of the screen
         self.reset_buffer_position();
 
-        // Fill the entire screen with space character and black background
-        for _ in 0..Vga::MAX_CHARACTERS_SCREEN {
-            self.print_char(b' ', 0x0F);
-        }
-
-        // Move buffer position at the start of the screen again
-        self.reset_buffer_position();
+        // Fill the entire screen with space character and black background.
+        // Cells are written directly: printing would scroll on the last line.
+        Vga::blank_cells(self.buffer_position, self.characters_screen());
+        self.paged_lines = 0;
+        self.sync_cursor();
     }
 
     pub fn clearln(&mut self) -> () {
 
-        // Set buffer position to line start (save value for later)
+        // Set buffer position to line start
         self.carriage_return();
-        let t = self.buffer_position;
 
         // Fill the entire line with space character and black background
-        for _ in 0..Vga::MAX_CHARACTERS_LINE {
-            self.print_char(b' ', 0x0F);
+        Vga::blank_cells(self.buffer_position, Vga::MAX_CHARACTERS_LINE);
+        self.sync_cursor();
+    }
+
+    /** Sets the lines (inclusive, 0 based) that are scrolled when a line
+     *  feed occurs on the bottom line; lines outside the region are kept,
+     *  so that headers and footers can stay on screen (like ANSI DECSTBM). */
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        if top > bottom || bottom >= self.lines { panic!("Invalid VGA scroll region!"); }
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+    }
+
+    /** Restores the default scroll region, which covers the whole screen. */
+    pub fn reset_scroll_region(&mut self) {
+        self.set_scroll_region(0, self.lines - 1);
+    }
+
+    /** Switches to the 80x50 text mode, halving the character height from 16
+     *  to 8 scanlines: the 400 scanlines of the screen fit 50 lines. There's
+     *  no BIOS to load the 8x8 ROM font in protected mode, so the glyphs of
+     *  the loaded 8x16 font are squashed in place (each row is the OR of two
+     *  original ones). The scroll region is reset and the screen cleared.
+     *  Registers written (index port/data port, register: value):
+     *  - Sequencer (0x3C4/0x3C5), map mask 0x02: 0x04 then 0x03 and memory
+     *    mode 0x04: 0x06 then 0x02, to access the font plane and go back;
+     *  - Graphics Controller (0x3CE/0x3CF), read map select 0x04: 0x02 then
+     *    0x00, mode 0x05: 0x00 then 0x10 and miscellaneous 0x06: 0x04 then
+     *    0x0E, to map the font plane at 0xA0000 and the text at 0xB8000;
+     *  - CRTC (0x3D4/0x3D5), maximum scan line 0x09: 7 (height - 1), cursor
+     *    start 0x0A: 6 and end 0x0B: 7, other bits kept, so that the cursor
+     *    is still drawn at the bottom of the cell. */
+    pub fn set_mode_80x50(&mut self) {
+        let sequencer: (Port, Port) = unsafe { (Port::new(PortMappedAddr::VgaRegisterIndexW1), Port::new(PortMappedAddr::VgaRegisterIndexRW1)) };
+        let graphics: (Port, Port) = unsafe { (Port::new(PortMappedAddr::VgaRegisterIndexW2), Port::new(PortMappedAddr::VgaRegisterIndexRW2)) };
+        let write = |ports: &(Port, Port), register: u8, value: u8| { ports.0.outb(register); ports.1.outb(value); };
+
+        // Map the font plane (2) at 0xA0000, with sequential addressing
+        write(&sequencer, 0x02, 0x04);
+        write(&sequencer, 0x04, 0x06);
+        write(&graphics, 0x04, 0x02);
+        write(&graphics, 0x05, 0x00);
+        write(&graphics, 0x06, 0x04);
+
+        // Squash each glyph: rows 2r and 2r+1 become row r. Rows are always
+        // read before being overwritten, since 2r >= r.
+        for glyph in 0..256 {
+            let rows: *mut u8 = unsafe { (Vga::FONT_START as *mut u8).add(glyph * Vga::FONT_GLYPH_SIZE) };
+            for row in 0..8 {
+                unsafe { *rows.add(row) = *rows.add(row * 2) | *rows.add(row * 2 + 1); }
+            }
+        }
+
+        // Restore the text mode access: planes 0 and 1 (odd/even) at 0xB8000
+        write(&sequencer, 0x02, 0x03);
+        write(&sequencer, 0x04, 0x02);
+        write(&graphics, 0x04, 0x00);
+        write(&graphics, 0x05, 0x10);
+        write(&graphics, 0x06, 0x0E);
+
+        // Set the character height and move the cursor scanlines inside it
+        for (register, value) in [(0x09, 0x07), (0x0A, 0x06), (0x0B, 0x07)] {
+            self.register_index_w_3_port.outb(register);
+            let current: u8 = self.register_index_rw_3_port.inb();
+            self.register_index_rw_3_port.outb((current & 0xE0) | value);
         }
 
-        // Reset buffer position to line start
-        self.buffer_position = t;
-        
+        self.lines = 50;
+        self.reset_scroll_region();
+        self.clear();
+    }
+
+    /** Enables or disables paging: when enabled, after a screenful of lines
+     *  the output stops with a "-- More --" prompt until a key is pressed. */
+    pub fn set_paging(&mut self, paging: bool) {
+        self.paging = paging;
+        self.paged_lines = 0;
+    }
+
+    /** Shifts the lines of the scroll region up by one, discarding the top
+     *  one; the bottom line is filled with space character and black
+     *  background. The buffer position is not changed. */
+    pub fn scroll_up(&mut self) {
+        let buffer_start: *mut u16 = self.buffer_start;
+        let line_start = |line: usize| unsafe { buffer_start.add(line * Vga::MAX_CHARACTERS_LINE) };
+
+        // Move lines (top+1..=bottom) to (top..bottom), color included.
+        // Regions overlap: copy handles it like memmove would.
+        let count: usize = (self.scroll_bottom - self.scroll_top) * Vga::MAX_CHARACTERS_LINE;
+        unsafe { core::ptr::copy(line_start(self.scroll_top + 1), line_start(self.scroll_top), count); }
+
+        // Clear the bottom line of the region
+        Vga::blank_cells(line_start(self.scroll_bottom), Vga::MAX_CHARACTERS_LINE);
     }
 }
 
 /* ==== PRIVATE TYPE METHODS ================================================ */
 impl Vga {
+    /** Number of characters per screen in the current text mode. */
+    fn characters_screen(&self) -> usize { Vga::MAX_CHARACTERS_LINE * self.lines }
+
     /** Calculates the buffer address position relative to the start of the screen. */
     fn get_buffer_relative_position(&self) -> usize {
-        self.buffer_position as usize - Vga::BUFFER_START as usize
+        self.buffer_position as usize - self.buffer_start as usize
     }
     
-    /** Resets the buffer_position value to VGA_BUFFER_START (0xB8000). */
+    /** Resets the buffer_position value to the buffer start. */
     fn reset_buffer_position(&mut self) -> () {
-        self.buffer_position = Vga::BUFFER_START as *mut u16;
+        self.buffer_position = self.buffer_start;
+    }
+
+    /** Moves the cursor to the buffer position, if cursor sync is enabled. */
+    fn sync_cursor(&self) {
+        if !self.cursor_sync { return; }
+        let cell: usize = self.get_buffer_relative_position() / 2;
+        self.set_cursor(cell % Vga::MAX_CHARACTERS_LINE, cell / Vga::MAX_CHARACTERS_LINE);
+    }
+
+    /** Fills count cells from the given one with space character and black
+     *  background, without moving the buffer position. */
+    fn blank_cells(start: *mut u16, count: usize) {
+        for i in 0..count {
+            unsafe { *start.add(i) = (0x0F * 256) + b' ' as u16; }
+        }
     }
 
-    /** If the buffer is overflowing, reset its position to the start. */
+    /** If the buffer position went past the last line of the scroll region
+     *  (the line was filled), the region is scrolled up and the position is
+     *  moved to the start of its last line, keeping the older output on
+     *  screen. Past the end of the screen (below the region), the position
+     *  is reset to the start. */
     fn check_buffer_position(&mut self) {
-        if self.buffer_position as usize > Vga::BUFFER_END as usize {
-            self.buffer_position = (self.buffer_position as usize - Vga::MAX_CHARACTERS_SCREEN*2) as *mut u16;
+        if self.get_buffer_relative_position() == (self.scroll_bottom + 1) * Vga::MAX_CHARACTERS_LINE*2 {
+            self.scroll_up();
+            self.buffer_position = (self.buffer_position as usize - Vga::MAX_CHARACTERS_LINE*2) as *mut u16;
+            return;
+        }
+
+        if self.get_buffer_relative_position() >= self.characters_screen()*2 {
+            self.buffer_position = (self.buffer_position as usize - self.characters_screen()*2) as *mut u16;
         }
     }
 
@@ -165,10 +569,325 @@ impl Vga {
     fn carriage_return(&mut self) {
         self.buffer_position = (self.buffer_position as usize - (self.get_buffer_relative_position() % (Vga::MAX_CHARACTERS_LINE*2))) as *mut u16;
     }
+    /** Calculates the column of the screen the buffer position is on. */
+    fn get_current_column(&self) -> usize {
+        (self.get_buffer_relative_position() / 2) % Vga::MAX_CHARACTERS_LINE
+    }
+
+    /** Handles the Backspace special character (\b).
+     *  It moves the buffer position back by one cell and clears it. At the
+     *  start of a line, it goes to the end of the previous one; at the start
+     *  of the screen, nothing happens. */
+    fn backspace(&mut self, color: u8) {
+        if self.buffer_position == self.buffer_start { return; }
+        self.buffer_position = unsafe { self.buffer_position.sub(1) };
+        unsafe { *self.buffer_position = ((color as u16) * 256) + b' ' as u16; }
+    }
+
+    /** Calculates the line of the screen the buffer position is on. */
+    fn get_current_line(&self) -> usize {
+        self.get_buffer_relative_position() / (Vga::MAX_CHARACTERS_LINE*2)
+    }
+
     /** Handles the Line Feed special character (\n).
-     *  It sets the buffer position to the start of the next line. */
+     *  It sets the buffer position to the start of the next line.
+     *  If the position is on the last line of the scroll region, the region
+     *  is scrolled up instead and the position stays on the same line. */
     fn line_feed(&mut self) {
-        self.buffer_position = (self.buffer_position as usize + Vga::MAX_CHARACTERS_LINE*2) as *mut u16;
-        self.check_buffer_position();
+        if self.get_current_line() == self.scroll_bottom {
+            self.scroll_up();
+        } else {
+            self.buffer_position = (self.buffer_position as usize + Vga::MAX_CHARACTERS_LINE*2) as *mut u16;
+            self.check_buffer_position();
+        }
+
+        if self.paging {
+            self.paged_lines += 1;
+            if Vga::page_is_full(self.paged_lines, self.scroll_bottom - self.scroll_top) { self.more_prompt(); }
+        }
     }
-}
\ No newline at end of file
+
+    /** Checks if enough lines have been printed to fill the scroll region.
+     *  The last line of the region is left for the prompt, so a page holds
+     *  one line less than the region. */
+    const fn page_is_full(lines: usize, page_size: usize) -> bool { lines >= page_size }
+
+    /** Prints the "-- More --" prompt on the current line and waits for a
+     *  key to be pressed, then clears the prompt and resets the line count. */
+    fn more_prompt(&mut self) {
+        let position: *mut u16 = self.buffer_position;
+        self.carriage_return();
+        for c in b"-- More --".iter() { self.print_char(*c, 0x70); }
+
+        (self.key_wait)();
+
+        self.clearln();
+        self.buffer_position = position;
+        self.paged_lines = 0;
+    }
+}
+/* ==== TESTS =============================================================== */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{printc, println, println_irq, prints::ToString};
+
+    /** Cells of a 80x25 screen, for the instances writing to plain memory. */
+    const CELLS: usize = Vga::MAX_CHARACTERS_LINE * Vga::DEFAULT_LINES;
+
+    /** Attribute of the default colors (green on black). */
+    const DEFAULT_COLOR: u8 = 0x02;
+
+    /** Buffer of the instance under test, inspected by fake_key_wait. */
+    static mut SCREEN: *const u16 = core::ptr::null();
+    /** Calls to fake_key_wait, and whether each one found the prompt. */
+    static mut KEY_WAITS: usize = 0;
+    static mut PROMPT_SHOWN: bool = true;
+
+    /** Stand-in for the keyboard wait: returns at once, as if a key was
+     *  already pressed, after checking the prompt on the last line. */
+    fn fake_key_wait() -> u8 {
+        unsafe {
+            let last_line: *const u16 = SCREEN.add(Vga::MAX_CHARACTERS_LINE * (Vga::DEFAULT_LINES - 1));
+            let prompt: bool = b"-- More --".iter().enumerate().all(|(i, c)| *last_line.add(i) as u8 == *c);
+            PROMPT_SHOWN &= prompt;
+            KEY_WAITS += 1;
+        }
+        b' '
+    }
+
+    /** Returns an instance with paging enabled, pausing on fake_key_wait. */
+    fn paged_vga(buffer: &mut [u16]) -> Vga {
+        unsafe {
+            SCREEN = buffer.as_ptr();
+            KEY_WAITS = 0;
+            PROMPT_SHOWN = true;
+        }
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+        vga.key_wait = fake_key_wait;
+        vga.set_paging(true);
+        vga
+    }
+
+    #[test_case]
+    fn println_writes_the_text_and_moves_to_the_next_line() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        vga.println(b"Hi");
+        vga.print(b"X");
+        assert!(vga.read_at(0, 0) == (b'H', DEFAULT_COLOR));
+        assert!(vga.read_at(1, 0) == (b'i', DEFAULT_COLOR));
+        assert!(vga.read_at(0, 1) == (b'X', DEFAULT_COLOR));
+    }
+
+    #[test_case]
+    fn clear_blanks_the_screen_and_resets_the_position() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        vga.println(b"Some text");
+        vga.print(b"More text");
+        vga.clear();
+        for y in 0..Vga::DEFAULT_LINES {
+            for x in 0..Vga::MAX_CHARACTERS_LINE { assert!(vga.read_at(x, y) == (b' ', 0x0F)); }
+        }
+
+        vga.print(b"A");
+        assert!(vga.read_at(0, 0) == (b'A', DEFAULT_COLOR));
+    }
+
+    #[test_case]
+    fn line_feed_on_the_last_line_scrolls_up() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        // One line more than the screen: the first one is scrolled out
+        for i in 0..Vga::DEFAULT_LINES as u8 { vga.println(&[b'a' + i]); }
+        assert!(vga.read_at(0, 0).0 == b'b');
+        assert!(vga.read_at(0, 23).0 == b'y');
+        assert!(vga.read_at(0, 24).0 == b' ');
+
+        vga.print(b"z");
+        assert!(vga.read_at(0, 24).0 == b'z');
+    }
+
+    #[test_case]
+    fn tab_moves_to_the_next_tab_stop() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        // On a tab stop (after the '7'), a whole tab is printed
+        vga.print(b"ab\tc1234567\te");
+        assert!(vga.read_at(2, 0).0 == b' ');
+        assert!(vga.read_at(8, 0).0 == b'c');
+        assert!(vga.read_at(16, 0).0 == b' ');
+        assert!(vga.read_at(24, 0).0 == b'e');
+    }
+
+    #[test_case]
+    fn backspace_clears_the_previous_cell() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        // Nothing to clear at the start of the screen
+        vga.print(b"\x08ab\x08c");
+        assert!(vga.read_at(0, 0).0 == b'a');
+        assert!(vga.read_at(1, 0).0 == b'c');
+        assert!(vga.read_at(2, 0).0 != b'b');
+
+        // At the start of a line, the last cell of the previous one is cleared
+        vga.print(b"\r\n\x08z");
+        assert!(vga.read_at(79, 0).0 == b'z');
+        assert!(vga.read_at(0, 1).0 != b'z');
+    }
+
+    #[test_case]
+    fn output_deferred_while_locked_is_printed_on_unlock() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        with_test_vga(&mut buffer, || {
+            let mut vga: VgaGuard = get_vga().lock();
+            vga.print(b"Main");
+
+            // A handler interrupting the print can't take the lock: its line
+            // is only printed once the lock is released.
+            println_irq!("IRQ");
+            assert!(vga.read_at(4, 0).0 != b'I');
+
+            // Leave a single line in the page: the deferred line feed must
+            // not stop the flush with the prompt, interrupts are disabled.
+            vga.set_paging(true);
+            vga.paged_lines = vga.scroll_bottom - vga.scroll_top - 1;
+            drop(vga);
+
+            let vga: &mut Vga = get_vga();
+            assert!(vga.read_at(4, 0).0 == b'I' && vga.read_at(6, 0).0 == b'Q');
+            assert!(vga.read_at(0, 1).0 != b'-');
+            assert!(vga.paging);
+        });
+    }
+
+    #[test_case]
+    fn print_at_clips_the_text_at_the_end_of_the_line() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        vga.print_at(75, 0, b"123456789", 0x70);
+        assert!(vga.read_at(75, 0) == (b'1', 0x70));
+        assert!(vga.read_at(79, 0) == (b'5', 0x70));
+        assert!(vga.read_at(0, 1) == (0, 0));
+    }
+
+    #[test_case]
+    fn print_at_leaves_the_buffer_position_untouched() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        vga.print(b"ab");
+        vga.print_at(10, 5, b"X", 0x70);
+        vga.print(b"c");
+        assert!(vga.read_at(10, 5) == (b'X', 0x70));
+        assert!(vga.read_at(2, 0) == (b'c', DEFAULT_COLOR));
+    }
+
+    #[test_case]
+    fn paging_prompts_after_a_full_screen() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = paged_vga(&mut buffer);
+
+        // A page is a line shorter than the screen, the last one is for the prompt
+        for _ in 0..Vga::DEFAULT_LINES - 2 { vga.println(b"line"); }
+        assert!(unsafe { KEY_WAITS } == 0);
+
+        vga.println(b"line");
+        assert!(unsafe { KEY_WAITS } == 1 && unsafe { PROMPT_SHOWN });
+    }
+
+    #[test_case]
+    fn paging_resumes_the_output_after_a_key() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = paged_vga(&mut buffer);
+
+        // The prompt is cleared and the text goes on from where it stopped
+        for _ in 0..Vga::DEFAULT_LINES - 1 { vga.println(b"line"); }
+        vga.print(b"next");
+        assert!(vga.read_at(0, 24).0 == b'n' && vga.read_at(4, 24).0 == b' ');
+
+        // The line count starts over: the next pause is a whole page later
+        for _ in 0..Vga::DEFAULT_LINES - 2 { vga.println(b""); }
+        assert!(unsafe { KEY_WAITS } == 1);
+        vga.println(b"");
+        assert!(unsafe { KEY_WAITS } == 2 && unsafe { PROMPT_SHOWN });
+    }
+
+    #[test_case]
+    fn printc_applies_the_color_then_restores_it() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        with_test_vga(&mut buffer, || {
+            get_vga().set_color(Color::Green, Color::Blue);
+
+            // Only the foreground changes, the background is kept
+            printc!(Color::Red, "Err ", 1u8);
+            let vga: &mut Vga = get_vga();
+            assert!(vga.read_at(0, 0) == (b'E', 0x14));
+            assert!(vga.read_at(4, 0) == (b'1', 0x14));
+            assert!(vga.colors() == (Color::Green, Color::Blue));
+
+            vga.print(b"x");
+            assert!(vga.read_at(5, 0) == (b'x', 0x12));
+        });
+    }
+
+    #[test_case]
+    fn print_macros_release_the_lock_when_done() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        with_test_vga(&mut buffer, || {
+            // The second print would wait forever if the first one still
+            // held the lock.
+            println!("first");
+            println!("second");
+            assert!(get_vga().try_lock().is_some());
+
+            let vga: &mut Vga = get_vga();
+            assert!(vga.read_at(0, 0).0 == b'f' && vga.read_at(0, 1).0 == b's');
+        });
+    }
+
+    #[test_case]
+    fn scroll_region_keeps_the_lines_outside_it() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+        vga.print_at(0, 0, b"HEADER", DEFAULT_COLOR);
+        vga.print_at(0, 24, b"FOOTER", DEFAULT_COLOR);
+        vga.set_scroll_region(2, 20);
+
+        // Move into the region, then print 22 lines in its 19
+        vga.ln();
+        vga.ln();
+        for i in 0..22u8 { vga.println(&[b'a' + i]); }
+
+        assert!(vga.read_at(0, 0).0 == b'H' && vga.read_at(5, 0).0 == b'R');
+        assert!(vga.read_at(0, 24).0 == b'F' && vga.read_at(5, 24).0 == b'R');
+        assert!(vga.read_at(0, 2).0 == b'e');
+        assert!(vga.read_at(0, 19).0 == b'v');
+        assert!(vga.read_at(0, 20).0 == b' ');
+    }
+
+    #[test_case]
+    fn cursor_probe_only_matches_the_written_location() {
+        assert!(Vga::cursor_probe_matches(0x0102, 0x0102));
+        assert!(!Vga::cursor_probe_matches(0x0102, 0x0000));
+        assert!(!Vga::cursor_probe_matches(0x0102, 0xFFFF));
+    }
+
+    #[test_case]
+    fn probe_cursor_stores_the_outcome_and_restores_the_location() {
+        let mut buffer: [u16; CELLS] = [0; CELLS];
+        let mut vga: Vga = Vga::new(buffer.as_mut_ptr());
+
+        let previous: u16 = vga.read_cursor_location();
+        let available: bool = vga.probe_cursor();
+        assert!(vga.cursor_available == available);
+        assert!(vga.read_cursor_location() == previous);
+    }
+}